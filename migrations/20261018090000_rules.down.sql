@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS public.transaction_tags;
+DROP TABLE IF EXISTS public.transaction_categories;
+DROP TABLE IF EXISTS public.rule_tags;
+DROP TABLE IF EXISTS public.rules;
+DROP TABLE IF EXISTS public.tags;
+DROP TABLE IF EXISTS public.categories;
@@ -0,0 +1,70 @@
+CREATE TABLE IF NOT EXISTS public.categories
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    name text NOT NULL,
+    CONSTRAINT categories_pkey PRIMARY KEY (id),
+    CONSTRAINT categories_user_id_name_key UNIQUE (user_id, name),
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES tokens (user_id)
+);
+
+CREATE TABLE IF NOT EXISTS public.tags
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    name text NOT NULL,
+    CONSTRAINT tags_pkey PRIMARY KEY (id),
+    CONSTRAINT tags_user_id_name_key UNIQUE (user_id, name),
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES tokens (user_id)
+);
+
+CREATE TABLE IF NOT EXISTS public.rules
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    name text NOT NULL,
+    priority integer NOT NULL DEFAULT 0,
+    merchant text,
+    description_pattern text,
+    min_amount bigint,
+    max_amount bigint,
+    account_id character varying,
+    category_id bigint,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT rules_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES tokens (user_id),
+    CONSTRAINT fk_account FOREIGN KEY (account_id) REFERENCES accounts (id) ON DELETE CASCADE,
+    CONSTRAINT fk_category FOREIGN KEY (category_id) REFERENCES categories (id) ON DELETE SET NULL
+);
+
+CREATE TABLE IF NOT EXISTS public.rule_tags
+(
+    rule_id bigint NOT NULL,
+    tag_id bigint NOT NULL,
+    CONSTRAINT rule_tags_pkey PRIMARY KEY (rule_id, tag_id),
+    CONSTRAINT fk_rule FOREIGN KEY (rule_id) REFERENCES rules (id) ON DELETE CASCADE,
+    CONSTRAINT fk_tag FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+);
+
+-- A NULL rule_id means the assignment was made by hand and is never touched by the rules engine.
+CREATE TABLE IF NOT EXISTS public.transaction_categories
+(
+    transaction_id character varying NOT NULL,
+    category_id bigint NOT NULL,
+    rule_id bigint,
+    CONSTRAINT transaction_categories_pkey PRIMARY KEY (transaction_id),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT fk_category FOREIGN KEY (category_id) REFERENCES categories (id) ON DELETE CASCADE,
+    CONSTRAINT fk_rule FOREIGN KEY (rule_id) REFERENCES rules (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS public.transaction_tags
+(
+    transaction_id character varying NOT NULL,
+    tag_id bigint NOT NULL,
+    rule_id bigint,
+    CONSTRAINT transaction_tags_pkey PRIMARY KEY (transaction_id, tag_id),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT fk_tag FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE,
+    CONSTRAINT fk_rule FOREIGN KEY (rule_id) REFERENCES rules (id) ON DELETE CASCADE
+);
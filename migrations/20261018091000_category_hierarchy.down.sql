@@ -0,0 +1,3 @@
+ALTER TABLE public.categories
+    DROP CONSTRAINT IF EXISTS fk_parent,
+    DROP COLUMN IF EXISTS parent_id;
@@ -0,0 +1,4 @@
+ALTER TABLE public.categories
+    ADD COLUMN IF NOT EXISTS parent_id bigint,
+    DROP CONSTRAINT IF EXISTS fk_parent,
+    ADD CONSTRAINT fk_parent FOREIGN KEY (parent_id) REFERENCES categories (id) ON DELETE SET NULL;
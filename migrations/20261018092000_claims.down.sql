@@ -0,0 +1,6 @@
+ALTER TABLE public.transactions
+    DROP CONSTRAINT IF EXISTS fk_claim,
+    DROP COLUMN IF EXISTS claim_id,
+    DROP COLUMN IF EXISTS reimbursable;
+
+DROP TABLE IF EXISTS public.claims;
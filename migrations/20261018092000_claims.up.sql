@@ -0,0 +1,20 @@
+CREATE TABLE IF NOT EXISTS public.claims
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    title text NOT NULL,
+    status text NOT NULL DEFAULT 'draft',
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    updated timestamp with time zone NOT NULL DEFAULT now(),
+    reimbursement_transaction_id character varying,
+    CONSTRAINT claims_pkey PRIMARY KEY (id),
+    CONSTRAINT claims_status_check CHECK (status IN ('draft', 'submitted', 'approved', 'paid')),
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES tokens (user_id),
+    CONSTRAINT fk_reimbursement_transaction FOREIGN KEY (reimbursement_transaction_id) REFERENCES transactions (id) ON DELETE SET NULL
+);
+
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS reimbursable boolean NOT NULL DEFAULT false,
+    ADD COLUMN IF NOT EXISTS claim_id bigint,
+    DROP CONSTRAINT IF EXISTS fk_claim,
+    ADD CONSTRAINT fk_claim FOREIGN KEY (claim_id) REFERENCES claims (id) ON DELETE SET NULL;
@@ -0,0 +1,8 @@
+DROP TABLE IF EXISTS public.claim_transitions;
+DROP TABLE IF EXISTS public.user_roles;
+
+UPDATE public.claims SET status = 'draft' WHERE status = 'rejected';
+
+ALTER TABLE public.claims
+    DROP CONSTRAINT IF EXISTS claims_status_check,
+    ADD CONSTRAINT claims_status_check CHECK (status IN ('draft', 'submitted', 'approved', 'paid'));
@@ -0,0 +1,29 @@
+ALTER TABLE public.claims
+    DROP CONSTRAINT IF EXISTS claims_status_check,
+    ADD CONSTRAINT claims_status_check CHECK (status IN ('draft', 'submitted', 'approved', 'rejected', 'paid'));
+
+-- Approvers are not necessarily Monzo users, so user_id is not tied to tokens.
+CREATE TABLE IF NOT EXISTS public.user_roles
+(
+    user_id character varying NOT NULL,
+    role text NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_roles_pkey PRIMARY KEY (user_id, role),
+    CONSTRAINT user_roles_role_check CHECK (role IN ('approver'))
+);
+
+-- A NULL actor_id means the transition was made by the application, e.g. reimbursement reconciliation.
+CREATE TABLE IF NOT EXISTS public.claim_transitions
+(
+    id bigserial NOT NULL,
+    claim_id bigint NOT NULL,
+    from_status text NOT NULL,
+    to_status text NOT NULL,
+    actor_id character varying,
+    comment text,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT claim_transitions_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_claim FOREIGN KEY (claim_id) REFERENCES claims (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS claim_transitions_claim_id_idx ON public.claim_transitions (claim_id);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS public.receipts
+(
+    id bigserial NOT NULL,
+    transaction_id character varying NOT NULL,
+    file_name text NOT NULL,
+    content_type text NOT NULL,
+    size bigint NOT NULL,
+    storage_key text NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT receipts_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS receipts_transaction_id_idx ON public.receipts (transaction_id);
@@ -0,0 +1,10 @@
+ALTER TABLE public.receipts
+    DROP CONSTRAINT IF EXISTS receipts_monzo_sync_status_check,
+    DROP COLUMN IF EXISTS monzo_synced,
+    DROP COLUMN IF EXISTS monzo_attachment_id,
+    DROP COLUMN IF EXISTS monzo_sync_status;
+
+ALTER TABLE public.transactions
+    DROP COLUMN IF EXISTS notes_conflict,
+    DROP COLUMN IF EXISTS notes_pending,
+    DROP COLUMN IF EXISTS notes_synced;
@@ -0,0 +1,15 @@
+-- notes_synced is the last notes value known to match Monzo. While notes_pending is set, the local
+-- notes have not been pushed yet and incoming updates must not overwrite them.
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS notes_synced text,
+    ADD COLUMN IF NOT EXISTS notes_pending boolean NOT NULL DEFAULT false,
+    ADD COLUMN IF NOT EXISTS notes_conflict text;
+
+UPDATE public.transactions SET notes_synced = notes;
+
+ALTER TABLE public.receipts
+    ADD COLUMN IF NOT EXISTS monzo_sync_status text NOT NULL DEFAULT 'pending',
+    ADD COLUMN IF NOT EXISTS monzo_attachment_id character varying,
+    ADD COLUMN IF NOT EXISTS monzo_synced timestamp with time zone,
+    DROP CONSTRAINT IF EXISTS receipts_monzo_sync_status_check,
+    ADD CONSTRAINT receipts_monzo_sync_status_check CHECK (monzo_sync_status IN ('pending', 'synced', 'removed'));
@@ -0,0 +1,13 @@
+DROP INDEX IF EXISTS public.receipts_unmatched_idx;
+
+DELETE FROM public.receipts WHERE transaction_id IS NULL;
+
+ALTER TABLE public.receipts
+    ALTER COLUMN transaction_id SET NOT NULL,
+    DROP COLUMN IF EXISTS merchant,
+    DROP COLUMN IF EXISTS receipt_date,
+    DROP COLUMN IF EXISTS amount,
+    DROP COLUMN IF EXISTS user_id;
+
+ALTER TABLE public.transactions
+    DROP COLUMN IF EXISTS merchant_name;
@@ -0,0 +1,21 @@
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS merchant_name text;
+
+-- Receipts can now be uploaded before it is known which transaction they belong to, with whatever
+-- the uploader knows about them to match on.
+ALTER TABLE public.receipts
+    ADD COLUMN IF NOT EXISTS user_id character varying,
+    ADD COLUMN IF NOT EXISTS amount bigint,
+    ADD COLUMN IF NOT EXISTS receipt_date date,
+    ADD COLUMN IF NOT EXISTS merchant text;
+
+UPDATE public.receipts r SET user_id = a.user_id
+    FROM public.transactions t
+    JOIN public.accounts a ON a.id = t.account_id
+    WHERE t.id = r.transaction_id;
+
+ALTER TABLE public.receipts
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN transaction_id DROP NOT NULL;
+
+CREATE INDEX IF NOT EXISTS receipts_unmatched_idx ON public.receipts (user_id) WHERE transaction_id IS NULL;
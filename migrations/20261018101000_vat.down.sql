@@ -0,0 +1,10 @@
+ALTER TABLE public.categories
+    DROP CONSTRAINT IF EXISTS categories_default_vat_rate_check,
+    DROP COLUMN IF EXISTS default_vat_rate;
+
+ALTER TABLE public.transactions
+    DROP CONSTRAINT IF EXISTS transactions_vat_amount_check,
+    DROP CONSTRAINT IF EXISTS transactions_vat_rate_check,
+    DROP COLUMN IF EXISTS supplier_vat_number,
+    DROP COLUMN IF EXISTS vat_amount,
+    DROP COLUMN IF EXISTS vat_rate;
@@ -0,0 +1,14 @@
+-- VAT rates are in basis points, so 20% is 2000.
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS vat_rate integer,
+    ADD COLUMN IF NOT EXISTS vat_amount bigint,
+    ADD COLUMN IF NOT EXISTS supplier_vat_number character varying,
+    DROP CONSTRAINT IF EXISTS transactions_vat_rate_check,
+    ADD CONSTRAINT transactions_vat_rate_check CHECK (vat_rate BETWEEN 0 AND 10000),
+    DROP CONSTRAINT IF EXISTS transactions_vat_amount_check,
+    ADD CONSTRAINT transactions_vat_amount_check CHECK (vat_amount >= 0);
+
+ALTER TABLE public.categories
+    ADD COLUMN IF NOT EXISTS default_vat_rate integer,
+    DROP CONSTRAINT IF EXISTS categories_default_vat_rate_check,
+    ADD CONSTRAINT categories_default_vat_rate_check CHECK (default_vat_rate BETWEEN 0 AND 10000);
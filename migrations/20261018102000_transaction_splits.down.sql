@@ -0,0 +1,3 @@
+DROP FUNCTION IF EXISTS public.category_vat_rate(bigint);
+DROP TABLE IF EXISTS public.split_tags;
+DROP TABLE IF EXISTS public.transaction_splits;
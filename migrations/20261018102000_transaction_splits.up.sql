@@ -0,0 +1,46 @@
+-- Splits allocate parts of a transaction to their own category, tags and claim. A transaction that
+-- has splits is never part of a claim itself; its splits are.
+CREATE TABLE IF NOT EXISTS public.transaction_splits
+(
+    id bigserial NOT NULL,
+    transaction_id character varying NOT NULL,
+    amount bigint NOT NULL,
+    note text,
+    category_id bigint,
+    reimbursable boolean NOT NULL DEFAULT false,
+    claim_id bigint,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT transaction_splits_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT fk_category FOREIGN KEY (category_id) REFERENCES categories (id) ON DELETE SET NULL,
+    CONSTRAINT fk_claim FOREIGN KEY (claim_id) REFERENCES claims (id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS transaction_splits_transaction_id_idx ON public.transaction_splits (transaction_id);
+CREATE INDEX IF NOT EXISTS transaction_splits_claim_id_idx ON public.transaction_splits (claim_id);
+
+CREATE TABLE IF NOT EXISTS public.split_tags
+(
+    split_id bigint NOT NULL,
+    tag_id bigint NOT NULL,
+    CONSTRAINT split_tags_pkey PRIMARY KEY (split_id, tag_id),
+    CONSTRAINT fk_split FOREIGN KEY (split_id) REFERENCES transaction_splits (id) ON DELETE CASCADE,
+    CONSTRAINT fk_tag FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+);
+
+-- Default VAT rate of a category, inherited from its closest ancestor with one.
+CREATE OR REPLACE FUNCTION public.category_vat_rate(category_id bigint) RETURNS integer
+LANGUAGE sql STABLE AS $$
+    WITH RECURSIVE ancestors AS (
+        SELECT id, parent_id, default_vat_rate, 0 AS depth
+        FROM categories WHERE id = category_id
+        UNION ALL
+        SELECT p.id, p.parent_id, p.default_vat_rate, a.depth + 1
+        FROM categories p
+        JOIN ancestors a ON p.id = a.parent_id
+    )
+    SELECT default_vat_rate FROM ancestors
+    WHERE default_vat_rate IS NOT NULL
+    ORDER BY depth
+    LIMIT 1
+$$;
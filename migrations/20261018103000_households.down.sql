@@ -0,0 +1,9 @@
+DROP TABLE IF EXISTS public.settlements;
+DROP TABLE IF EXISTS public.transaction_shares;
+DROP TABLE IF EXISTS public.shared_transactions;
+DROP TABLE IF EXISTS public.group_members;
+DROP TABLE IF EXISTS public.groups;
+
+ALTER TABLE public.transactions
+    DROP COLUMN IF EXISTS counterparty_name,
+    DROP COLUMN IF EXISTS counterparty_user_id;
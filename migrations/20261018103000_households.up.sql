@@ -0,0 +1,72 @@
+-- The Monzo user on the other side of a payment, for payments between Monzo users.
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS counterparty_user_id character varying,
+    ADD COLUMN IF NOT EXISTS counterparty_name text;
+
+CREATE TABLE IF NOT EXISTS public.groups
+(
+    id bigserial NOT NULL,
+    name text NOT NULL,
+    created_by character varying NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT groups_pkey PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS public.group_members
+(
+    group_id bigint NOT NULL,
+    user_id character varying NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT group_members_pkey PRIMARY KEY (group_id, user_id),
+    CONSTRAINT fk_group FOREIGN KEY (group_id) REFERENCES groups (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS group_members_user_id_idx ON public.group_members (user_id);
+
+-- A transaction shared with a group is paid for by the owner of its account and split between the
+-- members in proportion to their weights.
+CREATE TABLE IF NOT EXISTS public.shared_transactions
+(
+    transaction_id character varying NOT NULL,
+    group_id bigint NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT shared_transactions_pkey PRIMARY KEY (transaction_id),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT fk_group FOREIGN KEY (group_id) REFERENCES groups (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS shared_transactions_group_id_idx ON public.shared_transactions (group_id);
+
+CREATE TABLE IF NOT EXISTS public.transaction_shares
+(
+    transaction_id character varying NOT NULL,
+    user_id character varying NOT NULL,
+    weight integer NOT NULL,
+    CONSTRAINT transaction_shares_pkey PRIMARY KEY (transaction_id, user_id),
+    CONSTRAINT fk_shared_transaction FOREIGN KEY (transaction_id) REFERENCES shared_transactions (transaction_id) ON DELETE CASCADE,
+    CONSTRAINT transaction_shares_weight_check CHECK (weight > 0)
+);
+
+-- Money paid from one member to another. Payments between Monzo users are seen from both sides, so
+-- a settlement keeps the transaction of each side once it has arrived.
+CREATE TABLE IF NOT EXISTS public.settlements
+(
+    id bigserial NOT NULL,
+    group_id bigint NOT NULL,
+    from_user_id character varying NOT NULL,
+    to_user_id character varying NOT NULL,
+    amount bigint NOT NULL,
+    currency character varying NOT NULL,
+    payer_transaction_id character varying,
+    payee_transaction_id character varying,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT settlements_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_group FOREIGN KEY (group_id) REFERENCES groups (id) ON DELETE CASCADE,
+    CONSTRAINT fk_payer_transaction FOREIGN KEY (payer_transaction_id) REFERENCES transactions (id) ON DELETE SET NULL,
+    CONSTRAINT fk_payee_transaction FOREIGN KEY (payee_transaction_id) REFERENCES transactions (id) ON DELETE SET NULL,
+    CONSTRAINT settlements_payer_transaction_id_key UNIQUE (payer_transaction_id),
+    CONSTRAINT settlements_payee_transaction_id_key UNIQUE (payee_transaction_id),
+    CONSTRAINT settlements_amount_check CHECK (amount > 0)
+);
+
+CREATE INDEX IF NOT EXISTS settlements_group_id_idx ON public.settlements (group_id);
@@ -0,0 +1,28 @@
+-- Recurring charges found in a user's transactions. They are worked out again from scratch by
+-- subscription_detection_task, so nothing here is edited by hand.
+CREATE TABLE IF NOT EXISTS public.subscriptions
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    -- The Monzo merchant id, or the normalised description for charges without a merchant.
+    merchant_key text NOT NULL,
+    merchant character varying,
+    description text NOT NULL,
+    currency character varying NOT NULL,
+    cadence text NOT NULL,
+    -- What the latest charge was, as a positive amount.
+    amount bigint NOT NULL,
+    -- What the charge before it was, if the price changed.
+    previous_amount bigint,
+    occurrences integer NOT NULL,
+    first_date date NOT NULL,
+    last_date date NOT NULL,
+    next_date date NOT NULL,
+    last_transaction_id character varying NOT NULL,
+    missed boolean NOT NULL DEFAULT false,
+    price_changed boolean NOT NULL DEFAULT false,
+    updated timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT subscriptions_pkey PRIMARY KEY (id),
+    CONSTRAINT subscriptions_user_id_merchant_key_currency_key UNIQUE (user_id, merchant_key, currency),
+    CONSTRAINT subscriptions_cadence_check CHECK (cadence IN ('weekly', 'fortnightly', 'monthly', 'quarterly', 'yearly'))
+);
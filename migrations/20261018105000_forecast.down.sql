@@ -0,0 +1,8 @@
+DELETE FROM public.subscriptions WHERE income;
+
+ALTER TABLE public.subscriptions
+    DROP CONSTRAINT IF EXISTS subscriptions_user_id_merchant_key_currency_income_key,
+    DROP CONSTRAINT IF EXISTS subscriptions_user_id_merchant_key_currency_key,
+    ADD CONSTRAINT subscriptions_user_id_merchant_key_currency_key UNIQUE (user_id, merchant_key, currency),
+    DROP COLUMN IF EXISTS income,
+    DROP COLUMN IF EXISTS account_id;
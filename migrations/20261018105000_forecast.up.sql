@@ -0,0 +1,10 @@
+-- Subscriptions are worked out again from scratch, so they can be cleared rather than backfilled.
+DELETE FROM public.subscriptions;
+
+-- Recurring income is detected as well now, and forecasts are per account.
+ALTER TABLE public.subscriptions
+    ADD COLUMN IF NOT EXISTS account_id character varying NOT NULL,
+    ADD COLUMN IF NOT EXISTS income boolean NOT NULL DEFAULT false,
+    DROP CONSTRAINT IF EXISTS subscriptions_user_id_merchant_key_currency_key,
+    DROP CONSTRAINT IF EXISTS subscriptions_user_id_merchant_key_currency_income_key,
+    ADD CONSTRAINT subscriptions_user_id_merchant_key_currency_income_key UNIQUE (user_id, merchant_key, currency, income);
@@ -0,0 +1,16 @@
+-- Account balances as Monzo reported them each time the accounts were polled.
+CREATE TABLE IF NOT EXISTS public.balance_snapshots
+(
+    id bigserial NOT NULL,
+    account_id character varying NOT NULL,
+    balance bigint NOT NULL,
+    -- Including what is in the account's pots.
+    total_balance bigint NOT NULL,
+    spend_today bigint NOT NULL,
+    currency character varying NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT balance_snapshots_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_account FOREIGN KEY (account_id) REFERENCES accounts (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS balance_snapshots_account_id_created_idx ON public.balance_snapshots (account_id, created);
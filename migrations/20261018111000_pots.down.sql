@@ -0,0 +1,5 @@
+ALTER TABLE public.transactions
+    DROP COLUMN IF EXISTS pot_id;
+
+DROP TABLE IF EXISTS public.pot_balance_snapshots;
+DROP TABLE IF EXISTS public.pots;
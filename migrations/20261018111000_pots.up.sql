@@ -0,0 +1,35 @@
+-- Monzo pots: money set aside from an account, with its balance each time the accounts were
+-- polled.
+CREATE TABLE IF NOT EXISTS public.pots
+(
+    id character varying NOT NULL,
+    account_id character varying NOT NULL,
+    name text NOT NULL,
+    balance bigint NOT NULL,
+    currency character varying NOT NULL,
+    goal_amount bigint,
+    deleted boolean NOT NULL DEFAULT false,
+    created timestamp with time zone NOT NULL,
+    updated timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT pots_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_account FOREIGN KEY (account_id) REFERENCES accounts (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS pots_account_id_idx ON public.pots (account_id);
+
+CREATE TABLE IF NOT EXISTS public.pot_balance_snapshots
+(
+    id bigserial NOT NULL,
+    pot_id character varying NOT NULL,
+    balance bigint NOT NULL,
+    currency character varying NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT pot_balance_snapshots_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_pot FOREIGN KEY (pot_id) REFERENCES pots (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS pot_balance_snapshots_pot_id_created_idx ON public.pot_balance_snapshots (pot_id, created);
+
+-- The pot money was moved into or out of. These transfers are not spending.
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS pot_id character varying;
@@ -0,0 +1,10 @@
+ALTER TABLE public.transactions
+    DROP COLUMN IF EXISTS fx_rate_date,
+    DROP COLUMN IF EXISTS fx_rate,
+    DROP COLUMN IF EXISTS reporting_amount,
+    DROP COLUMN IF EXISTS reporting_currency,
+    DROP COLUMN IF EXISTS local_currency,
+    DROP COLUMN IF EXISTS local_amount;
+
+DROP TABLE IF EXISTS public.user_settings;
+DROP TABLE IF EXISTS public.fx_rates;
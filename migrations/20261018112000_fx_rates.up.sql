@@ -0,0 +1,26 @@
+-- Reference rates imported from the ECB, in units of the currency per euro.
+CREATE TABLE IF NOT EXISTS public.fx_rates
+(
+    date date NOT NULL,
+    currency character varying NOT NULL,
+    rate double precision NOT NULL,
+    CONSTRAINT fx_rates_pkey PRIMARY KEY (currency, date),
+    CONSTRAINT fx_rates_rate_check CHECK (rate > 0)
+);
+
+CREATE TABLE IF NOT EXISTS public.user_settings
+(
+    user_id character varying NOT NULL,
+    reporting_currency character varying NOT NULL DEFAULT 'GBP',
+    CONSTRAINT user_settings_pkey PRIMARY KEY (user_id)
+);
+
+-- What was charged in the currency of the country the card was used in, and the amount in the
+-- user's reporting currency together with the rate it was converted at.
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS local_amount bigint,
+    ADD COLUMN IF NOT EXISTS local_currency character varying,
+    ADD COLUMN IF NOT EXISTS reporting_currency character varying,
+    ADD COLUMN IF NOT EXISTS reporting_amount bigint,
+    ADD COLUMN IF NOT EXISTS fx_rate double precision,
+    ADD COLUMN IF NOT EXISTS fx_rate_date date;
@@ -0,0 +1,4 @@
+ALTER TABLE public.transactions
+    DROP COLUMN IF EXISTS duplicate_of;
+
+DROP TABLE IF EXISTS public.duplicate_transactions;
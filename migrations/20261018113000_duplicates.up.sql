@@ -0,0 +1,25 @@
+-- Pairs of transactions that look like the same spending recorded twice, e.g. once from a webhook
+-- and once from an import. Dismissed pairs are kept so they are not flagged again.
+CREATE TABLE IF NOT EXISTS public.duplicate_transactions
+(
+    id bigserial NOT NULL,
+    -- The transaction that is kept when the pair is merged.
+    transaction_id character varying NOT NULL,
+    duplicate_id character varying NOT NULL,
+    score double precision NOT NULL,
+    status text NOT NULL DEFAULT 'pending',
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    resolved timestamp with time zone,
+    CONSTRAINT duplicate_transactions_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT fk_duplicate FOREIGN KEY (duplicate_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT duplicate_transactions_status_check CHECK (status IN ('pending', 'merged', 'dismissed'))
+);
+
+-- A pair is the same pair whichever way round it was flagged.
+CREATE UNIQUE INDEX IF NOT EXISTS duplicate_transactions_pair_idx
+    ON public.duplicate_transactions (LEAST(transaction_id, duplicate_id), GREATEST(transaction_id, duplicate_id));
+
+-- Set on a transaction merged into another one, which leaves it out of summaries.
+ALTER TABLE public.transactions
+    ADD COLUMN IF NOT EXISTS duplicate_of character varying;
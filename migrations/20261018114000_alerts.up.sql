@@ -0,0 +1,17 @@
+-- Transactions that look unusual for the user, flagged when they arrive.
+CREATE TABLE IF NOT EXISTS public.alerts
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    transaction_id character varying NOT NULL,
+    kind text NOT NULL,
+    message text NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    dismissed timestamp with time zone,
+    CONSTRAINT alerts_pkey PRIMARY KEY (id),
+    CONSTRAINT alerts_transaction_id_kind_key UNIQUE (transaction_id, kind),
+    CONSTRAINT fk_transaction FOREIGN KEY (transaction_id) REFERENCES transactions (id) ON DELETE CASCADE,
+    CONSTRAINT alerts_kind_check CHECK (kind IN ('new_merchant', 'category_spike', 'foreign_odd_hours'))
+);
+
+CREATE INDEX IF NOT EXISTS alerts_user_id_created_idx ON public.alerts (user_id, created);
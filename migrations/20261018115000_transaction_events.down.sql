@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS transactions_notify_update ON public.transactions;
+DROP TRIGGER IF EXISTS transactions_notify_insert ON public.transactions;
+DROP FUNCTION IF EXISTS public.notify_transaction_event();
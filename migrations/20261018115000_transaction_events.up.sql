@@ -0,0 +1,22 @@
+-- Announces new and changed transactions on the transaction_events channel, so every app instance
+-- can push them to the event streams it is serving. Upserts that change nothing stay quiet.
+CREATE OR REPLACE FUNCTION public.notify_transaction_event() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    PERFORM pg_notify('transaction_events', json_build_object(
+        'user_id', (SELECT user_id FROM accounts WHERE id = NEW.account_id),
+        'transaction_id', NEW.id,
+        'kind', CASE TG_OP WHEN 'INSERT' THEN 'created' ELSE 'updated' END
+    )::text);
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE TRIGGER transactions_notify_insert
+    AFTER INSERT ON public.transactions
+    FOR EACH ROW EXECUTE FUNCTION public.notify_transaction_event();
+
+CREATE OR REPLACE TRIGGER transactions_notify_update
+    AFTER UPDATE ON public.transactions
+    FOR EACH ROW WHEN (OLD.* IS DISTINCT FROM NEW.*)
+    EXECUTE FUNCTION public.notify_transaction_event();
@@ -0,0 +1,6 @@
+DROP TRIGGER IF EXISTS transactions_queue_webhooks_update ON public.transactions;
+DROP TRIGGER IF EXISTS transactions_queue_webhooks_insert ON public.transactions;
+DROP FUNCTION IF EXISTS public.queue_transaction_webhooks();
+
+DROP TABLE IF EXISTS public.webhook_deliveries;
+DROP TABLE IF EXISTS public.webhook_endpoints;
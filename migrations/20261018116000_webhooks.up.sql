@@ -0,0 +1,67 @@
+-- Endpoints users register to be notified about their data, signed with the endpoint's secret.
+CREATE TABLE IF NOT EXISTS public.webhook_endpoints
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    url text NOT NULL,
+    secret text NOT NULL DEFAULT replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', ''),
+    -- The events to send, or all of them when empty.
+    events text[] NOT NULL DEFAULT '{}',
+    active boolean NOT NULL DEFAULT true,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT webhook_endpoints_pkey PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS webhook_endpoints_user_id_idx ON public.webhook_endpoints (user_id);
+
+-- Notifications waiting to be sent, and the log of those that were.
+CREATE TABLE IF NOT EXISTS public.webhook_deliveries
+(
+    id bigserial NOT NULL,
+    endpoint_id bigint NOT NULL,
+    event text NOT NULL,
+    -- Transaction events are queued by a trigger with just the transaction, and the payload is
+    -- filled in with the transaction when it is first sent.
+    transaction_id character varying,
+    payload jsonb,
+    status text NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt timestamp with time zone NOT NULL DEFAULT now(),
+    last_attempt timestamp with time zone,
+    response_status integer,
+    error text,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    delivered timestamp with time zone,
+    CONSTRAINT webhook_deliveries_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_endpoint FOREIGN KEY (endpoint_id) REFERENCES webhook_endpoints (id) ON DELETE CASCADE,
+    CONSTRAINT webhook_deliveries_status_check CHECK (status IN ('pending', 'delivered', 'failed'))
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_pending_idx ON public.webhook_deliveries (next_attempt)
+    WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS webhook_deliveries_endpoint_id_created_idx ON public.webhook_deliveries (endpoint_id, created);
+
+CREATE OR REPLACE FUNCTION public.queue_transaction_webhooks() RETURNS trigger
+LANGUAGE plpgsql AS $$
+DECLARE
+    event text := CASE TG_OP WHEN 'INSERT' THEN 'transaction.created' ELSE 'transaction.updated' END;
+BEGIN
+    INSERT INTO webhook_deliveries (endpoint_id, event, transaction_id)
+    SELECT e.id, event, NEW.id
+    FROM webhook_endpoints e
+    JOIN accounts a ON a.user_id = e.user_id
+    WHERE a.id = NEW.account_id
+        AND e.active
+        AND (cardinality(e.events) = 0 OR event = ANY(e.events));
+    RETURN NEW;
+END;
+$$;
+
+CREATE OR REPLACE TRIGGER transactions_queue_webhooks_insert
+    AFTER INSERT ON public.transactions
+    FOR EACH ROW EXECUTE FUNCTION public.queue_transaction_webhooks();
+
+CREATE OR REPLACE TRIGGER transactions_queue_webhooks_update
+    AFTER UPDATE ON public.transactions
+    FOR EACH ROW WHEN (OLD.* IS DISTINCT FROM NEW.*)
+    EXECUTE FUNCTION public.queue_transaction_webhooks();
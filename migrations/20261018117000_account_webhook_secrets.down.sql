@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS public.accounts_webhook_secret_idx;
+
+ALTER TABLE public.accounts DROP COLUMN IF EXISTS webhook_secret;
@@ -0,0 +1,8 @@
+-- Monzo does not sign webhooks, so each account's webhook URL carries a secret that only Monzo
+-- is told. Existing accounts each get their own, and their webhooks are registered again with it
+-- on the next poll.
+ALTER TABLE public.accounts
+    ADD COLUMN IF NOT EXISTS webhook_secret text NOT NULL
+        DEFAULT replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '');
+
+CREATE UNIQUE INDEX IF NOT EXISTS accounts_webhook_secret_idx ON public.accounts (webhook_secret);
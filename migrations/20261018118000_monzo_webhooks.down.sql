@@ -0,0 +1 @@
+DROP TABLE IF EXISTS public.monzo_webhooks;
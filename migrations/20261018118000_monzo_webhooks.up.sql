@@ -0,0 +1,22 @@
+-- Every webhook Monzo sends, stored as received before anything is done with it, so that none
+-- are lost when processing one fails.
+CREATE TABLE IF NOT EXISTS public.monzo_webhooks
+(
+    id bigserial NOT NULL,
+    -- The account whose webhook secret the callback came in on.
+    account_id character varying NOT NULL,
+    body text NOT NULL,
+    status text NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt timestamp with time zone NOT NULL DEFAULT now(),
+    error text,
+    received timestamp with time zone NOT NULL DEFAULT now(),
+    processed timestamp with time zone,
+    CONSTRAINT monzo_webhooks_pkey PRIMARY KEY (id),
+    CONSTRAINT fk_account FOREIGN KEY (account_id) REFERENCES accounts (id) ON DELETE CASCADE,
+    CONSTRAINT monzo_webhooks_status_check CHECK (status IN ('pending', 'processed', 'failed'))
+);
+
+CREATE INDEX IF NOT EXISTS monzo_webhooks_pending_idx ON public.monzo_webhooks (next_attempt)
+    WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS monzo_webhooks_status_received_idx ON public.monzo_webhooks (status, received);
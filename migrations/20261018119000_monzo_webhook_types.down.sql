@@ -0,0 +1,7 @@
+UPDATE public.monzo_webhooks SET status = 'processed' WHERE status = 'ignored';
+
+ALTER TABLE public.monzo_webhooks DROP CONSTRAINT IF EXISTS monzo_webhooks_status_check;
+ALTER TABLE public.monzo_webhooks ADD CONSTRAINT monzo_webhooks_status_check
+    CHECK (status IN ('pending', 'processed', 'failed'));
+
+ALTER TABLE public.monzo_webhooks DROP COLUMN IF EXISTS event_type;
@@ -0,0 +1,7 @@
+-- The type of each Monzo webhook, so that types there is nothing to do for yet can be found and
+-- replayed once they are handled.
+ALTER TABLE public.monzo_webhooks ADD COLUMN IF NOT EXISTS event_type text;
+
+ALTER TABLE public.monzo_webhooks DROP CONSTRAINT IF EXISTS monzo_webhooks_status_check;
+ALTER TABLE public.monzo_webhooks ADD CONSTRAINT monzo_webhooks_status_check
+    CHECK (status IN ('pending', 'processed', 'ignored', 'failed'));
@@ -0,0 +1,7 @@
+UPDATE public.receipts SET monzo_sync_status = 'pending' WHERE monzo_sync_status = 'syncing';
+
+ALTER TABLE public.receipts DROP CONSTRAINT IF EXISTS receipts_monzo_sync_status_check;
+ALTER TABLE public.receipts ADD CONSTRAINT receipts_monzo_sync_status_check
+    CHECK (monzo_sync_status IN ('pending', 'synced', 'removed'));
+
+ALTER TABLE public.receipts DROP COLUMN IF EXISTS monzo_sync_started;
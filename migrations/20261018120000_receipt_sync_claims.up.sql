@@ -0,0 +1,7 @@
+-- A receipt is claimed as syncing while it is being pushed to Monzo, so that two pushes of the same
+-- receipt cannot both upload it. monzo_sync_started lets claims left behind by a crash be released.
+ALTER TABLE public.receipts ADD COLUMN IF NOT EXISTS monzo_sync_started timestamp with time zone;
+
+ALTER TABLE public.receipts DROP CONSTRAINT IF EXISTS receipts_monzo_sync_status_check;
+ALTER TABLE public.receipts ADD CONSTRAINT receipts_monzo_sync_status_check
+    CHECK (monzo_sync_status IN ('pending', 'syncing', 'synced', 'removed'));
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS public.monzo_webhooks_processed_transaction_idx;
+
+ALTER TABLE public.monzo_webhooks DROP COLUMN IF EXISTS transaction_id;
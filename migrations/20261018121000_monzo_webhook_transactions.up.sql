@@ -0,0 +1,6 @@
+-- The transaction each processed Monzo webhook was about, so that a retry of an older webhook
+-- does not undo a newer one for the same transaction.
+ALTER TABLE public.monzo_webhooks ADD COLUMN IF NOT EXISTS transaction_id text;
+
+CREATE INDEX IF NOT EXISTS monzo_webhooks_processed_transaction_idx
+    ON public.monzo_webhooks (transaction_id, id) WHERE status = 'processed';
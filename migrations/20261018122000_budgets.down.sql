@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS public.budget_exceedances;
+DROP TABLE IF EXISTS public.budgets;
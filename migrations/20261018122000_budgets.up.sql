@@ -0,0 +1,26 @@
+-- How much a user means to spend in a category each month. The category is matched by name, the
+-- same way category totals are, so Monzo's own categories can have budgets too.
+CREATE TABLE IF NOT EXISTS public.budgets
+(
+    id bigserial NOT NULL,
+    user_id character varying NOT NULL,
+    category text NOT NULL,
+    amount bigint NOT NULL,
+    currency text NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT budgets_pkey PRIMARY KEY (id),
+    CONSTRAINT budgets_user_id_category_currency_key UNIQUE (user_id, category, currency),
+    CONSTRAINT fk_user FOREIGN KEY (user_id) REFERENCES tokens (user_id),
+    CONSTRAINT budgets_amount_check CHECK (amount > 0)
+);
+
+-- The months each budget was gone over in, so that it is only reported once a month.
+CREATE TABLE IF NOT EXISTS public.budget_exceedances
+(
+    budget_id bigint NOT NULL,
+    month date NOT NULL,
+    spent bigint NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT budget_exceedances_pkey PRIMARY KEY (budget_id, month),
+    CONSTRAINT fk_budget FOREIGN KEY (budget_id) REFERENCES budgets (id) ON DELETE CASCADE
+);
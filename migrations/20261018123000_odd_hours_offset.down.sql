@@ -0,0 +1,2 @@
+ALTER TABLE public.user_settings DROP CONSTRAINT IF EXISTS user_settings_odd_hours_utc_offset_check;
+ALTER TABLE public.user_settings DROP COLUMN IF EXISTS odd_hours_utc_offset;
@@ -0,0 +1,8 @@
+-- Hours ahead of UTC that the odd hours for payments abroad are counted in, since transactions are
+-- stored in UTC.
+ALTER TABLE public.user_settings
+    ADD COLUMN IF NOT EXISTS odd_hours_utc_offset integer NOT NULL DEFAULT 0;
+
+ALTER TABLE public.user_settings DROP CONSTRAINT IF EXISTS user_settings_odd_hours_utc_offset_check;
+ALTER TABLE public.user_settings ADD CONSTRAINT user_settings_odd_hours_utc_offset_check
+    CHECK (odd_hours_utc_offset BETWEEN -12 AND 14);
@@ -0,0 +1,5 @@
+ALTER TABLE public.receipts DROP CONSTRAINT IF EXISTS receipts_currency_check;
+ALTER TABLE public.receipts DROP COLUMN IF EXISTS currency;
+
+ALTER TABLE public.rules DROP CONSTRAINT IF EXISTS rules_currency_check;
+ALTER TABLE public.rules DROP COLUMN IF EXISTS currency;
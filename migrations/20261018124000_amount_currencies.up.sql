@@ -0,0 +1,16 @@
+-- Rule and receipt amounts were in minor units of no particular currency. Existing ones can only
+-- have meant pounds, which is what Monzo accounts are in.
+ALTER TABLE public.rules ADD COLUMN IF NOT EXISTS currency text;
+UPDATE public.rules SET currency = 'GBP'
+    WHERE currency IS NULL AND (min_amount IS NOT NULL OR max_amount IS NOT NULL);
+
+ALTER TABLE public.rules DROP CONSTRAINT IF EXISTS rules_currency_check;
+ALTER TABLE public.rules ADD CONSTRAINT rules_currency_check
+    CHECK ((currency IS NULL) = (min_amount IS NULL AND max_amount IS NULL));
+
+ALTER TABLE public.receipts ADD COLUMN IF NOT EXISTS currency text;
+UPDATE public.receipts SET currency = 'GBP' WHERE currency IS NULL AND amount IS NOT NULL;
+
+ALTER TABLE public.receipts DROP CONSTRAINT IF EXISTS receipts_currency_check;
+ALTER TABLE public.receipts ADD CONSTRAINT receipts_currency_check
+    CHECK ((currency IS NULL) = (amount IS NULL));
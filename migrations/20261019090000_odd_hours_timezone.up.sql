@@ -0,0 +1,15 @@
+-- The IANA timezone that the odd hours for payments abroad are counted in, which unlike a fixed
+-- offset follows daylight saving. Offsets already set become the Etc zone with that offset, whose
+-- sign is the other way round from the offset's.
+ALTER TABLE public.user_settings
+    ADD COLUMN IF NOT EXISTS odd_hours_timezone text NOT NULL DEFAULT 'UTC';
+
+UPDATE public.user_settings
+SET odd_hours_timezone = CASE
+    WHEN odd_hours_utc_offset > 0 THEN 'Etc/GMT-' || odd_hours_utc_offset
+    WHEN odd_hours_utc_offset < 0 THEN 'Etc/GMT+' || -odd_hours_utc_offset
+    ELSE 'UTC'
+END;
+
+ALTER TABLE public.user_settings DROP CONSTRAINT IF EXISTS user_settings_odd_hours_utc_offset_check;
+ALTER TABLE public.user_settings DROP COLUMN IF EXISTS odd_hours_utc_offset;
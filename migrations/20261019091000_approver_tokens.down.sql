@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS public.user_roles_token_idx;
+
+ALTER TABLE public.user_roles DROP COLUMN IF EXISTS token;
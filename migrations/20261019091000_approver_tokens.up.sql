@@ -0,0 +1,7 @@
+-- The bearer token an approver acts with, since the approver_id in the path alone proves nothing.
+-- Existing approvers get one nobody knows, so they need granting the role again to be shown it.
+ALTER TABLE public.user_roles
+    ADD COLUMN IF NOT EXISTS token text NOT NULL
+        DEFAULT replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '');
+
+CREATE UNIQUE INDEX IF NOT EXISTS user_roles_token_idx ON public.user_roles (token);
@@ -0,0 +1,197 @@
+use std::ops::Range;
+
+use chrono::{Duration, Timelike};
+use chrono_tz::Tz;
+
+use crate::{
+    domain::{AlertKind, Transaction},
+    money::{Money, minor_unit_exponent},
+};
+
+/// How far back spending is looked at to decide what is usual for the user.
+pub const HISTORY_DAYS: i64 = 365;
+/// The poll checks transactions from this far back, in case the webhook for one never came.
+pub const RECENT_DAYS: i64 = 7;
+/// Until the user has spent this many times in a currency there is no usual to compare with.
+const MIN_HISTORY: usize = 20;
+/// A first payment to a merchant is large when it is this many times the user's median payment...
+const NEW_MERCHANT_MEDIAN_MULTIPLE: i64 = 5;
+/// ...and at least this much, in major units.
+const NEW_MERCHANT_MIN_AMOUNT: i64 = 50;
+/// The rolling window a category's usual spending is worked out over.
+const CATEGORY_WINDOW_DAYS: i64 = 90;
+const MIN_CATEGORY_PAYMENTS: usize = 5;
+/// A payment stands out in its category when it is this many standard deviations above the
+/// average, and also this many times the average so that very regular categories do not flag
+/// small rises.
+const CATEGORY_DEVIATIONS: f64 = 3.0;
+const CATEGORY_AVERAGE_MULTIPLE: f64 = 2.0;
+/// Hours of the day that count as odd for a payment, in the user's odd hours timezone.
+const ODD_HOURS: Range<u32> = 0..5;
+
+pub struct Anomaly {
+    pub kind: AlertKind,
+    pub message: String,
+}
+
+/// Money the user spent themselves, rather than moved to a pot, paid to someone they share costs
+/// with or merged away as a duplicate.
+fn is_spending(transaction: &Transaction) -> bool {
+    transaction.money.amount < 0
+        && transaction.pot_id.is_none()
+        && transaction.duplicate_of.is_none()
+        && transaction.counterparty_user_id.is_none()
+}
+
+fn category_of(transaction: &Transaction) -> &String {
+    transaction
+        .custom_category
+        .as_ref()
+        .unwrap_or(&transaction.category)
+}
+
+fn merchant_key(transaction: &Transaction) -> String {
+    match &transaction.merchant {
+        Some(merchant) if !merchant.is_empty() => merchant.clone(),
+        _ => transaction.description.to_lowercase(),
+    }
+}
+
+fn median(values: &mut [i64]) -> i64 {
+    values.sort();
+    values[values.len() / 2]
+}
+
+/// The timezone odd hours are counted in, from the IANA name in the user's settings. A name that
+/// is not a timezone counts as UTC.
+pub fn odd_hours_timezone(name: &str) -> Tz {
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+fn foreign_odd_hours(transaction: &Transaction, spent: &Money, timezone: &Tz) -> Option<Anomaly> {
+    let local_currency = &transaction.local_amount.as_ref()?.currency;
+    let created = transaction.created.with_timezone(timezone);
+    if *local_currency == transaction.money.currency || !ODD_HOURS.contains(&created.hour()) {
+        return None;
+    }
+
+    Some(Anomaly {
+        kind: AlertKind::ForeignOddHours,
+        message: format!(
+            "{} spent in {} at {} UTC{}",
+            spent,
+            local_currency,
+            created.format("%H:%M"),
+            created.format("%:z")
+        ),
+    })
+}
+
+fn new_merchant(
+    transaction: &Transaction,
+    spent: &Money,
+    earlier: &[&Transaction],
+) -> Option<Anomaly> {
+    let key = merchant_key(transaction);
+    if earlier.iter().any(|other| merchant_key(other) == key) {
+        return None;
+    }
+
+    let mut amounts: Vec<i64> = earlier.iter().map(|other| -other.money.amount).collect();
+    let minimum = NEW_MERCHANT_MIN_AMOUNT * 10i64.pow(minor_unit_exponent(&spent.currency));
+    let threshold = (median(&mut amounts) * NEW_MERCHANT_MEDIAN_MULTIPLE).max(minimum);
+    if spent.amount < threshold {
+        return None;
+    }
+
+    Some(Anomaly {
+        kind: AlertKind::NewMerchant,
+        message: format!(
+            "{} spent at {}, which you have not paid before",
+            spent,
+            transaction
+                .merchant_name
+                .as_ref()
+                .unwrap_or(&transaction.description)
+        ),
+    })
+}
+
+fn category_spike(
+    transaction: &Transaction,
+    spent: &Money,
+    earlier: &[&Transaction],
+) -> Option<Anomaly> {
+    let category = category_of(transaction);
+    let since = transaction.created - Duration::days(CATEGORY_WINDOW_DAYS);
+    let amounts: Vec<f64> = earlier
+        .iter()
+        .filter(|other| other.created >= since && category_of(other) == category)
+        .map(|other| -other.money.amount as f64)
+        .collect();
+    if amounts.len() < MIN_CATEGORY_PAYMENTS {
+        return None;
+    }
+
+    let mean = amounts.iter().sum::<f64>() / amounts.len() as f64;
+    let variance = amounts
+        .iter()
+        .map(|amount| (amount - mean).powi(2))
+        .sum::<f64>()
+        / amounts.len() as f64;
+    let amount = spent.amount as f64;
+    if amount <= mean + CATEGORY_DEVIATIONS * variance.sqrt()
+        || amount <= mean * CATEGORY_AVERAGE_MULTIPLE
+    {
+        return None;
+    }
+
+    Some(Anomaly {
+        kind: AlertKind::CategorySpike,
+        message: format!(
+            "{} spent on {}, {:.1} times the usual {}",
+            spent,
+            category,
+            amount / mean,
+            Money::new(mean.round() as i64, &spent.currency)
+        ),
+    })
+}
+
+/// Checks whether a transaction is unusual compared with the user's spending before it in
+/// `history`: a large first payment to a merchant, far more than usual for its category, or a
+/// payment abroad in the middle of the night. The night is the one in `timezone`, since Monzo does
+/// not say which timezone a payment was made in.
+pub fn detect_anomalies(
+    transaction: &Transaction,
+    history: &[Transaction],
+    timezone: &Tz,
+) -> Vec<Anomaly> {
+    if !is_spending(transaction) {
+        return vec![];
+    }
+    let Ok(spent) = transaction.money.checked_neg() else {
+        return vec![];
+    };
+
+    let since = transaction.created - Duration::days(HISTORY_DAYS);
+    let earlier: Vec<&Transaction> = history
+        .iter()
+        .filter(|other| {
+            other.id != transaction.id
+                && other.created < transaction.created
+                && other.created >= since
+                && other.money.currency == transaction.money.currency
+                && is_spending(other)
+        })
+        .collect();
+
+    let mut anomalies: Vec<Anomaly> = foreign_odd_hours(transaction, &spent, timezone)
+        .into_iter()
+        .collect();
+    if earlier.len() >= MIN_HISTORY {
+        anomalies.extend(new_merchant(transaction, &spent, &earlier));
+        anomalies.extend(category_spike(transaction, &spent, &earlier));
+    }
+    anomalies
+}
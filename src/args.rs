@@ -1,22 +1,35 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::domain::MonzoWebhookStatus;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about = "Expenses web application", long_about = None)]
+// The server's own arguments are only required when no subcommand is given. They are options so
+// that a subcommand can be run without them.
+#[command(subcommand_negates_reqs = true)]
 pub struct Args {
     #[arg(long, default_value_t = String::from(""), help = "The log directory e.g. '/var/logs'. If this is not provided, only logs out to stdout.")]
     pub base_log_dir: String,
 
     #[arg(
         long,
+        required = true,
         help = "Base URL of the application e.g. \"https://example.com\""
     )]
-    pub base_url: String,
+    pub base_url: Option<String>,
 
-    #[arg(long, env = "CLIENT_ID", help = "Monzo Client ID")]
-    pub client_id: String,
+    #[arg(long, env = "CLIENT_ID", required = true, help = "Monzo Client ID")]
+    pub client_id: Option<String>,
 
-    #[arg(long, env = "CLIENT_SECRET", help = "Monzo Client Secret")]
-    pub client_secret: String,
+    #[arg(
+        long,
+        env = "CLIENT_SECRET",
+        required = true,
+        help = "Monzo Client Secret"
+    )]
+    pub client_secret: Option<String>,
 
     #[arg(
         long,
@@ -25,8 +38,8 @@ pub struct Args {
     )]
     pub database_url: String,
 
-    #[arg(long)]
-    pub port: u32,
+    #[arg(long, required = true)]
+    pub port: Option<u32>,
 
     #[arg(
         long,
@@ -48,8 +61,95 @@ pub struct Args {
         help = "Interval in seconds for polling accounts"
     )]
     pub account_poll_interval: u64,
+
+    #[arg(
+        long,
+        default_value_t = 300u64,
+        help = "Interval in seconds for retrying notes and receipts that have not been pushed to Monzo yet"
+    )]
+    pub monzo_sync_interval: u64,
+
+    #[arg(
+        long,
+        default_value_t = 3600u64,
+        help = "Interval in seconds for detecting recurring payments and subscriptions"
+    )]
+    pub subscription_detection_interval: u64,
+
+    #[arg(
+        long,
+        default_value_t = 30u64,
+        help = "Interval in seconds for sending webhooks to the endpoints users have registered"
+    )]
+    pub webhook_delivery_interval: u64,
+
+    #[arg(
+        long,
+        default_value_t = 60u64,
+        help = "Interval in seconds for retrying Monzo webhooks that could not be processed yet"
+    )]
+    pub monzo_webhook_interval: u64,
+
+    #[arg(
+        long,
+        env = "REFETCH_MONZO_CALLBACKS",
+        help = "Get each transaction Monzo's webhooks send from Monzo before storing it, rather than trusting the payload"
+    )]
+    pub refetch_monzo_callbacks: bool,
+
+    #[arg(
+        long,
+        env = "ADMIN_TOKEN",
+        help = "Bearer token required by the /api/admin endpoints. If this is not provided, the admin endpoints are disabled."
+    )]
+    pub admin_token: Option<String>,
+
+    #[arg(
+        long,
+        env = "RECEIPT_STORE",
+        default_value_t = String::from("receipts"),
+        help = "Where receipts are stored: a local directory, or an 's3://bucket/prefix' URL when built with the s3 feature"
+    )]
+    pub receipt_store: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Replays Monzo webhooks through the same processing as the callback instead of starting the
+    /// server, and prints what each changed about its transaction
+    Replay(ReplayArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    #[arg(
+        long = "webhook-id",
+        help = "The id of a webhook in the inbox to replay. Can be given more than once"
+    )]
+    pub webhook_ids: Vec<i64>,
+
+    #[arg(
+        long,
+        help = "Replay the most recent webhooks in the inbox with this status"
+    )]
+    pub status: Option<MonzoWebhookStatus>,
+
+    #[arg(
+        long,
+        help = "A JSON file with a webhook body, or an array of them, e.g. taken from the logs"
+    )]
+    pub file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Only print what would change in transactions, without changing anything"
+    )]
+    pub dry_run: bool,
 }
 
 pub fn parse_args() -> Args {
-    return Args::parse();
+    Args::parse()
 }
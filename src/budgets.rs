@@ -0,0 +1,106 @@
+use crate::{
+    domain::{Budget, Transaction},
+    model::category_totals,
+    money::{Money, MoneyError},
+};
+
+/// The budgets that the spending in `lines` has gone over, with how much was spent on each.
+/// Split transactions should be expanded into their lines first, so that each part counts towards
+/// the category it was split into.
+pub fn exceeded_budgets<'a>(
+    budgets: &'a [Budget],
+    lines: &[Transaction],
+) -> Result<Vec<(&'a Budget, Money)>, MoneyError> {
+    let totals = category_totals(lines)?;
+
+    let mut exceeded = vec![];
+    for budget in budgets.iter() {
+        let Some(total) = totals.iter().find(|total| {
+            total.category == budget.category && total.total.currency == budget.amount.currency
+        }) else {
+            continue;
+        };
+        // Spending is negative, so what was spent is the total the other way round.
+        let spent = total.total.checked_neg()?;
+        if spent.amount > budget.amount.amount {
+            exceeded.push((budget, spent));
+        }
+    }
+    Ok(exceeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{domain::Split, splits::split_lines};
+
+    fn budget(category: &str, amount: Money) -> Budget {
+        Budget {
+            id: 1,
+            user_id: String::from("user"),
+            category: String::from(category),
+            amount,
+            created: Utc::now(),
+        }
+    }
+
+    fn split(id: i64, money: Money, category: &str) -> Split {
+        Split {
+            id,
+            transaction_id: String::from("tx_1"),
+            money,
+            note: None,
+            category_id: Some(id),
+            category: Some(String::from(category)),
+            tags: vec![],
+            category_vat_rate: None,
+            reimbursable: false,
+            claim_id: None,
+            created: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn split_transactions_count_towards_the_categories_they_were_split_into() {
+        let budgets = vec![
+            budget("groceries", Money::new(5000, "GBP")),
+            budget("shopping", Money::new(5000, "GBP")),
+        ];
+        let transaction = Transaction {
+            id: String::from("tx_1"),
+            money: Money::new(-8000, "GBP"),
+            category: String::from("shopping"),
+            is_split: true,
+            ..Default::default()
+        };
+        let splits = vec![
+            split(1, Money::new(-6000, "GBP"), "groceries"),
+            split(2, Money::new(-2000, "GBP"), "shopping"),
+        ];
+
+        let lines = split_lines(vec![transaction], &splits).unwrap();
+        let exceeded = exceeded_budgets(&budgets, &lines).unwrap();
+
+        assert_eq!(exceeded.len(), 1);
+        assert_eq!(exceeded[0].0.category, "groceries");
+        assert_eq!(exceeded[0].1, Money::new(6000, "GBP"));
+    }
+
+    #[test]
+    fn budgets_in_another_currency_are_not_exceeded() {
+        let budgets = vec![budget("groceries", Money::new(5000, "EUR"))];
+        let transaction = Transaction {
+            money: Money::new(-8000, "GBP"),
+            category: String::from("groceries"),
+            ..Default::default()
+        };
+
+        assert!(
+            exceeded_budgets(&budgets, &[transaction])
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
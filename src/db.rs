@@ -1,7 +1,25 @@
-use chrono::{DateTime, Utc};
-use sqlx::{PgPool, Row, postgres::PgQueryResult};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{
+    PgConnection, PgExecutor, PgPool, Row,
+    postgres::{PgListener, PgQueryResult},
+};
 
-use crate::domain::{Account, Token, Transaction};
+use crate::{
+    anomalies::Anomaly,
+    domain::{
+        Account, Alert, BalanceSnapshot, Budget, Category, Claim, ClaimStatus, ClaimTransition,
+        DueWebhookDelivery, Duplicate, DuplicateStatus, FxRate, Group, MonzoWebhook,
+        MonzoWebhookStatus, NewReceipt, NewRule, NewSplit, Pot, PotBalanceSnapshot, Receipt,
+        ReceiptSyncStatus, Rule, Settlement, Share, SharedExpense, Split, Subscription, Tag, Token,
+        Transaction, UserSettings, WebhookDelivery, WebhookDeliveryStatus, WebhookEndpoint,
+    },
+    duplicates::DuplicateCandidate,
+    fx::Conversion,
+    money::Money,
+    rules::RuleOutcome,
+    webhooks::{BUDGET_EXCEEDED, CLAIM_STATUS_CHANGED},
+};
+use serde_json::{Value, json};
 
 pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
     PgPool::connect(database_url).await
@@ -26,7 +44,7 @@ pub async fn upsert_token(pool: &PgPool, token: &Token) -> Result<PgQueryResult,
         ",
     )
     .bind(&token.user_id)
-    .bind(&token.expiry_time)
+    .bind(token.expiry_time)
     .bind(&token.token_type)
     .bind(&token.access_token)
     .bind(&token.refresh_token)
@@ -56,17 +74,27 @@ pub async fn upsert_account(
     .bind(&account.id)
     .bind(&account.user_id)
     .bind(&account.description)
-    .bind(&account.created)
+    .bind(account.created)
     .execute(pool)
     .await
 }
 
 pub async fn upsert_transaction(
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
     transaction: &Transaction,
 ) -> Result<PgQueryResult, sqlx::Error> {
     sqlx::query(
         "
+            -- Splits no longer add up once Monzo changes the amount, for example when a pre-auth is
+            -- reversed, so they are dropped. Splits on a claim are kept for the claim's sake.
+            WITH dropped_splits AS (
+                DELETE FROM transaction_splits s
+                USING transactions t
+                WHERE s.transaction_id = t.id
+                    AND t.id = $1
+                    AND t.amount <> $3
+                    AND s.claim_id IS NULL
+            )
             INSERT INTO transactions (
                 id,
                 account_id,
@@ -77,16 +105,58 @@ pub async fn upsert_transaction(
                 merchant,
                 category,
                 created,
-                settled
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                settled,
+                notes_synced,
+                merchant_name,
+                counterparty_user_id,
+                counterparty_name,
+                pot_id,
+                local_amount,
+                local_currency
+            ) VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $6, $11, $12, $13, $14, $15, $16
+            )
             ON CONFLICT (id)
             DO UPDATE SET
                 account_id = EXCLUDED.account_id,
                 amount = EXCLUDED.amount,
                 currency = EXCLUDED.currency,
                 description = EXCLUDED.description,
-                notes = EXCLUDED.notes,
+                -- Local notes that have not been pushed yet win over what Monzo has. If Monzo's notes
+                -- changed in the meantime as well, they are kept aside in notes_conflict.
+                notes = CASE
+                    WHEN transactions.notes_pending THEN transactions.notes
+                    ELSE EXCLUDED.notes
+                END,
+                notes_pending = transactions.notes_pending
+                    AND EXCLUDED.notes <> transactions.notes,
+                notes_conflict = CASE
+                    WHEN transactions.notes_pending
+                        AND EXCLUDED.notes <> transactions.notes
+                        AND EXCLUDED.notes IS DISTINCT FROM transactions.notes_synced
+                        THEN EXCLUDED.notes
+                    WHEN NOT transactions.notes_pending
+                        AND EXCLUDED.notes <> transactions.notes
+                        THEN NULL
+                    ELSE transactions.notes_conflict
+                END,
+                notes_synced = EXCLUDED.notes,
                 merchant = EXCLUDED.merchant,
+                -- Only webhooks carry the merchant name, so polling must not clear it.
+                merchant_name = COALESCE(EXCLUDED.merchant_name, transactions.merchant_name),
+                counterparty_user_id = COALESCE(
+                    EXCLUDED.counterparty_user_id,
+                    transactions.counterparty_user_id
+                ),
+                counterparty_name = COALESCE(EXCLUDED.counterparty_name, transactions.counterparty_name),
+                pot_id = COALESCE(EXCLUDED.pot_id, transactions.pot_id),
+                local_amount = COALESCE(EXCLUDED.local_amount, transactions.local_amount),
+                local_currency = COALESCE(EXCLUDED.local_currency, transactions.local_currency),
+                -- A changed amount has to be converted again.
+                reporting_amount = CASE
+                    WHEN EXCLUDED.amount <> transactions.amount THEN NULL
+                    ELSE transactions.reporting_amount
+                END,
                 category = EXCLUDED.category,
                 created = EXCLUDED.created,
                 settled = EXCLUDED.settled
@@ -94,15 +164,21 @@ pub async fn upsert_transaction(
     )
     .bind(&transaction.id)
     .bind(&transaction.account_id)
-    .bind(&transaction.amount)
-    .bind(&transaction.currency)
+    .bind(transaction.money.amount)
+    .bind(&transaction.money.currency)
     .bind(&transaction.description)
     .bind(&transaction.notes)
     .bind(&transaction.merchant)
     .bind(&transaction.category)
-    .bind(&transaction.created)
-    .bind(&transaction.settled)
-    .execute(pool)
+    .bind(transaction.created)
+    .bind(transaction.settled)
+    .bind(&transaction.merchant_name)
+    .bind(&transaction.counterparty_user_id)
+    .bind(&transaction.counterparty_name)
+    .bind(&transaction.pot_id)
+    .bind(transaction.local_amount.as_ref().map(|money| money.amount))
+    .bind(transaction.local_amount.as_ref().map(|money| &money.currency))
+    .execute(executor)
     .await
     .inspect_err(|err| {
         tracing::error!(
@@ -151,23 +227,2973 @@ pub async fn query_tokens_expiring_before(
             WHERE expiry_time < $1
         ",
     )
-    .bind(&expiry_time)
+    .bind(expiry_time)
     .fetch_all(pool)
     .await
 }
 
+const SELECT_TRANSACTIONS: &str = "
+    SELECT
+        t.*,
+        c.name AS custom_category,
+        category_vat_rate(c.id) AS category_vat_rate,
+        EXISTS (
+            SELECT 1 FROM transaction_splits s WHERE s.transaction_id = t.id
+        ) AS is_split,
+        ARRAY(
+            SELECT tg.name FROM transaction_tags tt
+            JOIN tags tg ON tg.id = tt.tag_id
+            WHERE tt.transaction_id = t.id
+            ORDER BY tg.name
+        ) AS tags
+    FROM transactions t
+    LEFT JOIN transaction_categories tc ON tc.transaction_id = t.id
+    LEFT JOIN categories c ON c.id = tc.category_id
+";
+
 pub async fn query_transactions(
     pool: &PgPool,
     account_ids: &Vec<String>,
 ) -> Result<Vec<Transaction>, sqlx::Error> {
-    sqlx::query_as::<_, Transaction>(
+    sqlx::query_as::<_, Transaction>(&format!(
         "
-            SELECT * FROM transactions 
-            WHERE account_id = ANY($1)
-            ORDER BY created DESC
+            {SELECT_TRANSACTIONS}
+            WHERE t.account_id = ANY($1)
+            ORDER BY t.created DESC
+        "
+    ))
+    .bind(account_ids)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_account(
+    pool: &PgPool,
+    account_id: &str,
+) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query_as::<_, Account>(
+        "
+            SELECT * FROM accounts
+            WHERE id = $1
+        ",
+    )
+    .bind(account_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The account whose Monzo webhook URL carries `webhook_secret`.
+pub async fn query_account_by_webhook_secret(
+    pool: &PgPool,
+    webhook_secret: &str,
+) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query_as::<_, Account>(
+        "
+            SELECT * FROM accounts
+            WHERE webhook_secret = $1
+        ",
+    )
+    .bind(webhook_secret)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The user's account ids along with the secret for each one's Monzo webhook URL.
+pub async fn query_account_webhook_secrets(
+    pool: &PgPool,
+    user_id: &str,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, String)>(
+        "
+            SELECT id, webhook_secret FROM accounts
+            WHERE user_id = $1
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn upsert_category_by_name(
+    pool: &PgPool,
+    user_id: &str,
+    name: &str,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO categories (user_id, name) VALUES ($1, $2)
+            ON CONFLICT (user_id, name)
+            DO UPDATE SET name = EXCLUDED.name
+            RETURNING id
+        ",
+    )
+    .bind(user_id)
+    .bind(name)
+    .fetch_one(pool)
+    .await
+    .map(|row| row.get::<i64, &str>("id"))
+}
+
+pub async fn upsert_tag_by_name(
+    pool: &PgPool,
+    user_id: &str,
+    name: &str,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO tags (user_id, name) VALUES ($1, $2)
+            ON CONFLICT (user_id, name)
+            DO UPDATE SET name = EXCLUDED.name
+            RETURNING id
+        ",
+    )
+    .bind(user_id)
+    .bind(name)
+    .fetch_one(pool)
+    .await
+    .map(|row| row.get::<i64, &str>("id"))
+}
+
+pub async fn query_user_transaction(
+    pool: &PgPool,
+    user_id: &str,
+    transaction_id: &str,
+) -> Result<Option<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            JOIN accounts a ON a.id = t.account_id
+            WHERE t.id = $1 AND a.user_id = $2
+        "
+    ))
+    .bind(transaction_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn query_categories(pool: &PgPool, user_id: &str) -> Result<Vec<Category>, sqlx::Error> {
+    sqlx::query_as::<_, Category>(
+        "
+            SELECT * FROM categories
+            WHERE user_id = $1
+            ORDER BY name
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_category(
+    pool: &PgPool,
+    user_id: &str,
+    category_id: i64,
+) -> Result<Option<Category>, sqlx::Error> {
+    sqlx::query_as::<_, Category>(
+        "
+            SELECT * FROM categories
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(category_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns the ids of `category_id` and all of its ancestors.
+pub async fn query_category_ancestor_ids(
+    pool: &PgPool,
+    category_id: i64,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let ids: Vec<_> = sqlx::query(
+        "
+            WITH RECURSIVE ancestors AS (
+                SELECT id, parent_id FROM categories WHERE id = $1
+                UNION
+                SELECT c.id, c.parent_id FROM categories c
+                JOIN ancestors a ON c.id = a.parent_id
+            )
+            SELECT id FROM ancestors
+        ",
+    )
+    .bind(category_id)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.get::<i64, &str>("id"))
+    .collect();
+
+    Ok(ids)
+}
+
+pub async fn insert_category(
+    pool: &PgPool,
+    user_id: &str,
+    name: &str,
+    parent_id: Option<i64>,
+    default_vat_rate: Option<i32>,
+) -> Result<Option<Category>, sqlx::Error> {
+    sqlx::query_as::<_, Category>(
+        "
+            INSERT INTO categories (user_id, name, parent_id, default_vat_rate) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, name) DO NOTHING
+            RETURNING *
+        ",
+    )
+    .bind(user_id)
+    .bind(name)
+    .bind(parent_id)
+    .bind(default_vat_rate)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_category(
+    pool: &PgPool,
+    user_id: &str,
+    category_id: i64,
+    name: &str,
+    parent_id: Option<i64>,
+    default_vat_rate: Option<i32>,
+) -> Result<Option<Category>, sqlx::Error> {
+    sqlx::query_as::<_, Category>(
+        "
+            UPDATE categories
+            SET name = $3, parent_id = $4, default_vat_rate = $5
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+        ",
+    )
+    .bind(category_id)
+    .bind(user_id)
+    .bind(name)
+    .bind(parent_id)
+    .bind(default_vat_rate)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_category(
+    pool: &PgPool,
+    user_id: &str,
+    category_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM categories
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(category_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_tags(pool: &PgPool, user_id: &str) -> Result<Vec<Tag>, sqlx::Error> {
+    sqlx::query_as::<_, Tag>(
+        "
+            SELECT * FROM tags
+            WHERE user_id = $1
+            ORDER BY name
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn insert_tag(
+    pool: &PgPool,
+    user_id: &str,
+    name: &str,
+) -> Result<Option<Tag>, sqlx::Error> {
+    sqlx::query_as::<_, Tag>(
+        "
+            INSERT INTO tags (user_id, name) VALUES ($1, $2)
+            ON CONFLICT (user_id, name) DO NOTHING
+            RETURNING *
+        ",
+    )
+    .bind(user_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_tag(
+    pool: &PgPool,
+    user_id: &str,
+    tag_id: i64,
+    name: &str,
+) -> Result<Option<Tag>, sqlx::Error> {
+    sqlx::query_as::<_, Tag>(
+        "
+            UPDATE tags
+            SET name = $3
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+        ",
+    )
+    .bind(tag_id)
+    .bind(user_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_tag(
+    pool: &PgPool,
+    user_id: &str,
+    tag_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM tags
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(tag_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+/// Sets a manual category on a transaction, replacing any category assigned by a rule.
+pub async fn set_transaction_category(
+    pool: &PgPool,
+    transaction_id: &str,
+    category_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO transaction_categories (transaction_id, category_id, rule_id)
+            VALUES ($1, $2, NULL)
+            ON CONFLICT (transaction_id)
+            DO UPDATE SET
+                category_id = EXCLUDED.category_id,
+                rule_id = NULL
+        ",
+    )
+    .bind(transaction_id)
+    .bind(category_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn clear_transaction_category(
+    pool: &PgPool,
+    transaction_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM transaction_categories
+            WHERE transaction_id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .execute(pool)
+    .await
+}
+
+/// Tags a transaction by hand. A tag previously assigned by a rule becomes a manual one.
+pub async fn tag_transaction(
+    pool: &PgPool,
+    transaction_id: &str,
+    tag_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO transaction_tags (transaction_id, tag_id, rule_id)
+            VALUES ($1, $2, NULL)
+            ON CONFLICT (transaction_id, tag_id)
+            DO UPDATE SET rule_id = NULL
+        ",
+    )
+    .bind(transaction_id)
+    .bind(tag_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn untag_transaction(
+    pool: &PgPool,
+    transaction_id: &str,
+    tag_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM transaction_tags
+            WHERE transaction_id = $1 AND tag_id = $2
+        ",
+    )
+    .bind(transaction_id)
+    .bind(tag_id)
+    .execute(pool)
+    .await
+}
+
+const SELECT_RULES: &str = "
+    SELECT
+        r.*,
+        c.name AS category,
+        COALESCE(array_agg(tg.id ORDER BY tg.name) FILTER (WHERE tg.id IS NOT NULL), '{}') AS tag_ids,
+        COALESCE(array_agg(tg.name ORDER BY tg.name) FILTER (WHERE tg.id IS NOT NULL), '{}') AS tags
+    FROM rules r
+    LEFT JOIN categories c ON c.id = r.category_id
+    LEFT JOIN rule_tags rt ON rt.rule_id = r.id
+    LEFT JOIN tags tg ON tg.id = rt.tag_id
+";
+
+pub async fn query_rules(pool: &PgPool, user_id: &str) -> Result<Vec<Rule>, sqlx::Error> {
+    sqlx::query_as::<_, Rule>(&format!(
+        "
+            {SELECT_RULES}
+            WHERE r.user_id = $1
+            GROUP BY r.id, c.name
+            ORDER BY r.priority DESC, r.id
+        "
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_rule(pool: &PgPool, rule_id: i64) -> Result<Option<Rule>, sqlx::Error> {
+    sqlx::query_as::<_, Rule>(&format!(
+        "
+            {SELECT_RULES}
+            WHERE r.id = $1
+            GROUP BY r.id, c.name
+        "
+    ))
+    .bind(rule_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn insert_rule(pool: &PgPool, rule: &NewRule) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let rule_id = sqlx::query(
+        "
+            INSERT INTO rules (
+                user_id,
+                name,
+                priority,
+                merchant,
+                description_pattern,
+                min_amount,
+                max_amount,
+                currency,
+                account_id,
+                category_id
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id
+        ",
+    )
+    .bind(&rule.user_id)
+    .bind(&rule.name)
+    .bind(rule.priority)
+    .bind(&rule.merchant)
+    .bind(&rule.description_pattern)
+    .bind(rule.min_amount.as_ref().map(|money| money.amount))
+    .bind(rule.max_amount.as_ref().map(|money| money.amount))
+    .bind(
+        rule.min_amount
+            .as_ref()
+            .or(rule.max_amount.as_ref())
+            .map(|money| &money.currency),
+    )
+    .bind(&rule.account_id)
+    .bind(rule.category_id)
+    .fetch_one(&mut *tx)
+    .await?
+    .get::<i64, &str>("id");
+
+    sqlx::query(
+        "
+            INSERT INTO rule_tags (rule_id, tag_id)
+            SELECT $1, UNNEST($2::bigint[])
+            ON CONFLICT DO NOTHING
+        ",
+    )
+    .bind(rule_id)
+    .bind(&rule.tag_ids)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(rule_id)
+}
+
+pub async fn delete_rule(
+    pool: &PgPool,
+    user_id: &str,
+    rule_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM rules
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(rule_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+/// Replaces the rule-made category and tag assignments of a transaction with `outcome`. Manual
+/// assignments (those without a rule_id) are left untouched.
+pub async fn replace_rule_assignments(
+    pool: &PgPool,
+    transaction_id: &str,
+    outcome: &RuleOutcome,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "
+            DELETE FROM transaction_categories
+            WHERE transaction_id = $1 AND rule_id IS NOT NULL
+        ",
+    )
+    .bind(transaction_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if let Some((rule_id, category_id)) = outcome.category {
+        sqlx::query(
+            "
+                INSERT INTO transaction_categories (transaction_id, category_id, rule_id)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (transaction_id) DO NOTHING
+            ",
+        )
+        .bind(transaction_id)
+        .bind(category_id)
+        .bind(rule_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        "
+            DELETE FROM transaction_tags
+            WHERE transaction_id = $1 AND rule_id IS NOT NULL
+        ",
+    )
+    .bind(transaction_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let (rule_ids, tag_ids): (Vec<i64>, Vec<i64>) = outcome.tags.iter().copied().unzip();
+    sqlx::query(
+        "
+            INSERT INTO transaction_tags (transaction_id, rule_id, tag_id)
+            SELECT $1, UNNEST($2::bigint[]), UNNEST($3::bigint[])
+            ON CONFLICT (transaction_id, tag_id) DO NOTHING
+        ",
+    )
+    .bind(transaction_id)
+    .bind(&rule_ids)
+    .bind(&tag_ids)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+/// Lists transactions created in [from, to), oldest first.
+pub async fn query_transactions_between(
+    pool: &PgPool,
+    account_ids: &Vec<String>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    reimbursable_only: bool,
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            WHERE t.account_id = ANY($1)
+                AND t.created >= $2
+                AND t.created < $3
+                -- Moving money into or out of a pot is not spending.
+                AND t.pot_id IS NULL
+                AND t.duplicate_of IS NULL
+                AND (
+                    t.reimbursable
+                    OR NOT $4
+                    OR EXISTS (
+                        SELECT 1 FROM transaction_splits s
+                        WHERE s.transaction_id = t.id AND s.reimbursable
+                    )
+                )
+            ORDER BY t.created
+        "
+    ))
+    .bind(account_ids)
+    .bind(from)
+    .bind(to)
+    .bind(reimbursable_only)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn set_transaction_reimbursable(
+    pool: &PgPool,
+    transaction_id: &str,
+    reimbursable: bool,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions
+            SET
+                reimbursable = $2,
+                claim_id = CASE WHEN $2 THEN claim_id ELSE NULL END
+            WHERE id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .bind(reimbursable)
+    .execute(pool)
+    .await
+}
+
+/// Claims are made up of whole transactions and splits of transactions, totalled per currency.
+const SELECT_CLAIMS: &str = "
+    SELECT
+        c.*,
+        COALESCE(SUM(l.count), 0)::bigint AS transaction_count,
+        COALESCE(
+            jsonb_agg(jsonb_build_object('amount', l.total, 'currency', l.currency) ORDER BY l.currency)
+                FILTER (WHERE l.currency IS NOT NULL),
+            '[]'
+        ) AS totals
+    FROM claims c
+    LEFT JOIN (
+        SELECT claim_id, currency, COUNT(*) AS count, SUM(amount)::bigint AS total
+        FROM (
+            SELECT claim_id, amount, currency FROM transactions
+            WHERE claim_id IS NOT NULL
+            UNION ALL
+            SELECT s.claim_id, s.amount, t.currency FROM transaction_splits s
+            JOIN transactions t ON t.id = s.transaction_id
+            WHERE s.claim_id IS NOT NULL
+        ) lines
+        GROUP BY claim_id, currency
+    ) l ON l.claim_id = c.id
+";
+
+pub async fn query_claims(pool: &PgPool, user_id: &str) -> Result<Vec<Claim>, sqlx::Error> {
+    sqlx::query_as::<_, Claim>(&format!(
+        "
+            {SELECT_CLAIMS}
+            WHERE c.user_id = $1
+            GROUP BY c.id
+            ORDER BY c.created DESC
+        "
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_claim(
+    pool: &PgPool,
+    user_id: &str,
+    claim_id: i64,
+) -> Result<Option<Claim>, sqlx::Error> {
+    sqlx::query_as::<_, Claim>(&format!(
+        "
+            {SELECT_CLAIMS}
+            WHERE c.id = $1 AND c.user_id = $2
+            GROUP BY c.id
+        "
+    ))
+    .bind(claim_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn query_claim_transactions(
+    pool: &PgPool,
+    claim_id: i64,
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            WHERE t.claim_id = $1
+                OR EXISTS (
+                    SELECT 1 FROM transaction_splits s
+                    WHERE s.transaction_id = t.id AND s.claim_id = $1
+                )
+            ORDER BY t.created
+        "
+    ))
+    .bind(claim_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn insert_claim(pool: &PgPool, user_id: &str, title: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO claims (user_id, title) VALUES ($1, $2)
+            RETURNING id
+        ",
+    )
+    .bind(user_id)
+    .bind(title)
+    .fetch_one(pool)
+    .await
+    .map(|row| row.get::<i64, &str>("id"))
+}
+
+pub async fn delete_claim(
+    pool: &PgPool,
+    user_id: &str,
+    claim_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM claims
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(claim_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+/// Adds transactions to a claim, marking them as reimbursable.
+pub async fn add_claim_transactions(
+    pool: &PgPool,
+    claim_id: i64,
+    transaction_ids: &[String],
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions
+            SET reimbursable = true, claim_id = $1
+            WHERE id = ANY($2)
+        ",
+    )
+    .bind(claim_id)
+    .bind(transaction_ids)
+    .execute(pool)
+    .await
+}
+
+pub async fn remove_claim_transaction(
+    pool: &PgPool,
+    claim_id: i64,
+    transaction_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions
+            SET claim_id = NULL
+            WHERE id = $1 AND claim_id = $2
+        ",
+    )
+    .bind(transaction_id)
+    .bind(claim_id)
+    .execute(pool)
+    .await
+}
+
+/// Moves a claim from `from` to `to` and records the transition in the audit log. Returns false
+/// without changing anything when the claim is no longer in `from`.
+pub async fn transition_claim_status(
+    pool: &PgPool,
+    claim_id: i64,
+    from: ClaimStatus,
+    to: ClaimStatus,
+    actor_id: Option<&str>,
+    comment: Option<&str>,
+    reimbursement_transaction_id: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let claim = sqlx::query_as::<_, Claim>(
+        "
+            UPDATE claims
+            SET
+                status = $3,
+                reimbursement_transaction_id = COALESCE($4, reimbursement_transaction_id),
+                updated = now()
+            WHERE id = $1 AND status = $2
+            RETURNING *
+        ",
+    )
+    .bind(claim_id)
+    .bind(from)
+    .bind(to)
+    .bind(reimbursement_transaction_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(claim) = claim else {
+        return Ok(false);
+    };
+
+    sqlx::query(
+        "
+            INSERT INTO claim_transitions (
+                claim_id,
+                from_status,
+                to_status,
+                actor_id,
+                comment
+            ) VALUES ($1, $2, $3, $4, $5)
+        ",
+    )
+    .bind(claim_id)
+    .bind(from)
+    .bind(to)
+    .bind(actor_id)
+    .bind(comment)
+    .execute(&mut *tx)
+    .await?;
+
+    queue_webhook_event(
+        &mut tx,
+        &claim.user_id,
+        CLAIM_STATUS_CHANGED,
+        &json!({
+            "claim_id": claim.id,
+            "title": claim.title,
+            "from_status": from,
+            "to_status": to,
+            "actor_id": actor_id,
+            "comment": comment,
+            "reimbursement_transaction_id": claim.reimbursement_transaction_id,
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn query_claim_transitions(
+    pool: &PgPool,
+    claim_id: i64,
+) -> Result<Vec<ClaimTransition>, sqlx::Error> {
+    sqlx::query_as::<_, ClaimTransition>(
+        "
+            SELECT * FROM claim_transitions
+            WHERE claim_id = $1
+            ORDER BY created, id
+        ",
+    )
+    .bind(claim_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_claim_by_id(pool: &PgPool, claim_id: i64) -> Result<Option<Claim>, sqlx::Error> {
+    sqlx::query_as::<_, Claim>(&format!(
+        "
+            {SELECT_CLAIMS}
+            WHERE c.id = $1
+            GROUP BY c.id
+        "
+    ))
+    .bind(claim_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Lists claims in `status` across all users, oldest first.
+pub async fn query_claims_with_status(
+    pool: &PgPool,
+    status: ClaimStatus,
+) -> Result<Vec<Claim>, sqlx::Error> {
+    sqlx::query_as::<_, Claim>(&format!(
+        "
+            {SELECT_CLAIMS}
+            WHERE c.status = $1
+            GROUP BY c.id
+            ORDER BY c.updated
+        "
+    ))
+    .bind(status)
+    .fetch_all(pool)
+    .await
+}
+
+/// Whether the user has the role and `token` is the one it was granted with.
+pub async fn query_has_role(
+    pool: &PgPool,
+    user_id: &str,
+    role: &str,
+    token: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query(
+        "
+            SELECT EXISTS (
+                SELECT 1 FROM user_roles
+                WHERE user_id = $1 AND role = $2 AND token = $3
+            ) AS has_role
+        ",
+    )
+    .bind(user_id)
+    .bind(role)
+    .bind(token)
+    .fetch_one(pool)
+    .await
+    .map(|row| row.get::<bool, &str>("has_role"))
+}
+
+pub async fn query_users_with_role(pool: &PgPool, role: &str) -> Result<Vec<String>, sqlx::Error> {
+    let user_ids: Vec<_> = sqlx::query(
+        "
+            SELECT user_id FROM user_roles
+            WHERE role = $1
+            ORDER BY user_id
+        ",
+    )
+    .bind(role)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.get::<String, &str>("user_id"))
+    .collect();
+
+    Ok(user_ids)
+}
+
+/// Grants the role with a new token, which replaces any the user had for it, and returns the
+/// token.
+pub async fn insert_role(pool: &PgPool, user_id: &str, role: &str) -> Result<String, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO user_roles (user_id, role) VALUES ($1, $2)
+            ON CONFLICT (user_id, role) DO UPDATE SET token = EXCLUDED.token
+            RETURNING token
+        ",
+    )
+    .bind(user_id)
+    .bind(role)
+    .fetch_one(pool)
+    .await
+    .map(|row| row.get::<String, &str>("token"))
+}
+
+pub async fn delete_role(
+    pool: &PgPool,
+    user_id: &str,
+    role: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM user_roles
+            WHERE user_id = $1 AND role = $2
+        ",
+    )
+    .bind(user_id)
+    .bind(role)
+    .execute(pool)
+    .await
+}
+
+/// Finds the oldest outstanding claim whose transactions are exactly offset by a credit of
+/// `amount`, all in its currency, preferring approved claims over submitted ones.
+pub async fn query_claim_matching_reimbursement(
+    pool: &PgPool,
+    user_id: &str,
+    amount: &Money,
+) -> Result<Option<Claim>, sqlx::Error> {
+    sqlx::query_as::<_, Claim>(&format!(
+        "
+            {SELECT_CLAIMS}
+            WHERE c.user_id = $1
+                AND c.status IN ('submitted', 'approved')
+                AND c.reimbursement_transaction_id IS NULL
+            GROUP BY c.id
+            HAVING SUM(l.total) = -$2::bigint AND bool_and(l.currency = $3)
+            ORDER BY c.status = 'approved' DESC, c.created
+            LIMIT 1
+        "
+    ))
+    .bind(user_id)
+    .bind(amount.amount)
+    .bind(&amount.currency)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_transaction(
+    pool: &PgPool,
+    account_id: &str,
+    transaction_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM transactions
+            WHERE id = $1 AND account_id = $2
+        ",
+    )
+    .bind(transaction_id)
+    .bind(account_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_transaction(
+    executor: impl PgExecutor<'_>,
+    transaction_id: &str,
+) -> Result<Option<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            WHERE t.id = $1
+        "
+    ))
+    .bind(transaction_id)
+    .fetch_optional(executor)
+    .await
+}
+
+/// The transaction as it would be after upserting it, without keeping the change.
+pub async fn preview_upsert_transaction(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<Option<Transaction>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    upsert_transaction(&mut *tx, transaction).await?;
+    let preview = query_transaction(&mut *tx, &transaction.id).await?;
+
+    tx.rollback().await?;
+
+    Ok(preview)
+}
+
+pub async fn insert_receipt(pool: &PgPool, receipt: &NewReceipt) -> Result<Receipt, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            INSERT INTO receipts (
+                user_id,
+                transaction_id,
+                file_name,
+                content_type,
+                size,
+                storage_key,
+                amount,
+                currency,
+                receipt_date,
+                merchant
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING *
+        ",
+    )
+    .bind(&receipt.user_id)
+    .bind(&receipt.transaction_id)
+    .bind(&receipt.file_name)
+    .bind(&receipt.content_type)
+    .bind(receipt.size)
+    .bind(&receipt.storage_key)
+    .bind(receipt.amount.as_ref().map(|money| money.amount))
+    .bind(receipt.amount.as_ref().map(|money| &money.currency))
+    .bind(receipt.receipt_date)
+    .bind(&receipt.merchant)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn query_receipts(
+    pool: &PgPool,
+    transaction_ids: &[String],
+) -> Result<Vec<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            SELECT * FROM receipts
+            WHERE transaction_id = ANY($1)
+            ORDER BY created, id
+        ",
+    )
+    .bind(transaction_ids)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_receipt(
+    pool: &PgPool,
+    transaction_id: &str,
+    receipt_id: i64,
+) -> Result<Option<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            SELECT * FROM receipts
+            WHERE id = $1 AND transaction_id = $2
+        ",
+    )
+    .bind(receipt_id)
+    .bind(transaction_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_receipt(pool: &PgPool, receipt_id: i64) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM receipts
+            WHERE id = $1
+        ",
+    )
+    .bind(receipt_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_token_for_account(
+    pool: &PgPool,
+    account_id: &str,
+) -> Result<Option<Token>, sqlx::Error> {
+    sqlx::query_as::<_, Token>(
+        "
+            SELECT tokens.* FROM tokens
+            JOIN accounts a ON a.user_id = tokens.user_id
+            WHERE a.id = $1
+        ",
+    )
+    .bind(account_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Replaces the notes on a transaction with a local edit, which is pending until pushed to Monzo.
+pub async fn update_transaction_notes(
+    pool: &PgPool,
+    transaction_id: &str,
+    notes: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions SET
+                notes = $2,
+                notes_pending = notes_synced IS DISTINCT FROM $2,
+                notes_conflict = NULL
+            WHERE id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .bind(notes)
+    .execute(pool)
+    .await
+}
+
+/// Records that `notes` were pushed to Monzo, unless they were edited again in the meantime.
+pub async fn mark_transaction_notes_synced(
+    pool: &PgPool,
+    transaction_id: &str,
+    notes: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions SET
+                notes_synced = $2,
+                notes_pending = false
+            WHERE id = $1 AND notes = $2
+        ",
+    )
+    .bind(transaction_id)
+    .bind(notes)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_transactions_with_pending_notes(
+    pool: &PgPool,
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            WHERE t.notes_pending
+            ORDER BY t.created
+        "
+    ))
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_receipts_with_sync_status(
+    pool: &PgPool,
+    status: ReceiptSyncStatus,
+) -> Result<Vec<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            SELECT * FROM receipts
+            WHERE monzo_sync_status = $1 AND transaction_id IS NOT NULL
+            ORDER BY created, id
+        ",
+    )
+    .bind(status)
+    .fetch_all(pool)
+    .await
+}
+
+/// Claims a pending receipt for pushing to Monzo. Returns None if the receipt is not pending, for
+/// example because another push has already claimed it.
+pub async fn claim_receipt_for_sync(
+    pool: &PgPool,
+    receipt_id: i64,
+) -> Result<Option<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            UPDATE receipts SET
+                monzo_sync_status = 'syncing',
+                monzo_sync_started = now()
+            WHERE id = $1 AND monzo_sync_status = 'pending' AND transaction_id IS NOT NULL
+            RETURNING *
+        ",
+    )
+    .bind(receipt_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Puts a claimed receipt back to pending after a failed push so that it is retried.
+pub async fn release_receipt_sync(
+    pool: &PgPool,
+    receipt_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE receipts SET
+                monzo_sync_status = 'pending',
+                monzo_sync_started = NULL
+            WHERE id = $1 AND monzo_sync_status = 'syncing'
+        ",
+    )
+    .bind(receipt_id)
+    .execute(pool)
+    .await
+}
+
+/// Releases receipts claimed before `started_before` whose push never finished, for example
+/// because the process stopped halfway through.
+pub async fn release_stale_receipt_syncs(
+    pool: &PgPool,
+    started_before: DateTime<Utc>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE receipts SET
+                monzo_sync_status = 'pending',
+                monzo_sync_started = NULL
+            WHERE monzo_sync_status = 'syncing' AND monzo_sync_started < $1
+        ",
+    )
+    .bind(started_before)
+    .execute(pool)
+    .await
+}
+
+pub async fn mark_receipt_synced(
+    pool: &PgPool,
+    receipt_id: i64,
+    attachment_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE receipts SET
+                monzo_sync_status = 'synced',
+                monzo_attachment_id = $2,
+                monzo_synced = now(),
+                monzo_sync_started = NULL
+            WHERE id = $1
+        ",
+    )
+    .bind(receipt_id)
+    .bind(attachment_id)
+    .execute(pool)
+    .await
+}
+
+/// Marks the synced receipts of a transaction whose attachment is no longer in Monzo as removed.
+pub async fn mark_receipts_removed(
+    pool: &PgPool,
+    transaction_id: &str,
+    attachment_ids: &[String],
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE receipts SET
+                monzo_sync_status = 'removed',
+                monzo_attachment_id = NULL
+            WHERE transaction_id = $1
+                AND monzo_sync_status = 'synced'
+                AND monzo_attachment_id <> ALL($2)
+        ",
+    )
+    .bind(transaction_id)
+    .bind(attachment_ids)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_unmatched_receipts(
+    pool: &PgPool,
+    user_id: &str,
+) -> Result<Vec<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            SELECT * FROM receipts
+            WHERE user_id = $1 AND transaction_id IS NULL
+            ORDER BY created, id
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_user_receipt(
+    pool: &PgPool,
+    user_id: &str,
+    receipt_id: i64,
+) -> Result<Option<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            SELECT * FROM receipts
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(receipt_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Matches an unmatched receipt to a transaction. Returns None if it was matched already.
+pub async fn match_receipt(
+    pool: &PgPool,
+    receipt_id: i64,
+    transaction_id: &str,
+) -> Result<Option<Receipt>, sqlx::Error> {
+    sqlx::query_as::<_, Receipt>(
+        "
+            UPDATE receipts SET transaction_id = $2
+            WHERE id = $1 AND transaction_id IS NULL
+            RETURNING *
+        ",
+    )
+    .bind(receipt_id)
+    .bind(transaction_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn set_transaction_vat(
+    pool: &PgPool,
+    transaction_id: &str,
+    vat_rate: Option<i32>,
+    vat_amount: Option<i64>,
+    supplier_vat_number: Option<&str>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions
+            SET vat_rate = $2, vat_amount = $3, supplier_vat_number = $4
+            WHERE id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .bind(vat_rate)
+    .bind(vat_amount)
+    .bind(supplier_vat_number)
+    .execute(pool)
+    .await
+}
+
+const SELECT_SPLITS: &str = "
+    SELECT
+        s.*,
+        t.currency,
+        c.name AS category,
+        category_vat_rate(c.id) AS category_vat_rate,
+        ARRAY(
+            SELECT tg.name FROM split_tags st
+            JOIN tags tg ON tg.id = st.tag_id
+            WHERE st.split_id = s.id
+            ORDER BY tg.name
+        ) AS tags
+    FROM transaction_splits s
+    JOIN transactions t ON t.id = s.transaction_id
+    LEFT JOIN categories c ON c.id = s.category_id
+";
+
+pub async fn query_splits(
+    pool: &PgPool,
+    transaction_ids: &[String],
+) -> Result<Vec<Split>, sqlx::Error> {
+    sqlx::query_as::<_, Split>(&format!(
+        "
+            {SELECT_SPLITS}
+            WHERE s.transaction_id = ANY($1)
+            ORDER BY s.id
+        "
+    ))
+    .bind(transaction_ids)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_user_split(
+    pool: &PgPool,
+    user_id: &str,
+    split_id: i64,
+) -> Result<Option<Split>, sqlx::Error> {
+    sqlx::query_as::<_, Split>(&format!(
+        "
+            {SELECT_SPLITS}
+            JOIN accounts a ON a.id = t.account_id
+            WHERE s.id = $1 AND a.user_id = $2
+        "
+    ))
+    .bind(split_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Replaces the splits of a transaction. An empty `splits` removes them.
+pub async fn replace_transaction_splits(
+    pool: &PgPool,
+    transaction_id: &str,
+    splits: &[NewSplit],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "
+            DELETE FROM transaction_splits
+            WHERE transaction_id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .execute(&mut *tx)
+    .await?;
+
+    for split in splits.iter() {
+        let split_id = sqlx::query(
+            "
+                INSERT INTO transaction_splits (
+                    transaction_id,
+                    amount,
+                    note,
+                    category_id,
+                    reimbursable
+                ) VALUES ($1, $2, $3, $4, $5)
+                RETURNING id
+            ",
+        )
+        .bind(transaction_id)
+        .bind(split.money.amount)
+        .bind(&split.note)
+        .bind(split.category_id)
+        .bind(split.reimbursable)
+        .fetch_one(&mut *tx)
+        .await?
+        .get::<i64, &str>("id");
+
+        sqlx::query(
+            "
+                INSERT INTO split_tags (split_id, tag_id)
+                SELECT $1, UNNEST($2::bigint[])
+                ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(split_id)
+        .bind(&split.tag_ids)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Adds splits to a claim, marking them as reimbursable.
+pub async fn add_claim_splits(
+    pool: &PgPool,
+    claim_id: i64,
+    split_ids: &[i64],
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transaction_splits
+            SET reimbursable = true, claim_id = $1
+            WHERE id = ANY($2)
+        ",
+    )
+    .bind(claim_id)
+    .bind(split_ids)
+    .execute(pool)
+    .await
+}
+
+pub async fn remove_claim_split(
+    pool: &PgPool,
+    claim_id: i64,
+    split_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transaction_splits
+            SET claim_id = NULL
+            WHERE id = $1 AND claim_id = $2
+        ",
+    )
+    .bind(split_id)
+    .bind(claim_id)
+    .execute(pool)
+    .await
+}
+
+const SELECT_GROUPS: &str = "
+    SELECT
+        g.*,
+        ARRAY(
+            SELECT gm.user_id FROM group_members gm
+            WHERE gm.group_id = g.id
+            ORDER BY gm.user_id
+        ) AS members
+    FROM groups g
+";
+
+/// Creates a group with its creator as the first member.
+pub async fn insert_group(pool: &PgPool, user_id: &str, name: &str) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let group_id = sqlx::query(
+        "
+            INSERT INTO groups (name, created_by)
+            VALUES ($1, $2)
+            RETURNING id
+        ",
+    )
+    .bind(name)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?
+    .get::<i64, &str>("id");
+
+    sqlx::query(
+        "
+            INSERT INTO group_members (group_id, user_id)
+            VALUES ($1, $2)
+        ",
+    )
+    .bind(group_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(group_id)
+}
+
+pub async fn query_user_groups(pool: &PgPool, user_id: &str) -> Result<Vec<Group>, sqlx::Error> {
+    sqlx::query_as::<_, Group>(&format!(
+        "
+            {SELECT_GROUPS}
+            WHERE g.id IN (SELECT group_id FROM group_members WHERE user_id = $1)
+            ORDER BY g.id
+        "
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Looks up a group, but only if `user_id` is one of its members.
+pub async fn query_user_group(
+    pool: &PgPool,
+    user_id: &str,
+    group_id: i64,
+) -> Result<Option<Group>, sqlx::Error> {
+    sqlx::query_as::<_, Group>(&format!(
+        "
+            {SELECT_GROUPS}
+            WHERE g.id = $1
+                AND g.id IN (SELECT group_id FROM group_members WHERE user_id = $2)
+        "
+    ))
+    .bind(group_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Ids of the groups that both users are members of.
+pub async fn query_shared_group_ids(
+    pool: &PgPool,
+    user_id: &str,
+    other_user_id: &str,
+) -> Result<Vec<i64>, sqlx::Error> {
+    sqlx::query(
+        "
+            SELECT a.group_id FROM group_members a
+            JOIN group_members b ON b.group_id = a.group_id
+            WHERE a.user_id = $1 AND b.user_id = $2
+            ORDER BY a.group_id
+        ",
+    )
+    .bind(user_id)
+    .bind(other_user_id)
+    .fetch_all(pool)
+    .await
+    .map(|rows| rows.iter().map(|row| row.get("group_id")).collect())
+}
+
+pub async fn insert_group_member(
+    pool: &PgPool,
+    group_id: i64,
+    user_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO group_members (group_id, user_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+        ",
+    )
+    .bind(group_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn delete_group_member(
+    pool: &PgPool,
+    group_id: i64,
+    user_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM group_members
+            WHERE group_id = $1 AND user_id = $2
+        ",
+    )
+    .bind(group_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+/// Shares a transaction with a group, replacing any shares it had before.
+pub async fn share_transaction(
+    pool: &PgPool,
+    transaction_id: &str,
+    group_id: i64,
+    shares: &[Share],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "
+            DELETE FROM shared_transactions
+            WHERE transaction_id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "
+            INSERT INTO shared_transactions (transaction_id, group_id)
+            VALUES ($1, $2)
+        ",
+    )
+    .bind(transaction_id)
+    .bind(group_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let user_ids: Vec<&str> = shares.iter().map(|share| share.user_id.as_str()).collect();
+    let weights: Vec<i32> = shares.iter().map(|share| share.weight).collect();
+    sqlx::query(
+        "
+            INSERT INTO transaction_shares (transaction_id, user_id, weight)
+            SELECT $1, UNNEST($2::varchar[]), UNNEST($3::integer[])
+        ",
+    )
+    .bind(transaction_id)
+    .bind(&user_ids)
+    .bind(&weights)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+pub async fn unshare_transaction(
+    pool: &PgPool,
+    transaction_id: &str,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM shared_transactions
+            WHERE transaction_id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_transaction_shares(
+    pool: &PgPool,
+    transaction_id: &str,
+) -> Result<Vec<Share>, sqlx::Error> {
+    sqlx::query_as::<_, Share>(
+        "
+            SELECT user_id, weight FROM transaction_shares
+            WHERE transaction_id = $1
+            ORDER BY user_id
+        ",
+    )
+    .bind(transaction_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Every transaction shared with the group, paid for by the owner of its account.
+pub async fn query_shared_expenses(
+    pool: &PgPool,
+    group_id: i64,
+) -> Result<Vec<SharedExpense>, sqlx::Error> {
+    sqlx::query_as::<_, SharedExpense>(
+        "
+            SELECT
+                a.user_id AS paid_by,
+                t.amount,
+                t.currency,
+                ARRAY_AGG(ts.user_id ORDER BY ts.user_id) AS user_ids,
+                ARRAY_AGG(ts.weight ORDER BY ts.user_id) AS weights
+            FROM shared_transactions st
+            JOIN transactions t ON t.id = st.transaction_id
+            JOIN accounts a ON a.id = t.account_id
+            JOIN transaction_shares ts ON ts.transaction_id = st.transaction_id
+            WHERE st.group_id = $1 AND t.duplicate_of IS NULL
+            GROUP BY t.id, a.user_id
+            ORDER BY t.created
+        ",
+    )
+    .bind(group_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_settlements(
+    pool: &PgPool,
+    group_id: i64,
+) -> Result<Vec<Settlement>, sqlx::Error> {
+    sqlx::query_as::<_, Settlement>(
+        "
+            SELECT * FROM settlements
+            WHERE group_id = $1
+            ORDER BY created
+        ",
+    )
+    .bind(group_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_settlement_by_transaction(
+    pool: &PgPool,
+    transaction_id: &str,
+) -> Result<Option<Settlement>, sqlx::Error> {
+    sqlx::query_as::<_, Settlement>(
+        "
+            SELECT * FROM settlements
+            WHERE payer_transaction_id = $1 OR payee_transaction_id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn insert_settlement(
+    pool: &PgPool,
+    settlement: &Settlement,
+) -> Result<Settlement, sqlx::Error> {
+    sqlx::query_as::<_, Settlement>(
+        "
+            INSERT INTO settlements (
+                group_id,
+                from_user_id,
+                to_user_id,
+                amount,
+                currency,
+                payer_transaction_id,
+                payee_transaction_id,
+                created
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+        ",
+    )
+    .bind(settlement.group_id)
+    .bind(&settlement.from_user_id)
+    .bind(&settlement.to_user_id)
+    .bind(settlement.money.amount)
+    .bind(&settlement.money.currency)
+    .bind(&settlement.payer_transaction_id)
+    .bind(&settlement.payee_transaction_id)
+    .bind(settlement.created)
+    .fetch_one(pool)
+    .await
+}
+
+/// Attaches the other side of a payment between two members to the settlement already recorded
+/// for it, i.e. one between the same users for the same amount, a few days either side, that is
+/// still missing that side's transaction. Returns the settlement if there was one.
+pub async fn pair_settlement(
+    pool: &PgPool,
+    settlement: &Settlement,
+    window: Duration,
+) -> Result<Option<Settlement>, sqlx::Error> {
+    sqlx::query_as::<_, Settlement>(
+        "
+            UPDATE settlements
+            SET
+                payer_transaction_id = COALESCE(payer_transaction_id, $5),
+                payee_transaction_id = COALESCE(payee_transaction_id, $6)
+            WHERE id = (
+                SELECT id FROM settlements
+                WHERE from_user_id = $1
+                    AND to_user_id = $2
+                    AND amount = $3
+                    AND currency = $4
+                    AND ($5::varchar IS NULL OR payer_transaction_id IS NULL)
+                    AND ($6::varchar IS NULL OR payee_transaction_id IS NULL)
+                    AND created BETWEEN $7 - $8 AND $7 + $8
+                ORDER BY ABS(EXTRACT(EPOCH FROM created - $7))
+                LIMIT 1
+            )
+            RETURNING *
+        ",
+    )
+    .bind(&settlement.from_user_id)
+    .bind(&settlement.to_user_id)
+    .bind(settlement.money.amount)
+    .bind(&settlement.money.currency)
+    .bind(&settlement.payer_transaction_id)
+    .bind(&settlement.payee_transaction_id)
+    .bind(settlement.created)
+    .bind(window)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn query_subscriptions(
+    pool: &PgPool,
+    user_id: &str,
+) -> Result<Vec<Subscription>, sqlx::Error> {
+    sqlx::query_as::<_, Subscription>(
+        "
+            SELECT * FROM subscriptions
+            WHERE user_id = $1
+            ORDER BY next_date, id
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Replaces the user's subscriptions with the ones just detected. Subscriptions that are still
+/// detected keep their id.
+pub async fn replace_subscriptions(
+    pool: &PgPool,
+    user_id: &str,
+    subscriptions: &[Subscription],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let mut ids = vec![];
+    for subscription in subscriptions.iter() {
+        let id = sqlx::query(
+            "
+                INSERT INTO subscriptions (
+                    user_id,
+                    account_id,
+                    merchant_key,
+                    merchant,
+                    description,
+                    currency,
+                    cadence,
+                    income,
+                    amount,
+                    previous_amount,
+                    occurrences,
+                    first_date,
+                    last_date,
+                    next_date,
+                    last_transaction_id,
+                    missed,
+                    price_changed
+                ) VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17
+                )
+                ON CONFLICT (user_id, merchant_key, currency, income)
+                DO UPDATE SET
+                    account_id = EXCLUDED.account_id,
+                    merchant = EXCLUDED.merchant,
+                    description = EXCLUDED.description,
+                    cadence = EXCLUDED.cadence,
+                    amount = EXCLUDED.amount,
+                    previous_amount = EXCLUDED.previous_amount,
+                    occurrences = EXCLUDED.occurrences,
+                    first_date = EXCLUDED.first_date,
+                    last_date = EXCLUDED.last_date,
+                    next_date = EXCLUDED.next_date,
+                    last_transaction_id = EXCLUDED.last_transaction_id,
+                    missed = EXCLUDED.missed,
+                    price_changed = EXCLUDED.price_changed,
+                    updated = now()
+                RETURNING id
+            ",
+        )
+        .bind(user_id)
+        .bind(&subscription.account_id)
+        .bind(&subscription.merchant_key)
+        .bind(&subscription.merchant)
+        .bind(&subscription.description)
+        .bind(&subscription.money.currency)
+        .bind(subscription.cadence)
+        .bind(subscription.income)
+        .bind(subscription.money.amount)
+        .bind(
+            subscription
+                .previous_amount
+                .as_ref()
+                .map(|money| money.amount),
+        )
+        .bind(subscription.occurrences)
+        .bind(subscription.first_date)
+        .bind(subscription.last_date)
+        .bind(subscription.next_date)
+        .bind(&subscription.last_transaction_id)
+        .bind(subscription.missed)
+        .bind(subscription.price_changed)
+        .fetch_one(&mut *tx)
+        .await?
+        .get::<i64, &str>("id");
+        ids.push(id);
+    }
+
+    sqlx::query(
+        "
+            DELETE FROM subscriptions
+            WHERE user_id = $1 AND NOT (id = ANY($2))
+        ",
+    )
+    .bind(user_id)
+    .bind(&ids)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+pub async fn insert_balance_snapshot(
+    pool: &PgPool,
+    snapshot: &BalanceSnapshot,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO balance_snapshots (
+                account_id,
+                balance,
+                total_balance,
+                spend_today,
+                currency
+            ) VALUES ($1, $2, $3, $4, $5)
+        ",
+    )
+    .bind(&snapshot.account_id)
+    .bind(snapshot.balance.amount)
+    .bind(snapshot.total_balance.amount)
+    .bind(snapshot.spend_today.amount)
+    .bind(&snapshot.balance.currency)
+    .execute(pool)
+    .await
+}
+
+pub async fn query_balance_snapshots(
+    pool: &PgPool,
+    account_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<BalanceSnapshot>, sqlx::Error> {
+    sqlx::query_as::<_, BalanceSnapshot>(
+        "
+            SELECT * FROM balance_snapshots
+            WHERE account_id = $1 AND created >= $2 AND created < $3
+            ORDER BY created
+        ",
+    )
+    .bind(account_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn upsert_pot(pool: &PgPool, pot: &Pot) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO pots (
+                id,
+                account_id,
+                name,
+                balance,
+                currency,
+                goal_amount,
+                deleted,
+                created
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (id)
+            DO UPDATE SET
+                name = EXCLUDED.name,
+                balance = EXCLUDED.balance,
+                currency = EXCLUDED.currency,
+                goal_amount = EXCLUDED.goal_amount,
+                deleted = EXCLUDED.deleted,
+                updated = now()
+        ",
+    )
+    .bind(&pot.id)
+    .bind(&pot.account_id)
+    .bind(&pot.name)
+    .bind(pot.balance.amount)
+    .bind(&pot.balance.currency)
+    .bind(pot.goal_amount.as_ref().map(|money| money.amount))
+    .bind(pot.deleted)
+    .bind(pot.created)
+    .execute(pool)
+    .await
+}
+
+pub async fn insert_pot_balance_snapshot(
+    pool: &PgPool,
+    pot: &Pot,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO pot_balance_snapshots (pot_id, balance, currency)
+            VALUES ($1, $2, $3)
+        ",
+    )
+    .bind(&pot.id)
+    .bind(pot.balance.amount)
+    .bind(&pot.balance.currency)
+    .execute(pool)
+    .await
+}
+
+/// The pots of the given accounts, leaving out the ones that have been deleted.
+pub async fn query_pots(pool: &PgPool, account_ids: &[String]) -> Result<Vec<Pot>, sqlx::Error> {
+    sqlx::query_as::<_, Pot>(
+        "
+            SELECT * FROM pots
+            WHERE account_id = ANY($1) AND NOT deleted
+            ORDER BY created
+        ",
+    )
+    .bind(account_ids)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_user_pot(
+    pool: &PgPool,
+    user_id: &str,
+    pot_id: &str,
+) -> Result<Option<Pot>, sqlx::Error> {
+    sqlx::query_as::<_, Pot>(
+        "
+            SELECT p.* FROM pots p
+            JOIN accounts a ON a.id = p.account_id
+            WHERE p.id = $1 AND a.user_id = $2
+        ",
+    )
+    .bind(pot_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn query_pot_balance_snapshots(
+    pool: &PgPool,
+    pot_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<PotBalanceSnapshot>, sqlx::Error> {
+    sqlx::query_as::<_, PotBalanceSnapshot>(
+        "
+            SELECT * FROM pot_balance_snapshots
+            WHERE pot_id = $1 AND created >= $2 AND created < $3
+            ORDER BY created
+        ",
+    )
+    .bind(pot_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+}
+
+/// The most recent balance recorded for each of the given accounts.
+pub async fn query_latest_balance_snapshots(
+    pool: &PgPool,
+    account_ids: &[String],
+) -> Result<Vec<BalanceSnapshot>, sqlx::Error> {
+    sqlx::query_as::<_, BalanceSnapshot>(
+        "
+            SELECT DISTINCT ON (account_id) * FROM balance_snapshots
+            WHERE account_id = ANY($1)
+            ORDER BY account_id, created DESC
+        ",
+    )
+    .bind(account_ids)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_accounts(pool: &PgPool, user_id: &str) -> Result<Vec<Account>, sqlx::Error> {
+    sqlx::query_as::<_, Account>(
+        "
+            SELECT * FROM accounts
+            WHERE user_id = $1
+            ORDER BY created
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Stores imported rates, replacing any already stored for the same day and currency.
+pub async fn upsert_fx_rates(pool: &PgPool, rates: &[FxRate]) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let mut rows = 0;
+    // Postgres limits the size of a single statement, and a full history has well over 100,000
+    // rates.
+    for chunk in rates.chunks(10000) {
+        let dates: Vec<NaiveDate> = chunk.iter().map(|rate| rate.date).collect();
+        let currencies: Vec<&str> = chunk.iter().map(|rate| rate.currency.as_str()).collect();
+        let values: Vec<f64> = chunk.iter().map(|rate| rate.rate).collect();
+        rows += sqlx::query(
+            "
+                INSERT INTO fx_rates (date, currency, rate)
+                SELECT * FROM UNNEST($1::date[], $2::varchar[], $3::float8[])
+                ON CONFLICT (currency, date)
+                DO UPDATE SET rate = EXCLUDED.rate
+            ",
+        )
+        .bind(&dates)
+        .bind(&currencies)
+        .bind(&values)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
+
+    Ok(rows)
+}
+
+/// The most recent rate for the currency on or before `date`, as long as it is no older than
+/// `max_age`.
+pub async fn query_fx_rate(
+    pool: &PgPool,
+    currency: &str,
+    date: NaiveDate,
+    max_age: Duration,
+) -> Result<Option<FxRate>, sqlx::Error> {
+    sqlx::query_as::<_, FxRate>(
+        "
+            SELECT * FROM fx_rates
+            WHERE currency = $1 AND date <= $2 AND date >= $3
+            ORDER BY date DESC
+            LIMIT 1
+        ",
+    )
+    .bind(currency)
+    .bind(date)
+    .bind(date - max_age)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The user's settings, with the defaults for anything they have not set.
+pub async fn query_user_settings(
+    pool: &PgPool,
+    user_id: &str,
+) -> Result<UserSettings, sqlx::Error> {
+    sqlx::query_as::<_, UserSettings>(
+        "
+            SELECT
+                $1 AS user_id,
+                COALESCE(
+                    (SELECT reporting_currency FROM user_settings WHERE user_id = $1),
+                    'GBP'
+                ) AS reporting_currency,
+                COALESCE(
+                    (SELECT odd_hours_timezone FROM user_settings WHERE user_id = $1),
+                    'UTC'
+                ) AS odd_hours_timezone
+        ",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn upsert_user_settings(
+    pool: &PgPool,
+    settings: &UserSettings,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO user_settings (user_id, reporting_currency, odd_hours_timezone)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id)
+            DO UPDATE SET
+                reporting_currency = EXCLUDED.reporting_currency,
+                odd_hours_timezone = EXCLUDED.odd_hours_timezone
+        ",
+    )
+    .bind(&settings.user_id)
+    .bind(&settings.reporting_currency)
+    .bind(&settings.odd_hours_timezone)
+    .execute(pool)
+    .await
+}
+
+/// The user's transactions that have not been converted to `reporting_currency` yet.
+pub async fn query_transactions_to_convert(
+    pool: &PgPool,
+    user_id: &str,
+    reporting_currency: &str,
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            JOIN accounts a ON a.id = t.account_id
+            WHERE a.user_id = $1
+                AND (
+                    t.reporting_amount IS NULL
+                    OR t.reporting_currency IS DISTINCT FROM $2
+                )
+        "
+    ))
+    .bind(user_id)
+    .bind(reporting_currency)
+    .fetch_all(pool)
+    .await
+}
+
+/// Records what a transaction came to in the reporting currency, or clears it when there is no
+/// rate to convert it with.
+pub async fn set_transaction_conversion(
+    pool: &PgPool,
+    transaction_id: &str,
+    conversion: Option<&Conversion>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE transactions
+            SET
+                reporting_currency = $2,
+                reporting_amount = $3,
+                fx_rate = $4,
+                fx_rate_date = $5
+            WHERE id = $1
+        ",
+    )
+    .bind(transaction_id)
+    .bind(conversion.map(|conversion| &conversion.reporting_amount.currency))
+    .bind(conversion.map(|conversion| conversion.reporting_amount.amount))
+    .bind(conversion.map(|conversion| conversion.fx_rate))
+    .bind(conversion.map(|conversion| conversion.fx_rate_date))
+    .execute(pool)
+    .await
+}
+
+pub async fn query_transactions_by_ids(
+    pool: &PgPool,
+    transaction_ids: &[String],
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query_as::<_, Transaction>(&format!(
+        "
+            {SELECT_TRANSACTIONS}
+            WHERE t.id = ANY($1)
+        "
+    ))
+    .bind(transaction_ids)
+    .fetch_all(pool)
+    .await
+}
+
+/// Flags probable duplicates, skipping pairs that were flagged before either way round. Returns
+/// how many new pairs were flagged.
+pub async fn insert_duplicates(
+    pool: &PgPool,
+    duplicates: &[DuplicateCandidate],
+) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let mut inserted = 0;
+    for duplicate in duplicates.iter() {
+        inserted += sqlx::query(
+            "
+                INSERT INTO duplicate_transactions (transaction_id, duplicate_id, score)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (
+                    LEAST(transaction_id, duplicate_id),
+                    GREATEST(transaction_id, duplicate_id)
+                )
+                DO NOTHING
+            ",
+        )
+        .bind(&duplicate.transaction_id)
+        .bind(&duplicate.duplicate_id)
+        .bind(duplicate.score)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
+
+    Ok(inserted)
+}
+
+const SELECT_USER_DUPLICATES: &str = "
+    SELECT d.* FROM duplicate_transactions d
+    JOIN transactions t ON t.id = d.transaction_id
+    JOIN accounts a ON a.id = t.account_id
+";
+
+pub async fn query_user_duplicates(
+    pool: &PgPool,
+    user_id: &str,
+    status: Option<DuplicateStatus>,
+) -> Result<Vec<Duplicate>, sqlx::Error> {
+    sqlx::query_as::<_, Duplicate>(&format!(
+        "
+            {SELECT_USER_DUPLICATES}
+            WHERE a.user_id = $1 AND ($2::text IS NULL OR d.status = $2)
+            ORDER BY d.created DESC, d.id DESC
+        "
+    ))
+    .bind(user_id)
+    .bind(status)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_user_duplicate(
+    pool: &PgPool,
+    user_id: &str,
+    duplicate_id: i64,
+) -> Result<Option<Duplicate>, sqlx::Error> {
+    sqlx::query_as::<_, Duplicate>(&format!(
+        "
+            {SELECT_USER_DUPLICATES}
+            WHERE a.user_id = $1 AND d.id = $2
+        "
+    ))
+    .bind(user_id)
+    .bind(duplicate_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Merges the pair, keeping `transaction_id` and marking `duplicate_id` as a duplicate of it.
+pub async fn merge_duplicate(
+    pool: &PgPool,
+    id: i64,
+    transaction_id: &str,
+    duplicate_id: &str,
+) -> Result<Duplicate, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Whichever was merged before, e.g. the other way round, is not a duplicate any more.
+    sqlx::query(
+        "
+            UPDATE transactions SET duplicate_of = NULL
+            WHERE id IN ($1, $2) AND duplicate_of IN ($1, $2)
+        ",
+    )
+    .bind(transaction_id)
+    .bind(duplicate_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE transactions SET duplicate_of = $1 WHERE id = $2")
+        .bind(transaction_id)
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let duplicate = sqlx::query_as::<_, Duplicate>(
+        "
+            UPDATE duplicate_transactions
+            SET
+                transaction_id = $2,
+                duplicate_id = $3,
+                status = 'merged',
+                resolved = now()
+            WHERE id = $1
+            RETURNING *
+        ",
+    )
+    .bind(id)
+    .bind(transaction_id)
+    .bind(duplicate_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(duplicate)
+}
+
+/// Dismisses the pair as not being duplicates, undoing the merge if it was merged.
+pub async fn dismiss_duplicate(
+    pool: &PgPool,
+    duplicate: &Duplicate,
+) -> Result<Duplicate, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE transactions SET duplicate_of = NULL WHERE id = $1 AND duplicate_of = $2")
+        .bind(&duplicate.duplicate_id)
+        .bind(&duplicate.transaction_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let duplicate = sqlx::query_as::<_, Duplicate>(
+        "
+            UPDATE duplicate_transactions
+            SET status = 'dismissed', resolved = now()
+            WHERE id = $1
+            RETURNING *
+        ",
+    )
+    .bind(duplicate.id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(duplicate)
+}
+
+/// Stores alerts for a transaction, skipping any it already has. Returns how many were new.
+pub async fn insert_alerts(
+    pool: &PgPool,
+    user_id: &str,
+    transaction_id: &str,
+    anomalies: &[Anomaly],
+) -> Result<u64, sqlx::Error> {
+    let mut inserted = 0;
+    for anomaly in anomalies.iter() {
+        inserted += sqlx::query(
+            "
+                INSERT INTO alerts (user_id, transaction_id, kind, message)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (transaction_id, kind) DO NOTHING
+            ",
+        )
+        .bind(user_id)
+        .bind(transaction_id)
+        .bind(anomaly.kind)
+        .bind(&anomaly.message)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    }
+    Ok(inserted)
+}
+
+pub async fn query_alerts(
+    pool: &PgPool,
+    user_id: &str,
+    include_dismissed: bool,
+) -> Result<Vec<Alert>, sqlx::Error> {
+    sqlx::query_as::<_, Alert>(
+        "
+            SELECT * FROM alerts
+            WHERE user_id = $1 AND (dismissed IS NULL OR $2)
+            ORDER BY created DESC, id DESC
+        ",
+    )
+    .bind(user_id)
+    .bind(include_dismissed)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn dismiss_alert(
+    pool: &PgPool,
+    user_id: &str,
+    alert_id: i64,
+) -> Result<Option<Alert>, sqlx::Error> {
+    sqlx::query_as::<_, Alert>(
+        "
+            UPDATE alerts
+            SET dismissed = COALESCE(dismissed, now())
+            WHERE id = $1 AND user_id = $2
+            RETURNING *
+        ",
+    )
+    .bind(alert_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The channel the transactions table announces changes on, see notify_transaction_event.
+const TRANSACTION_EVENTS_CHANNEL: &str = "transaction_events";
+
+pub async fn listen_transaction_events(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(TRANSACTION_EVENTS_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Queues an event for each of the user's endpoints that wants it, as part of the change it is
+/// about so that neither happens without the other.
+async fn queue_webhook_event(
+    conn: &mut PgConnection,
+    user_id: &str,
+    event: &str,
+    payload: &Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "
+            INSERT INTO webhook_deliveries (endpoint_id, event, payload)
+            SELECT id, $2, $3 FROM webhook_endpoints
+            WHERE user_id = $1 AND active AND (cardinality(events) = 0 OR $2 = ANY(events))
+        ",
+    )
+    .bind(user_id)
+    .bind(event)
+    .bind(payload)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn query_budgets(pool: &PgPool, user_id: &str) -> Result<Vec<Budget>, sqlx::Error> {
+    sqlx::query_as::<_, Budget>(
+        "
+            SELECT * FROM budgets
+            WHERE user_id = $1
+            ORDER BY category, currency
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn insert_budget(
+    pool: &PgPool,
+    user_id: &str,
+    category: &str,
+    amount: &Money,
+) -> Result<Option<Budget>, sqlx::Error> {
+    sqlx::query_as::<_, Budget>(
+        "
+            INSERT INTO budgets (user_id, category, amount, currency) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, category, currency) DO NOTHING
+            RETURNING *
+        ",
+    )
+    .bind(user_id)
+    .bind(category)
+    .bind(amount.amount)
+    .bind(&amount.currency)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete_budget(
+    pool: &PgPool,
+    user_id: &str,
+    budget_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            DELETE FROM budgets
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(budget_id)
+    .bind(user_id)
+    .execute(pool)
+    .await
+}
+
+/// Records that a budget was gone over in a month and queues the event for it. Returns false when
+/// it was already recorded for that month, in which case nothing is queued.
+pub async fn record_budget_exceeded(
+    pool: &PgPool,
+    budget: &Budget,
+    month: NaiveDate,
+    spent: &Money,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        "
+            INSERT INTO budget_exceedances (budget_id, month, spent) VALUES ($1, $2, $3)
+            ON CONFLICT (budget_id, month) DO NOTHING
+        ",
+    )
+    .bind(budget.id)
+    .bind(month)
+    .bind(spent.amount)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    queue_webhook_event(
+        &mut tx,
+        &budget.user_id,
+        BUDGET_EXCEEDED,
+        &json!({
+            "budget_id": budget.id,
+            "category": budget.category,
+            "month": month,
+            "budget": budget.amount,
+            "spent": spent,
+        }),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn insert_webhook_endpoint(
+    pool: &PgPool,
+    user_id: &str,
+    url: &str,
+    events: &[String],
+) -> Result<WebhookEndpoint, sqlx::Error> {
+    sqlx::query_as::<_, WebhookEndpoint>(
+        "
+            INSERT INTO webhook_endpoints (user_id, url, events)
+            VALUES ($1, $2, $3)
+            RETURNING *
+        ",
+    )
+    .bind(user_id)
+    .bind(url)
+    .bind(events)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn query_webhook_endpoints(
+    pool: &PgPool,
+    user_id: &str,
+) -> Result<Vec<WebhookEndpoint>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookEndpoint>(
+        "
+            SELECT * FROM webhook_endpoints
+            WHERE user_id = $1
+            ORDER BY id
+        ",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_webhook_endpoint(
+    pool: &PgPool,
+    user_id: &str,
+    endpoint_id: i64,
+) -> Result<Option<WebhookEndpoint>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookEndpoint>(
+        "
+            SELECT * FROM webhook_endpoints
+            WHERE id = $1 AND user_id = $2
+        ",
+    )
+    .bind(endpoint_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Deletes the endpoint along with its delivery log.
+pub async fn delete_webhook_endpoint(
+    pool: &PgPool,
+    user_id: &str,
+    endpoint_id: i64,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query("DELETE FROM webhook_endpoints WHERE id = $1 AND user_id = $2")
+        .bind(endpoint_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+}
+
+pub async fn query_webhook_deliveries(
+    pool: &PgPool,
+    endpoint_id: i64,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookDelivery>(
+        "
+            SELECT * FROM webhook_deliveries
+            WHERE endpoint_id = $1
+            ORDER BY created DESC, id DESC
+            LIMIT $2
+        ",
+    )
+    .bind(endpoint_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Takes up to `limit` deliveries that are due. Their next attempt is pushed back by `lease`, so
+/// other instances leave them alone while they are being sent.
+pub async fn claim_due_webhook_deliveries(
+    pool: &PgPool,
+    limit: i64,
+    lease: Duration,
+) -> Result<Vec<DueWebhookDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, DueWebhookDelivery>(
+        "
+            UPDATE webhook_deliveries d
+            SET next_attempt = now() + $2
+            FROM webhook_endpoints e
+            WHERE e.id = d.endpoint_id
+                AND d.id IN (
+                    SELECT id FROM webhook_deliveries
+                    WHERE status = 'pending' AND next_attempt <= now()
+                    ORDER BY next_attempt, id
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED
+                )
+            RETURNING d.*, e.url, e.secret
+        ",
+    )
+    .bind(limit)
+    .bind(lease)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn set_webhook_delivery_payload(
+    pool: &PgPool,
+    delivery_id: i64,
+    payload: &Value,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query("UPDATE webhook_deliveries SET payload = $2 WHERE id = $1")
+        .bind(delivery_id)
+        .bind(payload)
+        .execute(pool)
+        .await
+}
+
+/// Records how an attempt to send a delivery went and what happens to it next.
+pub async fn record_webhook_attempt(
+    pool: &PgPool,
+    delivery_id: i64,
+    status: WebhookDeliveryStatus,
+    response_status: Option<i32>,
+    error: Option<&str>,
+    next_attempt: DateTime<Utc>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE webhook_deliveries
+            SET
+                status = $2,
+                attempts = attempts + 1,
+                last_attempt = now(),
+                next_attempt = $5,
+                response_status = $3,
+                error = $4,
+                delivered = CASE WHEN $2 = 'delivered' THEN now() END
+            WHERE id = $1
+        ",
+    )
+    .bind(delivery_id)
+    .bind(status)
+    .bind(response_status)
+    .bind(error)
+    .bind(next_attempt)
+    .execute(pool)
+    .await
+}
+
+pub async fn insert_monzo_webhook(
+    pool: &PgPool,
+    account_id: &str,
+    event_type: Option<&str>,
+    body: &str,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "
+            INSERT INTO monzo_webhooks (account_id, event_type, body)
+            VALUES ($1, $2, $3)
+            RETURNING id
+        ",
+    )
+    .bind(account_id)
+    .bind(event_type)
+    .bind(body)
+    .fetch_one(pool)
+    .await
+}
+
+/// Takes up to `limit` Monzo webhooks that are due to be processed, oldest first. Their next
+/// attempt is pushed back by `lease`, so other instances leave them alone in the meantime.
+pub async fn claim_due_monzo_webhooks(
+    pool: &PgPool,
+    limit: i64,
+    lease: Duration,
+) -> Result<Vec<MonzoWebhook>, sqlx::Error> {
+    sqlx::query_as::<_, MonzoWebhook>(
+        "
+            UPDATE monzo_webhooks
+            SET next_attempt = now() + $2
+            WHERE id IN (
+                SELECT id FROM monzo_webhooks
+                WHERE status = 'pending' AND next_attempt <= now()
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+        ",
+    )
+    .bind(limit)
+    .bind(lease)
+    .fetch_all(pool)
+    .await
+    .map(|mut webhooks| {
+        // RETURNING does not keep the order of the subquery.
+        webhooks.sort_by_key(|webhook| webhook.id);
+        webhooks
+    })
+}
+
+/// Records how an attempt to process a Monzo webhook went and what happens to it next.
+pub async fn record_monzo_webhook_attempt(
+    pool: &PgPool,
+    webhook_id: i64,
+    status: MonzoWebhookStatus,
+    transaction_id: Option<&str>,
+    error: Option<&str>,
+    next_attempt: DateTime<Utc>,
+) -> Result<PgQueryResult, sqlx::Error> {
+    sqlx::query(
+        "
+            UPDATE monzo_webhooks
+            SET
+                status = $2,
+                attempts = attempts + 1,
+                next_attempt = $4,
+                error = $3,
+                transaction_id = COALESCE($5, transaction_id),
+                processed = CASE WHEN $2 IN ('processed', 'ignored') THEN now() END
+            WHERE id = $1
+        ",
+    )
+    .bind(webhook_id)
+    .bind(status)
+    .bind(error)
+    .bind(next_attempt)
+    .bind(transaction_id)
+    .execute(pool)
+    .await
+}
+
+/// The latest Monzo webhook for a transaction that was received after `webhook_id` and has been
+/// processed, if there is one.
+pub async fn query_newer_processed_monzo_webhook(
+    pool: &PgPool,
+    transaction_id: &str,
+    webhook_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar(
+        "
+            SELECT id FROM monzo_webhooks
+            WHERE transaction_id = $1 AND id > $2 AND status = 'processed'
+            ORDER BY id DESC
+            LIMIT 1
+        ",
+    )
+    .bind(transaction_id)
+    .bind(webhook_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn query_monzo_webhooks(
+    pool: &PgPool,
+    status: MonzoWebhookStatus,
+    limit: i64,
+) -> Result<Vec<MonzoWebhook>, sqlx::Error> {
+    sqlx::query_as::<_, MonzoWebhook>(
+        "
+            SELECT * FROM monzo_webhooks
+            WHERE status = $1
+            ORDER BY received DESC, id DESC
+            LIMIT $2
+        ",
+    )
+    .bind(status)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn query_monzo_webhooks_by_ids(
+    pool: &PgPool,
+    webhook_ids: &[i64],
+) -> Result<Vec<MonzoWebhook>, sqlx::Error> {
+    sqlx::query_as::<_, MonzoWebhook>(
+        "
+            SELECT * FROM monzo_webhooks
+            WHERE id = ANY($1)
+            ORDER BY id
         ",
     )
-    .bind(&account_ids)
+    .bind(webhook_ids)
     .fetch_all(pool)
     .await
 }
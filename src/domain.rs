@@ -1,5 +1,19 @@
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Row, postgres::PgRow};
+
+use crate::money::Money;
+
+/// Reads a column, falling back to the default when the query did not select it.
+pub fn try_get_or_default<'r, T>(row: &'r PgRow, column: &str) -> Result<T, sqlx::Error>
+where
+    T: Default + sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres>,
+{
+    match row.try_get(column) {
+        Err(sqlx::Error::ColumnNotFound(_)) => Ok(T::default()),
+        result => result,
+    }
+}
 
 #[derive(sqlx::FromRow, Clone)]
 pub struct Token {
@@ -10,7 +24,7 @@ pub struct Token {
     pub refresh_token: String,
 }
 
-#[derive(sqlx::FromRow)]
+#[derive(sqlx::FromRow, Serialize)]
 pub struct Account {
     pub id: String,
     pub user_id: String,
@@ -18,16 +32,760 @@ pub struct Account {
     pub created: DateTime<Utc>,
 }
 
-#[derive(sqlx::FromRow, Serialize)]
+#[derive(Serialize, Default, Clone)]
 pub struct Transaction {
     pub id: String,
     pub account_id: String,
-    pub amount: i64,
-    pub currency: String,
+    #[serde(flatten)]
+    pub money: Money,
     pub description: String,
     pub notes: String,
     pub merchant: Option<String>,
+    pub merchant_name: Option<String>,
+    pub counterparty_user_id: Option<String>,
+    pub counterparty_name: Option<String>,
+    /// Set for money moved into or out of a pot.
+    pub pot_id: Option<String>,
+    /// Set when this was merged into another transaction as a duplicate of it.
+    pub duplicate_of: Option<String>,
+    /// The amount in the currency it was spent in, for transactions abroad.
+    pub local_amount: Option<Money>,
+    /// The amount in the user's reporting currency, once there is a rate to convert it with.
+    pub reporting_amount: Option<Money>,
+    /// Units of the reporting currency per unit of the transaction's currency.
+    pub fx_rate: Option<f64>,
+    pub fx_rate_date: Option<NaiveDate>,
     pub category: String,
     pub created: DateTime<Utc>,
     pub settled: Option<DateTime<Utc>>,
+    pub reimbursable: bool,
+    pub claim_id: Option<i64>,
+    pub custom_category: Option<String>,
+    pub tags: Vec<String>,
+    /// Set while locally edited notes are waiting to be pushed to Monzo.
+    pub notes_pending: bool,
+    /// Notes that were changed in Monzo while a local edit was pending, and were overwritten by it.
+    pub notes_conflict: Option<String>,
+    /// In basis points, so 20% is 2000.
+    pub vat_rate: Option<i32>,
+    pub vat_amount: Option<Money>,
+    pub supplier_vat_number: Option<String>,
+    /// Default VAT rate of the custom category, inherited from its closest ancestor with one.
+    pub category_vat_rate: Option<i32>,
+    pub is_split: bool,
+    /// Set when this is one split of a transaction rather than the transaction itself.
+    pub split_id: Option<i64>,
+}
+
+/// Written out by hand because the transaction holds several amounts, each with its own currency
+/// column. Columns that only some queries select are read as their default when missing.
+impl<'r> FromRow<'r, PgRow> for Transaction {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Transaction {
+            id: row.try_get("id")?,
+            account_id: row.try_get("account_id")?,
+            money: Money::from_columns(row, "amount", "currency")?,
+            description: row.try_get("description")?,
+            notes: row.try_get("notes")?,
+            merchant: row.try_get("merchant")?,
+            merchant_name: row.try_get("merchant_name")?,
+            counterparty_user_id: row.try_get("counterparty_user_id")?,
+            counterparty_name: row.try_get("counterparty_name")?,
+            pot_id: row.try_get("pot_id")?,
+            duplicate_of: row.try_get("duplicate_of")?,
+            local_amount: Money::from_nullable_columns(row, "local_amount", "local_currency")?,
+            reporting_amount: Money::from_nullable_columns(
+                row,
+                "reporting_amount",
+                "reporting_currency",
+            )?,
+            fx_rate: row.try_get("fx_rate")?,
+            fx_rate_date: row.try_get("fx_rate_date")?,
+            category: row.try_get("category")?,
+            created: row.try_get("created")?,
+            settled: row.try_get("settled")?,
+            reimbursable: try_get_or_default(row, "reimbursable")?,
+            claim_id: try_get_or_default(row, "claim_id")?,
+            custom_category: try_get_or_default(row, "custom_category")?,
+            tags: try_get_or_default(row, "tags")?,
+            notes_pending: try_get_or_default(row, "notes_pending")?,
+            notes_conflict: try_get_or_default(row, "notes_conflict")?,
+            vat_rate: try_get_or_default(row, "vat_rate")?,
+            vat_amount: Money::from_nullable_columns(row, "vat_amount", "currency")?,
+            supplier_vat_number: try_get_or_default(row, "supplier_vat_number")?,
+            category_vat_rate: try_get_or_default(row, "category_vat_rate")?,
+            is_split: try_get_or_default(row, "is_split")?,
+            split_id: try_get_or_default(row, "split_id")?,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Split {
+    pub id: i64,
+    pub transaction_id: String,
+    /// In the currency of the transaction.
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub money: Money,
+    pub note: Option<String>,
+    pub category_id: Option<i64>,
+    #[sqlx(default)]
+    pub category: Option<String>,
+    #[sqlx(default)]
+    pub tags: Vec<String>,
+    #[serde(skip)]
+    #[sqlx(default)]
+    pub category_vat_rate: Option<i32>,
+    pub reimbursable: bool,
+    pub claim_id: Option<i64>,
+    pub created: DateTime<Utc>,
+}
+
+pub struct NewSplit {
+    pub money: Money,
+    pub note: Option<String>,
+    pub category_id: Option<i64>,
+    pub tag_ids: Vec<i64>,
+    pub reimbursable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ClaimStatus {
+    Draft,
+    Submitted,
+    Approved,
+    Rejected,
+    Paid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClaimAction {
+    Submit,
+    Withdraw,
+    Reopen,
+    Approve,
+    Reject,
+    Pay,
+    /// Money matching the claim arrived, which settles it even if nobody got round to approving
+    /// it yet. Only taken by reconciliation, never asked for.
+    #[serde(skip_deserializing)]
+    Reconcile,
+}
+
+impl ClaimAction {
+    /// Actions that only an approver may take. The rest are taken by the owner of the claim.
+    pub fn requires_approver(&self) -> bool {
+        matches!(
+            self,
+            ClaimAction::Approve | ClaimAction::Reject | ClaimAction::Pay
+        )
+    }
+}
+
+impl ClaimStatus {
+    /// The claim state machine: returns the status reached by taking `action`, or None when the
+    /// action is not allowed from this status.
+    pub fn transition(&self, action: ClaimAction) -> Option<ClaimStatus> {
+        match (self, action) {
+            (ClaimStatus::Draft, ClaimAction::Submit) => Some(ClaimStatus::Submitted),
+            (ClaimStatus::Submitted, ClaimAction::Withdraw) => Some(ClaimStatus::Draft),
+            (ClaimStatus::Rejected, ClaimAction::Reopen) => Some(ClaimStatus::Draft),
+            (ClaimStatus::Submitted, ClaimAction::Approve) => Some(ClaimStatus::Approved),
+            (ClaimStatus::Submitted | ClaimStatus::Approved, ClaimAction::Reject) => {
+                Some(ClaimStatus::Rejected)
+            }
+            (ClaimStatus::Approved, ClaimAction::Pay) => Some(ClaimStatus::Paid),
+            (ClaimStatus::Submitted | ClaimStatus::Approved, ClaimAction::Reconcile) => {
+                Some(ClaimStatus::Paid)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Claim {
+    pub id: i64,
+    pub user_id: String,
+    pub title: String,
+    pub status: ClaimStatus,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub reimbursement_transaction_id: Option<String>,
+    #[sqlx(default)]
+    pub transaction_count: i64,
+    /// One total per currency the claimed transactions are in.
+    #[sqlx(default, json)]
+    pub totals: Vec<Money>,
+}
+
+#[derive(Serialize)]
+pub struct CategoryTotal {
+    pub category: String,
+    pub total: Money,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Category {
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    /// In basis points, so 20% is 2000.
+    pub default_vat_rate: Option<i32>,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct Rule {
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+    pub priority: i32,
+    pub merchant: Option<String>,
+    pub description_pattern: Option<String>,
+    /// Both amounts are in the same currency, and only match transactions in that currency.
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub account_id: Option<String>,
+    #[serde(skip)]
+    pub category_id: Option<i64>,
+    pub category: Option<String>,
+    #[serde(skip)]
+    pub tag_ids: Vec<i64>,
+    pub tags: Vec<String>,
+    pub created: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Rule {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Rule {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            priority: row.try_get("priority")?,
+            merchant: row.try_get("merchant")?,
+            description_pattern: row.try_get("description_pattern")?,
+            min_amount: Money::from_nullable_columns(row, "min_amount", "currency")?,
+            max_amount: Money::from_nullable_columns(row, "max_amount", "currency")?,
+            account_id: row.try_get("account_id")?,
+            category_id: row.try_get("category_id")?,
+            category: row.try_get("category")?,
+            tag_ids: row.try_get("tag_ids")?,
+            tags: row.try_get("tags")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+pub struct NewRule {
+    pub user_id: String,
+    pub name: String,
+    pub priority: i32,
+    pub merchant: Option<String>,
+    pub description_pattern: Option<String>,
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub account_id: Option<String>,
+    pub category_id: Option<i64>,
+    pub tag_ids: Vec<i64>,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct ClaimTransition {
+    pub id: i64,
+    pub claim_id: i64,
+    pub from_status: ClaimStatus,
+    pub to_status: ClaimStatus,
+    pub actor_id: Option<String>,
+    pub comment: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct Receipt {
+    pub id: i64,
+    pub user_id: String,
+    /// None until the receipt has been matched to a transaction.
+    pub transaction_id: Option<String>,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    #[serde(skip)]
+    pub storage_key: String,
+    pub created: DateTime<Utc>,
+    pub monzo_sync_status: ReceiptSyncStatus,
+    pub monzo_attachment_id: Option<String>,
+    pub monzo_synced: Option<DateTime<Utc>>,
+    /// What the uploader knows about an unmatched receipt, used to suggest transactions for it.
+    pub amount: Option<Money>,
+    pub receipt_date: Option<NaiveDate>,
+    pub merchant: Option<String>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Receipt {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Receipt {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            transaction_id: row.try_get("transaction_id")?,
+            file_name: row.try_get("file_name")?,
+            content_type: row.try_get("content_type")?,
+            size: row.try_get("size")?,
+            storage_key: row.try_get("storage_key")?,
+            created: row.try_get("created")?,
+            monzo_sync_status: row.try_get("monzo_sync_status")?,
+            monzo_attachment_id: row.try_get("monzo_attachment_id")?,
+            monzo_synced: row.try_get("monzo_synced")?,
+            amount: Money::from_nullable_columns(row, "amount", "currency")?,
+            receipt_date: row.try_get("receipt_date")?,
+            merchant: row.try_get("merchant")?,
+        })
+    }
+}
+
+pub struct NewReceipt {
+    pub user_id: String,
+    pub transaction_id: Option<String>,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub storage_key: String,
+    pub amount: Option<Money>,
+    pub receipt_date: Option<NaiveDate>,
+    pub merchant: Option<String>,
+}
+
+/// Whether a receipt has been attached to the transaction in Monzo. Receipts whose attachment was
+/// deleted in Monzo are marked as removed rather than uploaded again. A receipt is syncing while a
+/// push has claimed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptSyncStatus {
+    Pending,
+    Syncing,
+    Synced,
+    Removed,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Group {
+    pub id: i64,
+    pub name: String,
+    pub created_by: String,
+    pub created: DateTime<Utc>,
+    #[sqlx(default)]
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct Share {
+    pub user_id: String,
+    pub weight: i32,
+}
+
+/// A shared transaction with everything needed to work out who owes what for it.
+#[derive(sqlx::FromRow)]
+pub struct SharedExpense {
+    pub paid_by: String,
+    #[sqlx(flatten)]
+    pub money: Money,
+    pub user_ids: Vec<String>,
+    pub weights: Vec<i32>,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Settlement {
+    pub id: i64,
+    pub group_id: i64,
+    pub from_user_id: String,
+    pub to_user_id: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub money: Money,
+    pub payer_transaction_id: Option<String>,
+    pub payee_transaction_id: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+/// Positive when the user is owed money, negative when they owe it.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Balance {
+    pub user_id: String,
+    pub balance: Money,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Debt {
+    pub from_user_id: String,
+    pub to_user_id: String,
+    #[serde(flatten)]
+    pub money: Money,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Cadence {
+    Weekly,
+    Fortnightly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+#[derive(Serialize)]
+pub struct Subscription {
+    pub id: i64,
+    pub user_id: String,
+    /// The account of the latest payment.
+    pub account_id: String,
+    #[serde(skip)]
+    pub merchant_key: String,
+    pub merchant: Option<String>,
+    pub description: String,
+    pub cadence: Cadence,
+    /// Money coming in, e.g. a salary, rather than a charge.
+    pub income: bool,
+    /// The size of the latest payment, whichever way it goes.
+    #[serde(flatten)]
+    pub money: Money,
+    pub previous_amount: Option<Money>,
+    pub occurrences: i32,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+    pub next_date: NaiveDate,
+    pub last_transaction_id: String,
+    /// The next payment was expected by now but has not arrived.
+    pub missed: bool,
+    /// The latest payment was for a different amount than the one before.
+    pub price_changed: bool,
+    pub updated: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Subscription {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Subscription {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            account_id: row.try_get("account_id")?,
+            merchant_key: row.try_get("merchant_key")?,
+            merchant: row.try_get("merchant")?,
+            description: row.try_get("description")?,
+            cadence: row.try_get("cadence")?,
+            income: row.try_get("income")?,
+            money: Money::from_columns(row, "amount", "currency")?,
+            previous_amount: Money::from_nullable_columns(row, "previous_amount", "currency")?,
+            occurrences: row.try_get("occurrences")?,
+            first_date: row.try_get("first_date")?,
+            last_date: row.try_get("last_date")?,
+            next_date: row.try_get("next_date")?,
+            last_transaction_id: row.try_get("last_transaction_id")?,
+            missed: row.try_get("missed")?,
+            price_changed: row.try_get("price_changed")?,
+            updated: row.try_get("updated")?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct BalanceSnapshot {
+    pub id: i64,
+    pub account_id: String,
+    pub balance: Money,
+    pub total_balance: Money,
+    pub spend_today: Money,
+    pub created: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for BalanceSnapshot {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(BalanceSnapshot {
+            id: row.try_get("id")?,
+            account_id: row.try_get("account_id")?,
+            balance: Money::from_columns(row, "balance", "currency")?,
+            total_balance: Money::from_columns(row, "total_balance", "currency")?,
+            spend_today: Money::from_columns(row, "spend_today", "currency")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Pot {
+    pub id: String,
+    pub account_id: String,
+    pub name: String,
+    pub balance: Money,
+    pub goal_amount: Option<Money>,
+    pub deleted: bool,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Pot {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Pot {
+            id: row.try_get("id")?,
+            account_id: row.try_get("account_id")?,
+            name: row.try_get("name")?,
+            balance: Money::from_columns(row, "balance", "currency")?,
+            goal_amount: Money::from_nullable_columns(row, "goal_amount", "currency")?,
+            deleted: row.try_get("deleted")?,
+            created: row.try_get("created")?,
+            updated: row.try_get("updated")?,
+        })
+    }
+}
+
+/// How much the user means to spend in a category each month.
+#[derive(Serialize)]
+pub struct Budget {
+    pub id: i64,
+    pub user_id: String,
+    pub category: String,
+    pub amount: Money,
+    pub created: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Budget {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Budget {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            category: row.try_get("category")?,
+            amount: Money::from_columns(row, "amount", "currency")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct PotBalanceSnapshot {
+    pub id: i64,
+    pub pot_id: String,
+    pub balance: Money,
+    pub created: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for PotBalanceSnapshot {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(PotBalanceSnapshot {
+            id: row.try_get("id")?,
+            pot_id: row.try_get("pot_id")?,
+            balance: Money::from_columns(row, "balance", "currency")?,
+            created: row.try_get("created")?,
+        })
+    }
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Clone)]
+pub struct FxRate {
+    pub date: NaiveDate,
+    pub currency: String,
+    /// Units of the currency per euro.
+    pub rate: f64,
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize)]
+pub struct UserSettings {
+    #[serde(skip_deserializing)]
+    pub user_id: String,
+    pub reporting_currency: String,
+    /// The IANA timezone, such as Europe/London, that payments abroad are checked for odd hours
+    /// in. Monzo does not say which timezone a payment was made in, so by default the odd hours
+    /// are UTC ones.
+    #[serde(default = "default_odd_hours_timezone")]
+    pub odd_hours_timezone: String,
+}
+
+fn default_odd_hours_timezone() -> String {
+    String::from("UTC")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateStatus {
+    Pending,
+    Merged,
+    Dismissed,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Duplicate {
+    pub id: i64,
+    /// The transaction kept when the pair is merged.
+    pub transaction_id: String,
+    pub duplicate_id: String,
+    /// Between 0 and 1, how alike the two transactions are.
+    pub score: f64,
+    pub status: DuplicateStatus,
+    pub created: DateTime<Utc>,
+    pub resolved: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// A large payment to a merchant the user has not paid before.
+    NewMerchant,
+    /// Far more than the user usually spends in the category.
+    CategorySpike,
+    /// A payment abroad in the middle of the night.
+    ForeignOddHours,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct Alert {
+    pub id: i64,
+    pub user_id: String,
+    pub transaction_id: String,
+    pub kind: AlertKind,
+    pub message: String,
+    pub created: DateTime<Utc>,
+    pub dismissed: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionEventKind {
+    Created,
+    Updated,
+}
+
+/// A transaction was stored or changed, as announced by Postgres.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransactionEvent {
+    pub user_id: Option<String>,
+    pub transaction_id: String,
+    pub kind: TransactionEventKind,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct WebhookEndpoint {
+    pub id: i64,
+    pub user_id: String,
+    pub url: String,
+    /// Only shown when the endpoint is created.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// The events sent to the endpoint, or all of them when empty.
+    pub events: Vec<String>,
+    pub active: bool,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub endpoint_id: i64,
+    pub event: String,
+    #[serde(skip)]
+    pub transaction_id: Option<String>,
+    /// Filled in for transaction events when they are first sent.
+    pub payload: Option<serde_json::Value>,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt: DateTime<Utc>,
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created: DateTime<Utc>,
+    pub delivered: Option<DateTime<Utc>>,
+}
+
+/// A delivery that is due, with where to send it.
+#[derive(sqlx::FromRow)]
+pub struct DueWebhookDelivery {
+    #[sqlx(flatten)]
+    pub delivery: WebhookDelivery,
+    pub url: String,
+    pub secret: String,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MonzoWebhookStatus {
+    Pending,
+    Processed,
+    /// Of a type there is nothing to do for.
+    Ignored,
+    /// Gave up on after failing every attempt.
+    Failed,
+}
+
+/// A webhook from Monzo as it was received.
+#[derive(sqlx::FromRow, Serialize)]
+pub struct MonzoWebhook {
+    pub id: i64,
+    pub account_id: String,
+    /// The type the body says it is, if it could be read.
+    pub event_type: Option<String>,
+    pub body: String,
+    /// The transaction it was about, once it has been processed.
+    pub transaction_id: Option<String>,
+    pub status: MonzoWebhookStatus,
+    pub attempts: i32,
+    pub next_attempt: DateTime<Utc>,
+    pub error: Option<String>,
+    pub received: DateTime<Utc>,
+    pub processed: Option<DateTime<Utc>>,
+}
+
+/// Monzo webhooks to run through processing again: inbox entries by id or by status, and webhook
+/// bodies from elsewhere, e.g. logs.
+#[derive(Debug, Default, Deserialize)]
+pub struct ReplayRequest {
+    #[serde(default)]
+    pub webhook_ids: Vec<i64>,
+    pub status: Option<MonzoWebhookStatus>,
+    #[serde(default)]
+    pub payloads: Vec<serde_json::Value>,
+    /// Only report what would change.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// What replaying a Monzo webhook did, or would do in a dry run.
+#[derive(Debug, Serialize)]
+pub struct WebhookReplay {
+    /// The inbox entry, when it was one.
+    pub webhook_id: Option<i64>,
+    pub status: MonzoWebhookStatus,
+    pub transaction_id: Option<String>,
+    /// The fields of the transaction the webhook changed, with their values before and after.
+    pub changes: Vec<FieldChange>,
+    pub error: Option<String>,
 }
@@ -0,0 +1,90 @@
+use crate::{domain::Transaction, matching::merchant_similarity};
+
+/// How far apart two transactions can be and still be the same spending. Imports often only have
+/// the date, so this allows for a whole day and time zones.
+pub const MAX_HOURS_APART: i64 = 36;
+/// Descriptions less alike than this are different spending that happens to cost the same.
+const MIN_DESCRIPTION_SIMILARITY: f64 = 0.5;
+const TIME_WEIGHT: f64 = 0.4;
+const DESCRIPTION_WEIGHT: f64 = 0.6;
+
+pub struct DuplicateCandidate {
+    /// The transaction to keep.
+    pub transaction_id: String,
+    pub duplicate_id: String,
+    pub score: f64,
+}
+
+/// Compares descriptions both ways round, and the merchant names when both have one. The same
+/// Monzo merchant counts as a perfect match.
+fn description_similarity(a: &Transaction, b: &Transaction) -> f64 {
+    if let (Some(merchant_a), Some(merchant_b)) = (&a.merchant, &b.merchant)
+        && !merchant_a.is_empty()
+        && merchant_a == merchant_b
+    {
+        return 1.0;
+    }
+
+    let name_a = a.merchant_name.as_ref().unwrap_or(&a.description);
+    let name_b = b.merchant_name.as_ref().unwrap_or(&b.description);
+    [
+        merchant_similarity(&a.description, &b.description),
+        merchant_similarity(&b.description, &a.description),
+        merchant_similarity(name_a, name_b),
+        merchant_similarity(name_b, name_a),
+    ]
+    .into_iter()
+    .fold(0.0, f64::max)
+}
+
+/// Which of the two to keep: the one with a Monzo merchant, as that came from Monzo itself, and
+/// otherwise the one recorded first.
+fn keep_first(a: &Transaction, b: &Transaction) -> bool {
+    match (a.merchant.is_some(), b.merchant.is_some()) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => (a.created, &a.id) <= (b.created, &b.id),
+    }
+}
+
+/// Finds pairs of transactions that look like the same spending recorded twice: on the same
+/// account, for the same amount, close together in time and with similar descriptions. Pot
+/// transfers and transactions already merged into another are left out.
+pub fn find_duplicates(transactions: &[Transaction]) -> Vec<DuplicateCandidate> {
+    let mut candidates: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|transaction| {
+            transaction.money.amount != 0
+                && transaction.pot_id.is_none()
+                && transaction.duplicate_of.is_none()
+        })
+        .collect();
+    candidates.sort_by_key(|transaction| transaction.created);
+
+    let mut duplicates = vec![];
+    for (index, a) in candidates.iter().enumerate() {
+        for b in candidates[index + 1..].iter() {
+            let hours_apart = (b.created - a.created).num_hours();
+            if hours_apart > MAX_HOURS_APART {
+                break;
+            }
+            if a.id == b.id || a.account_id != b.account_id || a.money != b.money {
+                continue;
+            }
+
+            let similarity = description_similarity(a, b);
+            if similarity < MIN_DESCRIPTION_SIMILARITY {
+                continue;
+            }
+            let time_score = 1.0 - hours_apart as f64 / (MAX_HOURS_APART + 1) as f64;
+
+            let (kept, duplicate) = if keep_first(a, b) { (a, b) } else { (b, a) };
+            duplicates.push(DuplicateCandidate {
+                transaction_id: kept.id.clone(),
+                duplicate_id: duplicate.id.clone(),
+                score: TIME_WEIGHT * time_score + DESCRIPTION_WEIGHT * similarity,
+            });
+        }
+    }
+    duplicates
+}
@@ -0,0 +1,129 @@
+use chrono::{Days, NaiveDate};
+use serde::Serialize;
+
+use crate::{domain::Subscription, money::Money};
+
+/// How many days ahead forecasts project the balance to.
+pub const FORECAST_DAYS: [u64; 3] = [30, 60, 90];
+
+#[derive(Debug, Serialize)]
+pub struct ForecastPayment {
+    pub subscription_id: i64,
+    pub date: NaiveDate,
+    pub description: String,
+    /// Negative for money going out, like a transaction.
+    pub amount: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForecastHorizon {
+    pub days: u64,
+    pub date: NaiveDate,
+    pub income: Money,
+    pub outgoings: Money,
+    pub balance: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Forecast {
+    pub account_id: String,
+    pub date: NaiveDate,
+    pub balance: Money,
+    pub horizons: Vec<ForecastHorizon>,
+    /// Where the balance is expected to be at its lowest over the whole forecast.
+    pub lowest_balance: Money,
+    pub lowest_date: NaiveDate,
+    pub payments: Vec<ForecastPayment>,
+}
+
+/// Projects an account's balance forward from `today` by playing out the recurring payments
+/// expected into and out of it. Payments that were due by today but have not arrived are left out,
+/// since they were most likely cancelled or moved.
+pub fn forecast(
+    account_id: &str,
+    balance: &Money,
+    today: NaiveDate,
+    subscriptions: &[Subscription],
+) -> Option<Forecast> {
+    let end = today.checked_add_days(Days::new(*FORECAST_DAYS.iter().max()?))?;
+
+    let mut payments = vec![];
+    for subscription in subscriptions.iter().filter(|subscription| {
+        subscription.account_id == account_id && subscription.money.currency == balance.currency
+    }) {
+        let amount = if subscription.income {
+            subscription.money.clone()
+        } else {
+            subscription.money.checked_neg().ok()?
+        };
+        for n in 1.. {
+            let Some(date) = subscription.cadence.date_after(subscription.last_date, n) else {
+                break;
+            };
+            if date > end {
+                break;
+            }
+            if date > today {
+                payments.push(ForecastPayment {
+                    subscription_id: subscription.id,
+                    date,
+                    description: subscription.description.clone(),
+                    amount: amount.clone(),
+                });
+            }
+        }
+    }
+    payments.sort_by_key(|payment| payment.date);
+
+    let mut horizons = vec![];
+    for days in FORECAST_DAYS {
+        let date = today.checked_add_days(Days::new(days))?;
+        let upcoming = payments.iter().filter(|payment| payment.date <= date);
+        let income = Money::sum(
+            &balance.currency,
+            upcoming
+                .clone()
+                .filter(|payment| payment.amount.amount > 0)
+                .map(|payment| &payment.amount),
+        )
+        .ok()?;
+        let outgoings = Money::sum(
+            &balance.currency,
+            upcoming
+                .filter(|payment| payment.amount.amount < 0)
+                .map(|payment| &payment.amount),
+        )
+        .ok()?
+        .checked_neg()
+        .ok()?;
+        horizons.push(ForecastHorizon {
+            days,
+            date,
+            balance: balance
+                .checked_add(&income)
+                .and_then(|balance| balance.checked_sub(&outgoings))
+                .ok()?,
+            income,
+            outgoings,
+        });
+    }
+
+    let (mut lowest_balance, mut lowest_date) = (balance.clone(), today);
+    let mut running = balance.clone();
+    for payment in payments.iter() {
+        running = running.checked_add(&payment.amount).ok()?;
+        if running.amount < lowest_balance.amount {
+            (lowest_balance, lowest_date) = (running.clone(), payment.date);
+        }
+    }
+
+    Some(Forecast {
+        account_id: account_id.to_string(),
+        date: today,
+        balance: balance.clone(),
+        horizons,
+        lowest_balance,
+        lowest_date,
+        payments,
+    })
+}
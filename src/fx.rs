@@ -0,0 +1,132 @@
+use chrono::NaiveDate;
+
+use crate::{
+    domain::{FxRate, Transaction},
+    money::{Money, minor_unit_exponent},
+};
+
+/// ECB reference rates are quoted against the euro, which is not in the files itself.
+pub const BASE_CURRENCY: &str = "EUR";
+
+/// How old a rate can be and still be used. The ECB does not publish rates at weekends or on
+/// holidays, so the last one before the transaction is used instead.
+pub const MAX_RATE_AGE_DAYS: i64 = 7;
+
+pub struct Conversion {
+    pub reporting_amount: Money,
+    pub fx_rate: f64,
+    pub fx_rate_date: NaiveDate,
+}
+
+/// Normalises a currency code, e.g. " usd" to "USD".
+pub fn normalise_currency(currency: &str) -> Option<String> {
+    let currency = currency.trim().to_ascii_uppercase();
+    (currency.len() == 3 && currency.chars().all(|c| c.is_ascii_alphabetic())).then_some(currency)
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%d %B %Y"))
+        .ok()
+}
+
+/// Parses rates in the ECB's CSV format: a `Date` column followed by one column per currency, as
+/// in both the daily file (dates like "17 October 2026") and the history file (dates like
+/// "2026-10-17"). Currencies without a rate on a day are marked "N/A" and left out.
+pub fn parse_ecb_csv(text: &str) -> Result<Vec<FxRate>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let (_, header) = lines.next().ok_or("The file is empty")?;
+    let columns: Vec<&str> = header.split(',').map(|column| column.trim()).collect();
+    if !columns.first().is_some_and(|column| {
+        column
+            .trim_start_matches('\u{feff}')
+            .eq_ignore_ascii_case("date")
+    }) {
+        return Err(String::from("The first column should be Date"));
+    }
+
+    let mut rates = vec![];
+    for (index, line) in lines {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        let date = parse_date(fields[0])
+            .ok_or_else(|| format!("Invalid date '{}' on line {}", fields[0], index + 1))?;
+        for (currency, value) in columns.iter().zip(fields.iter()).skip(1) {
+            if currency.is_empty() || value.is_empty() || *value == "N/A" {
+                continue;
+            }
+            let currency = normalise_currency(currency)
+                .ok_or_else(|| format!("Invalid currency '{}' in the header", currency))?;
+            let rate = value
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| rate.is_finite() && *rate > 0.0)
+                .ok_or_else(|| format!("Invalid rate '{}' on line {}", value, index + 1))?;
+            rates.push(FxRate {
+                date,
+                currency,
+                rate,
+            });
+        }
+    }
+
+    Ok(rates)
+}
+
+/// Converts an amount to another currency, at `rate` units of the other currency per unit of the
+/// amount's currency.
+pub fn convert_amount(money: &Money, to_currency: &str, rate: f64) -> Money {
+    let exponent =
+        minor_unit_exponent(to_currency) as i32 - minor_unit_exponent(&money.currency) as i32;
+    Money::new(
+        (money.amount as f64 * rate * 10f64.powi(exponent)).round() as i64,
+        to_currency,
+    )
+}
+
+/// Works out a transaction's amount in the reporting currency. Card payments abroad already say
+/// what was charged locally, which is used as is when it is in the reporting currency. Otherwise
+/// the amount is converted via the euro rates of both currencies, which are only needed then.
+pub fn convert_transaction(
+    transaction: &Transaction,
+    reporting_currency: &str,
+    from_rate: Option<&FxRate>,
+    to_rate: Option<&FxRate>,
+) -> Option<Conversion> {
+    let date = transaction.created.date_naive();
+
+    if transaction.money.currency == reporting_currency {
+        return Some(Conversion {
+            reporting_amount: transaction.money.clone(),
+            fx_rate: 1.0,
+            fx_rate_date: date,
+        });
+    }
+
+    if let Some(local_amount) = &transaction.local_amount
+        && local_amount.currency == reporting_currency
+        && !local_amount.is_zero()
+        && !transaction.money.is_zero()
+    {
+        // The rate Monzo charged at, in units rather than minor units.
+        let exponent = minor_unit_exponent(&transaction.money.currency) as i32
+            - minor_unit_exponent(reporting_currency) as i32;
+        return Some(Conversion {
+            reporting_amount: local_amount.clone(),
+            fx_rate: local_amount.amount as f64 / transaction.money.amount as f64
+                * 10f64.powi(exponent),
+            fx_rate_date: date,
+        });
+    }
+
+    let (from_rate, to_rate) = (from_rate?, to_rate?);
+    let rate = to_rate.rate / from_rate.rate;
+    Some(Conversion {
+        reporting_amount: convert_amount(&transaction.money, reporting_currency, rate),
+        fx_rate: rate,
+        fx_rate_date: from_rate.date.min(to_rate.date),
+    })
+}
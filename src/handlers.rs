@@ -1,23 +1,79 @@
-use std::sync::Arc;
+use std::{convert::Infallible, sync::Arc};
 
 use crate::{
     AppState,
-    db::{query_account_ids, query_transactions, upsert_token, upsert_transaction},
-    domain::{Token, Transaction},
-    model::{initial_load_data, parse_monzo_date},
-    monzo::{TransactionRequest, exchange_auth_code},
+    db::{
+        add_claim_splits, add_claim_transactions, clear_transaction_category, delete_budget,
+        delete_category, delete_claim, delete_group_member, delete_receipt, delete_role,
+        delete_rule, delete_tag, delete_webhook_endpoint, dismiss_alert, dismiss_duplicate,
+        insert_budget, insert_category, insert_claim, insert_group, insert_group_member,
+        insert_monzo_webhook, insert_receipt, insert_role, insert_rule, insert_settlement,
+        insert_tag, insert_webhook_endpoint, match_receipt, merge_duplicate, query_account,
+        query_account_by_webhook_secret, query_account_ids, query_accounts, query_alerts,
+        query_all_tokens, query_balance_snapshots, query_budgets, query_categories, query_category,
+        query_category_ancestor_ids, query_claim, query_claim_by_id, query_claim_transactions,
+        query_claim_transitions, query_claims, query_claims_with_status, query_has_role,
+        query_latest_balance_snapshots, query_monzo_webhooks, query_pot_balance_snapshots,
+        query_pots, query_receipt, query_receipts, query_rule, query_rules, query_settlements,
+        query_splits, query_subscriptions, query_tags, query_transaction, query_transaction_shares,
+        query_transactions, query_transactions_between, query_transactions_by_ids,
+        query_unmatched_receipts, query_user_duplicate, query_user_duplicates, query_user_group,
+        query_user_groups, query_user_pot, query_user_receipt, query_user_settings,
+        query_user_split, query_user_transaction, query_users_with_role, query_webhook_deliveries,
+        query_webhook_endpoint, query_webhook_endpoints, remove_claim_split,
+        remove_claim_transaction, replace_transaction_splits, set_transaction_category,
+        set_transaction_reimbursable, set_transaction_vat, share_transaction, tag_transaction,
+        transition_claim_status, unshare_transaction, untag_transaction, update_category,
+        update_tag, update_transaction_notes, upsert_category_by_name, upsert_fx_rates,
+        upsert_tag_by_name, upsert_token, upsert_user_settings,
+    },
+    domain::{
+        Account, Alert, Balance, BalanceSnapshot, Budget, Category, CategoryTotal, Claim,
+        ClaimAction, ClaimStatus, ClaimTransition, Debt, Duplicate, DuplicateStatus, Group,
+        MonzoWebhook, MonzoWebhookStatus, NewReceipt, NewRule, NewSplit, Pot, PotBalanceSnapshot,
+        Receipt, ReplayRequest, Rule, Settlement, Share, Split, Subscription, Tag, Token,
+        Transaction, TransactionEventKind, UserSettings, WebhookDelivery, WebhookEndpoint,
+        WebhookReplay,
+    },
+    forecast::Forecast,
+    fx::{normalise_currency, parse_ecb_csv},
+    matching::{DATE_WINDOW_DAYS, MatchSuggestion, rank_candidates},
+    model::{
+        apply_rules, category_totals, convert_user_transactions, detect_user_duplicates,
+        detect_user_subscriptions, forecast_account, group_balances, initial_load_data,
+        push_receipt, push_transaction_notes, remove_receipt_from_monzo, replay_monzo_webhooks,
+    },
+    money::{Money, MoneyError},
+    monzo::{WebhookRequest, exchange_auth_code},
+    report::{Report, ReportReceipt, render_report},
+    splits::split_lines,
+    storage::{delete_blob, get_blob, put_blob},
+    vat::{MAX_VAT_RATE, VatReport, normalise_vat_number, quarter_dates, vat_report},
+    webhooks::WEBHOOK_EVENTS,
 };
 use axum::{
     Json,
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Redirect, Response},
+    body::Bytes,
+    extract::{Multipart, Path, Query, State, multipart::MultipartError},
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    response::{
+        IntoResponse, Redirect, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use futures::{Stream, StreamExt, stream};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
 use url::form_urlencoded;
 
+const APPROVER_ROLE: &str = "approver";
+
 #[derive(Debug, Deserialize)]
 pub struct CallbackParams {
     pub code: Option<String>,
@@ -37,6 +93,8 @@ pub enum AppError {
     NotFound,
     InternalServerError,
     BadRequest(String),
+    Unauthorized,
+    Forbidden(String),
 }
 
 impl From<sqlx::Error> for AppError {
@@ -45,6 +103,12 @@ impl From<sqlx::Error> for AppError {
     }
 }
 
+impl From<MoneyError> for AppError {
+    fn from(err: MoneyError) -> Self {
+        AppError::BadRequest(err.to_string())
+    }
+}
+
 impl From<reqwest::Error> for AppError {
     fn from(err: reqwest::Error) -> Self {
         AppError::ReqwestError(err)
@@ -65,7 +129,7 @@ impl IntoResponse for AppError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Database error".to_string(),
             ),
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Todo not found".to_string()),
+            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
             AppError::InternalServerError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal server error".to_string(),
@@ -74,6 +138,11 @@ impl IntoResponse for AppError {
                 tracing::info!("Bad request: {}", &msg);
                 (StatusCode::BAD_REQUEST, msg)
             }
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            AppError::Forbidden(msg) => {
+                tracing::info!("Forbidden: {}", &msg);
+                (StatusCode::FORBIDDEN, msg)
+            }
         };
 
         (
@@ -177,61 +246,3077 @@ pub async fn get_transactions(
     Ok(Json(DataResponse { data: transactions }))
 }
 
+/// Receives Monzo's webhooks for an account. The URL carries the secret registered for that
+/// account, since Monzo does not sign them. Webhooks are stored as they are and acknowledged
+/// straight away, then processed in the background so that none are lost when that fails.
 #[axum::debug_handler]
 pub async fn monzo_callback(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<Value>,
+    Path(webhook_secret): Path<String>,
+    body: Bytes,
 ) -> Result<StatusCode, AppError> {
+    let account = query_account_by_webhook_secret(&state.pool, &webhook_secret)
+        .await?
+        .ok_or_else(|| {
+            tracing::warn!("Monzo callback was called with an unknown webhook secret");
+            AppError::Unauthorized
+        })?;
+
+    let body = String::from_utf8_lossy(&body);
+    tracing::info!(
+        "Monzo callback was called for account_id={} with body={}",
+        &account.id,
+        &body
+    );
+
+    // Whatever the body is, it is stored, and processing it is what decides whether it is valid.
+    let event_type = serde_json::from_str::<WebhookRequest>(&body)
+        .ok()
+        .map(|request| request.event_type);
+    let webhook_id =
+        insert_monzo_webhook(&state.pool, &account.id, event_type.as_deref(), &body).await?;
+
     tracing::info!(
-        "Monzo callback was called with payload={}",
-        serde_json::to_string_pretty(&payload).unwrap_or(String::from("<invalid json>"))
+        "Stored Monzo webhook id={} of type={}",
+        webhook_id,
+        event_type.as_deref().unwrap_or("<unknown>")
     );
 
-    let payload_type = payload.get("type");
-    if payload_type.is_none() {
+    state.monzo_webhooks_received.notify_one();
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRuleRequest {
+    pub name: String,
+    #[serde(default)]
+    pub priority: i32,
+    pub merchant: Option<String>,
+    pub description_pattern: Option<String>,
+    /// Either `amount` in minor units or `amount_decimal`, with the `currency`. Both amounts should
+    /// be in the same currency.
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    pub account_id: Option<String>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApplyRulesResponse {
+    pub transactions: usize,
+    pub matched: usize,
+}
+
+#[axum::debug_handler]
+pub async fn get_rules(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Rule>>>, AppError> {
+    let rules = query_rules(&state.pool, &user_id)
+        .await
+        .inspect_err(|err| tracing::error!("Error querying rules in get_rules: {:#?}", err))?;
+
+    Ok(Json(DataResponse { data: rules }))
+}
+
+#[axum::debug_handler]
+pub async fn create_rule(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CreateRuleRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Rule>>), AppError> {
+    if request.merchant.is_none()
+        && request.description_pattern.is_none()
+        && request.min_amount.is_none()
+        && request.max_amount.is_none()
+        && request.account_id.is_none()
+    {
         return Err(AppError::BadRequest(String::from(
-            "Request body should have type 'transaction.created'",
+            "A rule needs at least one of merchant, description_pattern, min_amount, max_amount or account_id",
         )));
     }
 
-    let payload_type = payload_type.unwrap().as_str().unwrap_or("");
-    if payload_type != "transaction.created" && payload_type != "transaction.updated" {
+    if request.category.is_none() && request.tags.is_empty() {
         return Err(AppError::BadRequest(String::from(
-            "Request body should have type 'transaction.created|updated'",
+            "A rule needs a category or at least one tag to assign",
         )));
     }
 
-    let data = payload.get("data");
-    if data.is_none() {
+    let min_amount = request.min_amount.map(validate_money).transpose()?;
+    let max_amount = request.max_amount.map(validate_money).transpose()?;
+    if let (Some(min_amount), Some(max_amount)) = (&min_amount, &max_amount) {
+        if min_amount.currency != max_amount.currency {
+            return Err(AppError::BadRequest(String::from(
+                "min_amount and max_amount should be in the same currency",
+            )));
+        }
+        if min_amount.amount > max_amount.amount {
+            return Err(AppError::BadRequest(String::from(
+                "min_amount should not be greater than max_amount",
+            )));
+        }
+    }
+
+    if let Some(pattern) = &request.description_pattern {
+        Regex::new(pattern)
+            .map_err(|err| AppError::BadRequest(format!("Invalid description_pattern: {}", err)))?;
+    }
+
+    if let Some(account_id) = &request.account_id {
+        let account_ids = query_account_ids(&state.pool, &user_id).await?;
+        if !account_ids.contains(account_id) {
+            return Err(AppError::BadRequest(format!(
+                "Unknown account_id={} for user_id={}",
+                account_id, &user_id
+            )));
+        }
+    }
+
+    let category_id = match &request.category {
+        Some(name) => Some(upsert_category_by_name(&state.pool, &user_id, name).await?),
+        None => None,
+    };
+
+    let mut tag_ids = vec![];
+    for name in request.tags.iter() {
+        tag_ids.push(upsert_tag_by_name(&state.pool, &user_id, name).await?);
+    }
+
+    let new_rule = NewRule {
+        user_id: user_id.clone(),
+        name: request.name,
+        priority: request.priority,
+        merchant: request.merchant,
+        description_pattern: request.description_pattern,
+        min_amount,
+        max_amount,
+        account_id: request.account_id,
+        category_id,
+        tag_ids,
+    };
+
+    let rule_id = insert_rule(&state.pool, &new_rule)
+        .await
+        .inspect_err(|err| {
+            tracing::error!("Error inserting rule for user_id={}: {:#?}", &user_id, err)
+        })?;
+
+    let rule = query_rule(&state.pool, rule_id)
+        .await?
+        .ok_or(AppError::InternalServerError)?;
+
+    tracing::info!("Created rule id={} for user_id={}", rule.id, &user_id);
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: rule })))
+}
+
+#[axum::debug_handler]
+pub async fn remove_rule(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, rule_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let result = delete_rule(&state.pool, &user_id, rule_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    tracing::info!("Deleted rule id={} for user_id={}", rule_id, &user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Re-evaluates the user's rules against all of their historical transactions.
+#[axum::debug_handler]
+pub async fn reapply_rules(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<ApplyRulesResponse>>, AppError> {
+    let account_ids = query_account_ids(&state.pool, &user_id).await?;
+    let transactions = query_transactions(&state.pool, &account_ids).await?;
+
+    let matched = apply_rules(&state.pool, &user_id, &transactions)
+        .await
+        .map_err(|err| {
+            tracing::error!("Error re-applying rules for user_id={}: {}", &user_id, err);
+            AppError::InternalServerError
+        })?;
+
+    Ok(Json(DataResponse {
+        data: ApplyRulesResponse {
+            transactions: transactions.len(),
+            matched,
+        },
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryRequest {
+    pub name: String,
+    pub parent_id: Option<i64>,
+    pub default_vat_rate: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetRequest {
+    pub category: String,
+    /// Either `amount` in minor units or `amount_decimal`, with the `currency`.
+    #[serde(flatten)]
+    pub amount: Money,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionCategoryRequest {
+    pub category_id: i64,
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .map(|err| err.is_unique_violation())
+        .unwrap_or(false)
+}
+
+/// Checks that the currency of an amount is a currency code, and normalises it.
+fn validate_money(money: Money) -> Result<Money, AppError> {
+    let currency = normalise_currency(&money.currency).ok_or(AppError::BadRequest(
+        String::from("currency should be a three letter currency code"),
+    ))?;
+    Ok(Money::new(money.amount, &currency))
+}
+
+fn validate_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
         return Err(AppError::BadRequest(String::from(
-            "Unable to parse data payload to transaction request",
+            "Name should not be empty",
         )));
     }
+    Ok(name.to_string())
+}
 
-    let transaction =
-        serde_json::from_value::<TransactionRequest>(data.unwrap().clone()).map_err(|_err| {
-            AppError::BadRequest(String::from(
-                "Unable to parse data payload to transaction request",
-            ))
-        })?;
+/// Checks that `parent_id` belongs to the user and, when moving an existing category, that it is
+/// not the category itself or one of its descendants.
+async fn validate_category_parent(
+    state: &AppState,
+    user_id: &str,
+    category_id: Option<i64>,
+    parent_id: Option<i64>,
+) -> Result<(), AppError> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+
+    if query_category(&state.pool, user_id, parent_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(format!(
+            "Unknown parent_id={}",
+            parent_id
+        )));
+    }
+
+    if let Some(category_id) = category_id {
+        let ancestor_ids = query_category_ancestor_ids(&state.pool, parent_id).await?;
+        if ancestor_ids.contains(&category_id) {
+            return Err(AppError::BadRequest(String::from(
+                "A category cannot be moved underneath itself",
+            )));
+        }
+    }
+
+    Ok(())
+}
 
-    upsert_transaction(
+fn validate_vat_rate(vat_rate: Option<i32>) -> Result<(), AppError> {
+    match vat_rate {
+        Some(vat_rate) if !(0..=MAX_VAT_RATE).contains(&vat_rate) => {
+            Err(AppError::BadRequest(format!(
+                "VAT rates should be between 0 and {} basis points",
+                MAX_VAT_RATE
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn find_user_transaction(
+    state: &AppState,
+    user_id: &str,
+    transaction_id: &str,
+) -> Result<Transaction, AppError> {
+    query_user_transaction(&state.pool, user_id, transaction_id)
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error querying transaction id={} for user_id={}: {:#?}",
+                transaction_id,
+                user_id,
+                err
+            )
+        })?
+        .ok_or(AppError::NotFound)
+}
+
+#[axum::debug_handler]
+pub async fn get_categories(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Category>>>, AppError> {
+    let categories = query_categories(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: categories }))
+}
+
+#[axum::debug_handler]
+pub async fn create_category(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CategoryRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Category>>), AppError> {
+    let name = validate_name(&request.name)?;
+    validate_category_parent(&state, &user_id, None, request.parent_id).await?;
+    validate_vat_rate(request.default_vat_rate)?;
+
+    let category = insert_category(
         &state.pool,
-        &Transaction {
-            id: transaction.id,
-            account_id: transaction.account_id,
-            amount: transaction.amount,
-            currency: transaction.currency,
-            description: transaction.description,
-            notes: transaction.notes,
-            merchant: transaction.merchant.map(|merchant| merchant.id),
-            category: transaction.category,
-            created: parse_monzo_date(&transaction.created).unwrap(),
-            settled: parse_monzo_date(&transaction.settled),
-        },
+        &user_id,
+        &name,
+        request.parent_id,
+        request.default_vat_rate,
+    )
+    .await?
+    .ok_or(AppError::BadRequest(format!(
+        "Category name={} already exists",
+        &name
+    )))?;
+
+    tracing::info!(
+        "Created category id={} for user_id={}",
+        category.id,
+        &user_id
+    );
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: category })))
+}
+
+#[axum::debug_handler]
+pub async fn edit_category(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, category_id)): Path<(String, i64)>,
+    Json(request): Json<CategoryRequest>,
+) -> Result<Json<DataResponse<Category>>, AppError> {
+    let name = validate_name(&request.name)?;
+    validate_category_parent(&state, &user_id, Some(category_id), request.parent_id).await?;
+    validate_vat_rate(request.default_vat_rate)?;
+
+    let category = update_category(
+        &state.pool,
+        &user_id,
+        category_id,
+        &name,
+        request.parent_id,
+        request.default_vat_rate,
     )
     .await
-    .map_err(|_err| AppError::SqlxError)?;
+    .map_err(|err| {
+        if is_unique_violation(&err) {
+            AppError::BadRequest(format!("Category name={} already exists", &name))
+        } else {
+            AppError::from(err)
+        }
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    Ok(Json(DataResponse { data: category }))
+}
+
+#[axum::debug_handler]
+pub async fn remove_category(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, category_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let result = delete_category(&state.pool, &user_id, category_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    tracing::info!(
+        "Deleted category id={} for user_id={}",
+        category_id,
+        &user_id
+    );
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn get_tags(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Tag>>>, AppError> {
+    let tags = query_tags(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: tags }))
+}
+
+#[axum::debug_handler]
+pub async fn create_tag(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<TagRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Tag>>), AppError> {
+    let name = validate_name(&request.name)?;
+
+    let tag = insert_tag(&state.pool, &user_id, &name)
+        .await?
+        .ok_or(AppError::BadRequest(format!(
+            "Tag name={} already exists",
+            &name
+        )))?;
+
+    tracing::info!("Created tag id={} for user_id={}", tag.id, &user_id);
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: tag })))
+}
+
+#[axum::debug_handler]
+pub async fn edit_tag(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, tag_id)): Path<(String, i64)>,
+    Json(request): Json<TagRequest>,
+) -> Result<Json<DataResponse<Tag>>, AppError> {
+    let name = validate_name(&request.name)?;
+
+    let tag = update_tag(&state.pool, &user_id, tag_id, &name)
+        .await
+        .map_err(|err| {
+            if is_unique_violation(&err) {
+                AppError::BadRequest(format!("Tag name={} already exists", &name))
+            } else {
+                AppError::from(err)
+            }
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(DataResponse { data: tag }))
+}
+
+#[axum::debug_handler]
+pub async fn remove_tag(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, tag_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let result = delete_tag(&state.pool, &user_id, tag_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    tracing::info!("Deleted tag id={} for user_id={}", tag_id, &user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn get_budgets(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Budget>>>, AppError> {
+    let budgets = query_budgets(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: budgets }))
+}
+
+#[axum::debug_handler]
+pub async fn create_budget(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<BudgetRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Budget>>), AppError> {
+    let category = validate_name(&request.category)?;
+    let currency = normalise_currency(&request.amount.currency).ok_or(AppError::BadRequest(
+        String::from("currency should be a three letter currency code"),
+    ))?;
+    if request.amount.amount <= 0 {
+        return Err(AppError::BadRequest(String::from(
+            "amount should be more than zero",
+        )));
+    }
+    let amount = Money::new(request.amount.amount, &currency);
+
+    let budget = insert_budget(&state.pool, &user_id, &category, &amount)
+        .await?
+        .ok_or(AppError::BadRequest(format!(
+            "A budget for category={} in {} already exists",
+            &category, &currency
+        )))?;
+
+    tracing::info!("Created budget id={} for user_id={}", budget.id, &user_id);
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: budget })))
+}
+
+#[axum::debug_handler]
+pub async fn remove_budget(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, budget_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let result = delete_budget(&state.pool, &user_id, budget_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    tracing::info!("Deleted budget id={} for user_id={}", budget_id, &user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn put_transaction_category(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<TransactionCategoryRequest>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    if query_category(&state.pool, &user_id, request.category_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(format!(
+            "Unknown category_id={}",
+            request.category_id
+        )));
+    }
+
+    set_transaction_category(&state.pool, &transaction.id, request.category_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn delete_transaction_category(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    clear_transaction_category(&state.pool, &transaction.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Tags a transaction by name, creating the tag if the user does not have it yet.
+#[axum::debug_handler]
+pub async fn add_transaction_tag(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<TagRequest>,
+) -> Result<StatusCode, AppError> {
+    let name = validate_name(&request.name)?;
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    let tag_id = upsert_tag_by_name(&state.pool, &user_id, &name).await?;
+    tag_transaction(&state.pool, &transaction.id, tag_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn remove_transaction_tag(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id, tag_id)): Path<(String, String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    let result = untag_transaction(&state.pool, &transaction.id, tag_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotesRequest {
+    pub notes: String,
+}
+
+/// Edits the notes locally and pushes them to Monzo in the background. If the push fails, the
+/// notes stay pending and monzo_sync_task retries them.
+#[axum::debug_handler]
+pub async fn put_transaction_notes(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<NotesRequest>,
+) -> Result<Json<DataResponse<Transaction>>, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    update_transaction_notes(&state.pool, &transaction.id, &request.notes).await?;
+
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    if transaction.notes_pending {
+        let state = state.clone();
+        let transaction_id = transaction.id.clone();
+        tokio::spawn(async move {
+            if let Ok(Some(transaction)) = query_transaction(&state.pool, &transaction_id).await
+                && let Err(err) = push_transaction_notes(&state.pool, &transaction).await
+            {
+                tracing::error!(
+                    "Error pushing notes for transaction id={}: {}",
+                    &transaction.id,
+                    err
+                );
+            }
+        });
+    }
+
+    Ok(Json(DataResponse { data: transaction }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SplitRequest {
+    /// Either `amount` in minor units or `amount_decimal`, with the `currency` of the transaction.
+    #[serde(flatten)]
+    pub money: Money,
+    pub note: Option<String>,
+    pub category_id: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub reimbursable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SplitsRequest {
+    pub splits: Vec<SplitRequest>,
+}
+
+/// Splits can only be changed while neither the transaction nor any of its splits is in a claim.
+async fn ensure_splits_unclaimed(
+    state: &AppState,
+    transaction: &Transaction,
+) -> Result<(), AppError> {
+    if let Some(claim_id) = transaction.claim_id {
+        return Err(AppError::BadRequest(format!(
+            "Transaction id={} is part of claim id={}, so it cannot be split",
+            &transaction.id, claim_id
+        )));
+    }
+    let splits = query_splits(&state.pool, std::slice::from_ref(&transaction.id)).await?;
+    if let Some(split) = splits.iter().find(|split| split.claim_id.is_some()) {
+        return Err(AppError::BadRequest(format!(
+            "Split id={} is part of claim id={}, so the splits cannot be changed",
+            split.id,
+            split.claim_id.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+#[axum::debug_handler]
+pub async fn get_transaction_splits(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+) -> Result<Json<DataResponse<Vec<Split>>>, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    let splits = query_splits(&state.pool, &[transaction.id]).await?;
+
+    Ok(Json(DataResponse { data: splits }))
+}
+
+/// Replaces the splits of a transaction. There should be at least two, each with an amount of the
+/// same sign as the transaction, and together they should add up to the transaction's amount.
+#[axum::debug_handler]
+pub async fn put_transaction_splits(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<SplitsRequest>,
+) -> Result<Json<DataResponse<Vec<Split>>>, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    ensure_splits_unclaimed(&state, &transaction).await?;
+    ensure_not_merged(&transaction)?;
+
+    if request.splits.len() < 2 {
+        return Err(AppError::BadRequest(String::from(
+            "A transaction should be split into at least two parts",
+        )));
+    }
+    if let Some(split) = request
+        .splits
+        .iter()
+        .find(|split| split.money.currency != transaction.money.currency)
+    {
+        return Err(AppError::BadRequest(format!(
+            "Splits should be in {}, the currency of the transaction, not {}",
+            &transaction.money.currency, &split.money.currency
+        )));
+    }
+    if request.splits.iter().any(|split| {
+        split.money.is_zero() || split.money.amount.signum() != transaction.money.amount.signum()
+    }) {
+        return Err(AppError::BadRequest(String::from(
+            "Every split should have a non-zero amount of the same sign as the transaction",
+        )));
+    }
+    let total = Money::sum(
+        &transaction.money.currency,
+        request.splits.iter().map(|split| &split.money),
+    )?;
+    if total != transaction.money {
+        return Err(AppError::BadRequest(format!(
+            "Splits should add up to the transaction amount of {}",
+            transaction.money
+        )));
+    }
+
+    let mut splits = vec![];
+    for split in request.splits.iter() {
+        if let Some(category_id) = split.category_id
+            && query_category(&state.pool, &user_id, category_id)
+                .await?
+                .is_none()
+        {
+            return Err(AppError::BadRequest(format!(
+                "Unknown category_id={}",
+                category_id
+            )));
+        }
+        let mut tag_ids = vec![];
+        for tag in split.tags.iter() {
+            let name = validate_name(tag)?;
+            tag_ids.push(upsert_tag_by_name(&state.pool, &user_id, &name).await?);
+        }
+        splits.push(NewSplit {
+            money: split.money.clone(),
+            note: split.note.clone(),
+            category_id: split.category_id,
+            tag_ids,
+            reimbursable: split.reimbursable,
+        });
+    }
+
+    replace_transaction_splits(&state.pool, &transaction.id, &splits).await?;
+
+    tracing::info!(
+        "Split transaction id={} into {} parts",
+        &transaction.id,
+        splits.len()
+    );
+
+    let splits = query_splits(&state.pool, &[transaction.id]).await?;
+
+    Ok(Json(DataResponse { data: splits }))
+}
+
+#[axum::debug_handler]
+pub async fn delete_transaction_splits(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    ensure_splits_unclaimed(&state, &transaction).await?;
+
+    replace_transaction_splits(&state.pool, &transaction.id, &[]).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReimbursableRequest {
+    pub reimbursable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateClaimRequest {
+    pub title: String,
+    #[serde(default)]
+    pub transaction_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimTransactionsRequest {
+    pub transaction_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimTransitionRequest {
+    pub action: ClaimAction,
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ClaimReport {
+    pub claim: Claim,
+    pub transactions: Vec<Transaction>,
+    pub totals: Vec<CategoryTotal>,
+}
+
+/// Expands transactions that have been split into one line per split.
+async fn transaction_lines(
+    state: &AppState,
+    transactions: Vec<Transaction>,
+) -> Result<Vec<Transaction>, AppError> {
+    let transaction_ids: Vec<_> = transactions
+        .iter()
+        .filter(|transaction| transaction.is_split)
+        .map(|transaction| transaction.id.clone())
+        .collect();
+    if transaction_ids.is_empty() {
+        return Ok(transactions);
+    }
+
+    let splits = query_splits(&state.pool, &transaction_ids).await?;
+
+    Ok(split_lines(transactions, &splits)?)
+}
+
+/// The transactions and splits that make up a claim.
+async fn claim_lines(state: &AppState, claim_id: i64) -> Result<Vec<Transaction>, AppError> {
+    let transactions = query_claim_transactions(&state.pool, claim_id).await?;
+
+    Ok(transaction_lines(state, transactions)
+        .await?
+        .into_iter()
+        .filter(|line| line.claim_id == Some(claim_id))
+        .collect())
+}
+
+async fn find_claim(state: &AppState, user_id: &str, claim_id: i64) -> Result<Claim, AppError> {
+    query_claim(&state.pool, user_id, claim_id)
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error querying claim id={} for user_id={}: {:#?}",
+                claim_id,
+                user_id,
+                err
+            )
+        })?
+        .ok_or(AppError::NotFound)
+}
+
+fn ensure_draft(claim: &Claim) -> Result<(), AppError> {
+    if claim.status != ClaimStatus::Draft {
+        return Err(AppError::BadRequest(format!(
+            "Claim id={} can only be changed while it is a draft",
+            claim.id
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses a transaction that has been merged into another as its duplicate.
+fn ensure_not_merged(transaction: &Transaction) -> Result<(), AppError> {
+    match &transaction.duplicate_of {
+        Some(duplicate_of) => Err(AppError::BadRequest(format!(
+            "Transaction id={} is merged into transaction id={}",
+            &transaction.id, duplicate_of
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks that every transaction belongs to the user and is not already part of another claim.
+async fn validate_claim_transactions(
+    state: &AppState,
+    user_id: &str,
+    claim_id: Option<i64>,
+    transaction_ids: &[String],
+) -> Result<(), AppError> {
+    for transaction_id in transaction_ids.iter() {
+        let transaction = find_user_transaction(state, user_id, transaction_id)
+            .await
+            .map_err(|err| match err {
+                AppError::NotFound => {
+                    AppError::BadRequest(format!("Unknown transaction_id={}", transaction_id))
+                }
+                err => err,
+            })?;
+        if transaction.is_split {
+            return Err(AppError::BadRequest(format!(
+                "Transaction id={} is split, so its splits should be added instead",
+                transaction_id
+            )));
+        }
+        ensure_not_merged(&transaction)?;
+        if transaction.claim_id.is_some() && transaction.claim_id != claim_id {
+            return Err(AppError::BadRequest(format!(
+                "Transaction id={} is already part of claim id={}",
+                transaction_id,
+                transaction.claim_id.unwrap_or_default()
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[axum::debug_handler]
+pub async fn put_transaction_reimbursable(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<ReimbursableRequest>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    if !request.reimbursable
+        && let Some(claim_id) = transaction.claim_id
+    {
+        ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+    }
+
+    set_transaction_reimbursable(&state.pool, &transaction.id, request.reimbursable).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VatRequest {
+    pub vat_rate: Option<i32>,
+    /// Either `amount` in minor units or `amount_decimal`, with the `currency` of the transaction.
+    pub vat_amount: Option<Money>,
+    pub supplier_vat_number: Option<String>,
+}
+
+/// Records the VAT on a reimbursable transaction, replacing what was recorded before. Without a
+/// rate or amount the category's default rate applies.
+#[axum::debug_handler]
+pub async fn put_transaction_vat(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<VatRequest>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    let splits = query_splits(&state.pool, std::slice::from_ref(&transaction.id)).await?;
+    if !transaction.reimbursable && !splits.iter().any(|split| split.reimbursable) {
+        return Err(AppError::BadRequest(String::from(
+            "VAT can only be recorded on reimbursable transactions",
+        )));
+    }
+    let claim_ids = transaction
+        .claim_id
+        .into_iter()
+        .chain(splits.iter().filter_map(|split| split.claim_id));
+    for claim_id in claim_ids {
+        ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+    }
+
+    validate_vat_rate(request.vat_rate)?;
+    if let Some(vat_amount) = &request.vat_amount
+        && (vat_amount.currency != transaction.money.currency
+            || vat_amount.amount < 0
+            || vat_amount.amount > -transaction.money.amount)
+    {
+        return Err(AppError::BadRequest(String::from(
+            "vat_amount should be in the currency of the transaction, between 0 and the amount spent",
+        )));
+    }
+    let supplier_vat_number = match request.supplier_vat_number.as_deref() {
+        Some(vat_number) => Some(normalise_vat_number(vat_number).ok_or(AppError::BadRequest(
+            format!("Invalid supplier VAT number {}", vat_number),
+        ))?),
+        None => None,
+    };
+
+    set_transaction_vat(
+        &state.pool,
+        &transaction.id,
+        request.vat_rate,
+        request
+            .vat_amount
+            .as_ref()
+            .map(|vat_amount| vat_amount.amount),
+        supplier_vat_number.as_deref(),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn get_claims(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Claim>>>, AppError> {
+    let claims = query_claims(&state.pool, &user_id)
+        .await
+        .inspect_err(|err| tracing::error!("Error querying claims in get_claims: {:#?}", err))?;
+
+    Ok(Json(DataResponse { data: claims }))
+}
+
+#[axum::debug_handler]
+pub async fn create_claim(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CreateClaimRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Claim>>), AppError> {
+    let title = validate_name(&request.title)?;
+    validate_claim_transactions(&state, &user_id, None, &request.transaction_ids).await?;
+
+    let claim_id = insert_claim(&state.pool, &user_id, &title).await?;
+    add_claim_transactions(&state.pool, claim_id, &request.transaction_ids).await?;
+
+    tracing::info!("Created claim id={} for user_id={}", claim_id, &user_id);
+
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: claim })))
+}
+
+/// Returns the claim with its transactions and totals per category.
+#[axum::debug_handler]
+pub async fn get_claim(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<ClaimReport>>, AppError> {
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+    let transactions = claim_lines(&state, claim.id).await?;
+    let totals = category_totals(&transactions)?;
+
+    Ok(Json(DataResponse {
+        data: ClaimReport {
+            claim,
+            transactions,
+            totals,
+        },
+    }))
+}
+
+#[axum::debug_handler]
+pub async fn remove_claim(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+
+    delete_claim(&state.pool, &user_id, claim_id).await?;
+
+    tracing::info!("Deleted claim id={} for user_id={}", claim_id, &user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn post_claim_transactions(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+    Json(request): Json<ClaimTransactionsRequest>,
+) -> Result<Json<DataResponse<Claim>>, AppError> {
+    ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+    validate_claim_transactions(&state, &user_id, Some(claim_id), &request.transaction_ids).await?;
+
+    add_claim_transactions(&state.pool, claim_id, &request.transaction_ids).await?;
+
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+
+    Ok(Json(DataResponse { data: claim }))
+}
+
+#[axum::debug_handler]
+pub async fn delete_claim_transaction(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id, transaction_id)): Path<(String, i64, String)>,
+) -> Result<StatusCode, AppError> {
+    ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+
+    let result = remove_claim_transaction(&state.pool, claim_id, &transaction_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimSplitsRequest {
+    pub split_ids: Vec<i64>,
+}
+
+/// Adds splits of the user's transactions to a draft claim.
+#[axum::debug_handler]
+pub async fn post_claim_splits(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+    Json(request): Json<ClaimSplitsRequest>,
+) -> Result<Json<DataResponse<Claim>>, AppError> {
+    ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+
+    for split_id in request.split_ids.iter() {
+        let split = query_user_split(&state.pool, &user_id, *split_id)
+            .await?
+            .ok_or(AppError::BadRequest(format!(
+                "Unknown split_id={}",
+                split_id
+            )))?;
+        if let Some(existing) = split.claim_id
+            && existing != claim_id
+        {
+            return Err(AppError::BadRequest(format!(
+                "Split id={} is already part of claim id={}",
+                split_id, existing
+            )));
+        }
+    }
+
+    add_claim_splits(&state.pool, claim_id, &request.split_ids).await?;
+
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+
+    Ok(Json(DataResponse { data: claim }))
+}
+
+#[axum::debug_handler]
+pub async fn delete_claim_split(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id, split_id)): Path<(String, i64, i64)>,
+) -> Result<StatusCode, AppError> {
+    ensure_draft(&find_claim(&state, &user_id, claim_id).await?)?;
+
+    let result = remove_claim_split(&state.pool, claim_id, split_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn apply_claim_action(
+    state: &AppState,
+    claim: &Claim,
+    actor_id: &str,
+    request: &ClaimTransitionRequest,
+) -> Result<(), AppError> {
+    let next = claim
+        .status
+        .transition(request.action)
+        .ok_or(AppError::BadRequest(format!(
+            "Claim id={} cannot {:?} while {:?}",
+            claim.id, request.action, claim.status
+        )))?;
+
+    let comment = request
+        .comment
+        .as_deref()
+        .map(str::trim)
+        .filter(|comment| !comment.is_empty());
+
+    if request.action == ClaimAction::Submit && claim.transaction_count == 0 {
+        return Err(AppError::BadRequest(String::from(
+            "An empty claim cannot be submitted",
+        )));
+    }
+
+    if request.action == ClaimAction::Reject && comment.is_none() {
+        return Err(AppError::BadRequest(String::from(
+            "A comment is required when rejecting a claim",
+        )));
+    }
+
+    let moved = transition_claim_status(
+        &state.pool,
+        claim.id,
+        claim.status,
+        next,
+        Some(actor_id),
+        comment,
+        None,
+    )
+    .await?;
+
+    if !moved {
+        return Err(AppError::BadRequest(format!(
+            "Claim id={} was changed concurrently, please retry",
+            claim.id
+        )));
+    }
+
+    tracing::info!(
+        "Moved claim id={} from {:?} to {:?} by actor_id={}",
+        claim.id,
+        claim.status,
+        next,
+        actor_id
+    );
+
+    Ok(())
+}
+
+/// Approvers authenticate with the bearer token they were shown when granted the role, since
+/// the approver_id in the path is only who they say they are.
+async fn ensure_approver(
+    state: &AppState,
+    headers: &HeaderMap,
+    approver_id: &str,
+) -> Result<(), AppError> {
+    let token = bearer_token(headers).ok_or(AppError::Unauthorized)?;
+    if !query_has_role(&state.pool, approver_id, APPROVER_ROLE, token).await? {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(())
+}
+
+async fn find_approver_claim(
+    state: &AppState,
+    headers: &HeaderMap,
+    approver_id: &str,
+    claim_id: i64,
+) -> Result<Claim, AppError> {
+    ensure_approver(state, headers, approver_id).await?;
+
+    query_claim_by_id(&state.pool, claim_id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn ensure_admin(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    let admin_token = state.admin_token.as_ref().ok_or(AppError::NotFound)?;
+
+    if bearer_token(headers) != Some(admin_token.as_str()) {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(())
+}
+
+/// Takes one of the owner's actions (submit, withdraw, reopen) on a claim.
+#[axum::debug_handler]
+pub async fn post_claim_transition(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+    Json(request): Json<ClaimTransitionRequest>,
+) -> Result<Json<DataResponse<Claim>>, AppError> {
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+
+    if request.action.requires_approver() {
+        return Err(AppError::Forbidden(format!(
+            "Only an approver can {:?} a claim",
+            request.action
+        )));
+    }
+
+    apply_claim_action(&state, &claim, &user_id, &request).await?;
+
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+
+    Ok(Json(DataResponse { data: claim }))
+}
+
+#[axum::debug_handler]
+pub async fn get_claim_transitions(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<Vec<ClaimTransition>>>, AppError> {
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+    let transitions = query_claim_transitions(&state.pool, claim.id).await?;
+
+    Ok(Json(DataResponse { data: transitions }))
+}
+
+/// Lists submitted claims across all users, oldest first.
+#[axum::debug_handler]
+pub async fn get_pending_claims(
+    State(state): State<Arc<AppState>>,
+    Path(approver_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DataResponse<Vec<Claim>>>, AppError> {
+    ensure_approver(&state, &headers, &approver_id).await?;
+
+    let claims = query_claims_with_status(&state.pool, ClaimStatus::Submitted)
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error querying pending claims in get_pending_claims: {:#?}",
+                err
+            )
+        })?;
+
+    Ok(Json(DataResponse { data: claims }))
+}
+
+#[axum::debug_handler]
+pub async fn get_approver_claim(
+    State(state): State<Arc<AppState>>,
+    Path((approver_id, claim_id)): Path<(String, i64)>,
+    headers: HeaderMap,
+) -> Result<Json<DataResponse<ClaimReport>>, AppError> {
+    let claim = find_approver_claim(&state, &headers, &approver_id, claim_id).await?;
+    let transactions = claim_lines(&state, claim.id).await?;
+    let totals = category_totals(&transactions)?;
+
+    Ok(Json(DataResponse {
+        data: ClaimReport {
+            claim,
+            transactions,
+            totals,
+        },
+    }))
+}
+
+/// Takes one of the approver's actions (approve, reject, pay) on someone else's claim.
+#[axum::debug_handler]
+pub async fn post_approver_claim_transition(
+    State(state): State<Arc<AppState>>,
+    Path((approver_id, claim_id)): Path<(String, i64)>,
+    headers: HeaderMap,
+    Json(request): Json<ClaimTransitionRequest>,
+) -> Result<Json<DataResponse<Claim>>, AppError> {
+    let claim = find_approver_claim(&state, &headers, &approver_id, claim_id).await?;
+
+    if !request.action.requires_approver() {
+        return Err(AppError::Forbidden(format!(
+            "Only the owner can {:?} a claim",
+            request.action
+        )));
+    }
+
+    if claim.user_id == approver_id {
+        return Err(AppError::Forbidden(String::from(
+            "Approvers cannot act on their own claims",
+        )));
+    }
+
+    apply_claim_action(&state, &claim, &approver_id, &request).await?;
+
+    let claim = find_approver_claim(&state, &headers, &approver_id, claim_id).await?;
+
+    Ok(Json(DataResponse { data: claim }))
+}
+
+#[axum::debug_handler]
+pub async fn get_approvers(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<DataResponse<Vec<String>>>, AppError> {
+    ensure_admin(&state, &headers)?;
+
+    let approvers = query_users_with_role(&state.pool, APPROVER_ROLE).await?;
+
+    Ok(Json(DataResponse { data: approvers }))
+}
+
+/// A newly granted approver along with the token they act with, which is not shown again.
+#[derive(Serialize)]
+pub struct GrantedApprover {
+    pub approver_id: String,
+    pub token: String,
+}
+
+/// Grants the approver role with a new token, so granting it again is how a lost token is
+/// replaced.
+#[axum::debug_handler]
+pub async fn put_approver(
+    State(state): State<Arc<AppState>>,
+    Path(approver_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DataResponse<GrantedApprover>>, AppError> {
+    ensure_admin(&state, &headers)?;
+
+    let token = insert_role(&state.pool, &approver_id, APPROVER_ROLE).await?;
+
+    tracing::info!("Granted approver role to user_id={}", &approver_id);
+
+    Ok(Json(DataResponse {
+        data: GrantedApprover { approver_id, token },
+    }))
+}
+
+#[axum::debug_handler]
+pub async fn delete_approver(
+    State(state): State<Arc<AppState>>,
+    Path(approver_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    ensure_admin(&state, &headers)?;
+
+    let result = delete_role(&state.pool, &approver_id, APPROVER_ROLE).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    tracing::info!("Revoked approver role from user_id={}", &approver_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportParams {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    #[serde(default)]
+    pub reimbursable: bool,
+}
+
+/// Loads the receipts of the transactions and renders the report off the async runtime.
+async fn render_pdf_report(
+    state: &AppState,
+    filename: &str,
+    title: String,
+    subtitle: String,
+    transactions: Vec<Transaction>,
+) -> Result<Response, AppError> {
+    let transaction_ids: Vec<_> = transactions.iter().map(|t| t.id.clone()).collect();
+    let mut receipts = vec![];
+    for receipt in query_receipts(&state.pool, &transaction_ids).await? {
+        let Some(transaction) = transactions
+            .iter()
+            .find(|t| receipt.transaction_id.as_ref() == Some(&t.id))
+        else {
+            continue;
+        };
+        match get_blob(&state.blob_store, &receipt.storage_key).await {
+            Ok(data) => receipts.push(ReportReceipt {
+                caption: format!(
+                    "{} {}",
+                    transaction.created.format("%Y-%m-%d"),
+                    &transaction.description
+                ),
+                file_name: receipt.file_name,
+                data,
+            }),
+            Err(_) => tracing::warn!(
+                "Leaving receipt id={} out of report {}",
+                receipt.id,
+                filename
+            ),
+        }
+    }
+
+    let report = Report {
+        title,
+        subtitle,
+        totals: category_totals(&transactions)?,
+        transactions,
+        receipts,
+    };
+
+    let pdf = tokio::task::spawn_blocking(move || render_report(&report))
+        .await
+        .map_err(|err| {
+            tracing::error!("PDF report {} task failed: {:#?}", filename, err);
+            AppError::InternalServerError
+        })?
+        .map_err(|err| {
+            tracing::error!("Error rendering PDF report {}: {:#?}", filename, err);
+            AppError::InternalServerError
+        })?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, String::from("application/pdf")),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        pdf,
+    )
+        .into_response())
+}
+
+async fn render_claim_report(state: &AppState, claim: &Claim) -> Result<Response, AppError> {
+    let transactions = claim_lines(state, claim.id).await?;
+
+    let subtitle = format!(
+        "Claim #{} for {} - {:?} - created {}",
+        claim.id,
+        &claim.user_id,
+        claim.status,
+        claim.created.format("%Y-%m-%d")
+    );
+
+    render_pdf_report(
+        state,
+        &format!("claim-{}.pdf", claim.id),
+        claim.title.clone(),
+        subtitle,
+        transactions,
+    )
+    .await
+}
+
+#[axum::debug_handler]
+pub async fn get_claim_report(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, claim_id)): Path<(String, i64)>,
+) -> Result<Response, AppError> {
+    let claim = find_claim(&state, &user_id, claim_id).await?;
+
+    render_claim_report(&state, &claim).await
+}
+
+#[axum::debug_handler]
+pub async fn get_approver_claim_report(
+    State(state): State<Arc<AppState>>,
+    Path((approver_id, claim_id)): Path<(String, i64)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let claim = find_approver_claim(&state, &headers, &approver_id, claim_id).await?;
+
+    render_claim_report(&state, &claim).await
+}
+
+/// The start of the day after `to`, where a range that includes `to` ends. The last date chrono
+/// can represent has no day after it, so it is refused.
+fn end_of_day(to: NaiveDate) -> Result<DateTime<Utc>, AppError> {
+    to.checked_add_signed(Duration::days(1))
+        .map(|next| next.and_time(NaiveTime::MIN).and_utc())
+        .ok_or(AppError::BadRequest(String::from(
+            "to is too far in the future",
+        )))
+}
+
+/// Renders a report of the user's transactions between `from` and `to` inclusive, optionally
+/// limited to reimbursable ones.
+#[axum::debug_handler]
+pub async fn get_expense_report(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Query(params): Query<ReportParams>,
+) -> Result<Response, AppError> {
+    if params.from > params.to {
+        return Err(AppError::BadRequest(String::from(
+            "from should not be after to",
+        )));
+    }
+    let to = end_of_day(params.to)?;
+
+    let account_ids = query_account_ids(&state.pool, &user_id).await?;
+    let transactions = query_transactions_between(
+        &state.pool,
+        &account_ids,
+        params.from.and_time(NaiveTime::MIN).and_utc(),
+        to,
+        params.reimbursable,
+    )
+    .await
+    .inspect_err(|err| {
+        tracing::error!(
+            "Error querying transactions in get_expense_report: {:#?}",
+            err
+        )
+    })?;
+    let transactions: Vec<_> = transaction_lines(&state, transactions)
+        .await?
+        .into_iter()
+        .filter(|line| line.reimbursable || !params.reimbursable)
+        .collect();
+
+    let title = if params.reimbursable {
+        "Reimbursable expenses"
+    } else {
+        "Expenses"
+    };
+    let subtitle = format!("{} to {} for {}", params.from, params.to, &user_id);
+
+    render_pdf_report(
+        &state,
+        &format!("expenses-{}-{}.pdf", params.from, params.to),
+        String::from(title),
+        subtitle,
+        transactions,
+    )
+    .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VatReportParams {
+    pub year: i32,
+    pub quarter: u32,
+}
+
+/// Totals the VAT on the user's reimbursable transactions in a calendar quarter per currency and
+/// rate.
+#[axum::debug_handler]
+pub async fn get_vat_report(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Query(params): Query<VatReportParams>,
+) -> Result<Json<DataResponse<VatReport>>, AppError> {
+    let (from, to) = quarter_dates(params.year, params.quarter).ok_or(AppError::BadRequest(
+        String::from("quarter should be between 1 and 4"),
+    ))?;
+
+    let account_ids = query_account_ids(&state.pool, &user_id).await?;
+    let transactions = query_transactions_between(
+        &state.pool,
+        &account_ids,
+        from.and_time(NaiveTime::MIN).and_utc(),
+        to.and_time(NaiveTime::MIN).and_utc(),
+        true,
+    )
+    .await
+    .inspect_err(|err| {
+        tracing::error!("Error querying transactions in get_vat_report: {:#?}", err)
+    })?;
+    let transactions: Vec<_> = transaction_lines(&state, transactions)
+        .await?
+        .into_iter()
+        .filter(|line| line.reimbursable)
+        .collect();
+
+    let report = vat_report(params.year, params.quarter, &transactions)?
+        .ok_or(AppError::InternalServerError)?;
+
+    Ok(Json(DataResponse { data: report }))
+}
+
+pub const MAX_RECEIPT_SIZE: usize = 10 * 1024 * 1024;
+/// The full ECB history file is a few megabytes.
+pub const MAX_FX_RATES_SIZE: usize = 20 * 1024 * 1024;
+
+/// Works out the type of an uploaded receipt from its contents rather than trusting the client.
+fn sniff_receipt_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(&[0x89, b'P', b'N', b'G']) {
+        Some("image/png")
+    } else if data.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if data.len() > 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if data.len() > 12
+        && &data[4..8] == b"ftyp"
+        && matches!(&data[8..12], b"heic" | b"heix" | b"mif1")
+    {
+        Some("image/heic")
+    } else {
+        None
+    }
+}
+
+struct ReceiptUpload {
+    file_name: String,
+    data: Bytes,
+    content_type: &'static str,
+    amount: Option<Money>,
+    receipt_date: Option<NaiveDate>,
+    merchant: Option<String>,
+}
+
+fn multipart_error(err: MultipartError) -> AppError {
+    AppError::BadRequest(format!("Invalid multipart body: {}", err))
+}
+
+/// Reads a multipart upload with the receipt in a `file` field and, optionally, the `amount` paid
+/// in minor units of its `currency`, the `date` (YYYY-MM-DD) and the `merchant` to match it on.
+async fn read_receipt_upload(mut multipart: Multipart) -> Result<ReceiptUpload, AppError> {
+    let mut file: Option<(String, Bytes)> = None;
+    let mut amount = None;
+    let mut currency = None;
+    let mut receipt_date = None;
+    let mut merchant = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name() {
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or("receipt").to_string();
+                let data = field.bytes().await.map_err(multipart_error)?;
+                file = Some((file_name, data));
+            }
+            Some("amount") => {
+                let text = field.text().await.map_err(multipart_error)?;
+                amount = Some(text.trim().parse::<i64>().map_err(|_err| {
+                    AppError::BadRequest(String::from(
+                        "amount should be a whole number of minor units",
+                    ))
+                })?);
+            }
+            Some("currency") => {
+                let text = field.text().await.map_err(multipart_error)?;
+                currency = Some(normalise_currency(&text).ok_or(AppError::BadRequest(
+                    String::from("currency should be a three letter currency code"),
+                ))?);
+            }
+            Some("date") => {
+                let text = field.text().await.map_err(multipart_error)?;
+                receipt_date = Some(NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(
+                    |_err| {
+                        AppError::BadRequest(String::from("date should be formatted YYYY-MM-DD"))
+                    },
+                )?);
+            }
+            Some("merchant") => {
+                let text = field.text().await.map_err(multipart_error)?;
+                merchant = Some(text.trim().to_string()).filter(|text| !text.is_empty());
+            }
+            _ => continue,
+        }
+    }
+
+    let (file_name, data) = file.ok_or(AppError::BadRequest(String::from(
+        "Request body should have a 'file' field",
+    )))?;
+
+    let content_type = sniff_receipt_content_type(&data).ok_or(AppError::BadRequest(
+        String::from("Receipts should be a PDF or a JPEG, PNG, GIF, WebP or HEIC image"),
+    ))?;
+
+    let amount = match (amount, currency) {
+        (Some(amount), _) if amount <= 0 => {
+            return Err(AppError::BadRequest(String::from(
+                "amount should be more than zero",
+            )));
+        }
+        (Some(amount), Some(currency)) => Some(Money::new(amount, &currency)),
+        (None, None) => None,
+        _ => {
+            return Err(AppError::BadRequest(String::from(
+                "amount and currency should be given together",
+            )));
+        }
+    };
+
+    Ok(ReceiptUpload {
+        file_name,
+        data,
+        content_type,
+        amount,
+        receipt_date,
+        merchant,
+    })
+}
+
+/// Pushes a receipt that has just been matched to a transaction to Monzo in the background. If the
+/// push fails, monzo_sync_task retries it.
+fn spawn_receipt_push(state: &Arc<AppState>, receipt: &Receipt) {
+    let state = state.clone();
+    let user_id = receipt.user_id.clone();
+    let receipt_id = receipt.id;
+    tokio::spawn(async move {
+        if let Ok(Some(receipt)) = query_user_receipt(&state.pool, &user_id, receipt_id).await
+            && let Err(err) = push_receipt(&state.pool, &state.blob_store, &receipt).await
+        {
+            tracing::error!("Error pushing receipt id={}: {}", receipt.id, err);
+        }
+    });
+}
+
+async fn store_receipt(
+    state: &Arc<AppState>,
+    user_id: &str,
+    transaction_id: Option<&str>,
+    upload: ReceiptUpload,
+) -> Result<Receipt, AppError> {
+    let storage_key = format!(
+        "receipts/{}/{}",
+        transaction_id.unwrap_or(&format!("unmatched/{}", user_id)),
+        Utc::now().timestamp_micros()
+    );
+
+    put_blob(&state.blob_store, &storage_key, upload.data.clone())
+        .await
+        .map_err(|_err| AppError::InternalServerError)?;
+
+    let new_receipt = NewReceipt {
+        user_id: user_id.to_string(),
+        transaction_id: transaction_id.map(String::from),
+        file_name: upload.file_name,
+        content_type: upload.content_type.to_string(),
+        size: upload.data.len() as i64,
+        storage_key: storage_key.clone(),
+        amount: upload.amount,
+        receipt_date: upload.receipt_date,
+        merchant: upload.merchant,
+    };
+
+    let receipt = match insert_receipt(&state.pool, &new_receipt).await {
+        Ok(receipt) => receipt,
+        Err(err) => {
+            let _ = delete_blob(&state.blob_store, &storage_key).await;
+            return Err(err.into());
+        }
+    };
+
+    tracing::info!(
+        "Stored receipt id={} for user_id={} transaction id={:?}",
+        receipt.id,
+        user_id,
+        transaction_id
+    );
+
+    if receipt.transaction_id.is_some() {
+        spawn_receipt_push(state, &receipt);
+    }
+
+    Ok(receipt)
+}
+
+async fn delete_stored_receipt(state: &AppState, receipt: &Receipt) -> Result<(), AppError> {
+    // Deleting the attachment first means a failure leaves both copies in place rather than an
+    // attachment in Monzo that nothing here knows about.
+    remove_receipt_from_monzo(&state.pool, receipt)
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "Error removing receipt id={} from Monzo: {}",
+                receipt.id,
+                err
+            );
+            AppError::InternalServerError
+        })?;
+
+    delete_receipt(&state.pool, receipt.id).await?;
+
+    // The row is what makes the receipt visible, so a blob left behind is only wasted space.
+    let _ = delete_blob(&state.blob_store, &receipt.storage_key).await;
+
+    tracing::info!(
+        "Deleted receipt id={} for user_id={}",
+        receipt.id,
+        &receipt.user_id
+    );
+
+    Ok(())
+}
+
+fn receipt_response(receipt: Receipt, data: Bytes) -> Response {
+    (
+        [
+            (CONTENT_TYPE, receipt.content_type),
+            (
+                CONTENT_DISPOSITION,
+                format!(
+                    "inline; filename=\"{}\"",
+                    receipt.file_name.replace('"', "")
+                ),
+            ),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+#[axum::debug_handler]
+pub async fn upload_receipt(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<DataResponse<Receipt>>), AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    let upload = read_receipt_upload(multipart).await?;
+    let receipt = store_receipt(&state, &user_id, Some(&transaction.id), upload).await?;
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: receipt })))
+}
+
+#[axum::debug_handler]
+pub async fn get_receipts(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+) -> Result<Json<DataResponse<Vec<Receipt>>>, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    let receipts = query_receipts(&state.pool, &[transaction.id]).await?;
+
+    Ok(Json(DataResponse { data: receipts }))
+}
+
+#[axum::debug_handler]
+pub async fn download_receipt(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id, receipt_id)): Path<(String, String, i64)>,
+) -> Result<Response, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    let receipt = query_receipt(&state.pool, &transaction.id, receipt_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let data = get_blob(&state.blob_store, &receipt.storage_key)
+        .await
+        .map_err(|_err| AppError::InternalServerError)?;
+
+    Ok(receipt_response(receipt, data))
+}
+
+#[axum::debug_handler]
+pub async fn remove_receipt(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id, receipt_id)): Path<(String, String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    let receipt = query_receipt(&state.pool, &transaction.id, receipt_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    delete_stored_receipt(&state, &receipt).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn find_user_receipt(
+    state: &AppState,
+    user_id: &str,
+    receipt_id: i64,
+) -> Result<Receipt, AppError> {
+    query_user_receipt(&state.pool, user_id, receipt_id)
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error querying receipt id={} for user_id={}: {:#?}",
+                receipt_id,
+                user_id,
+                err
+            )
+        })?
+        .ok_or(AppError::NotFound)
+}
+
+/// Uploads a receipt that is not matched to a transaction yet, e.g. one that was emailed in. Takes
+/// the same multipart body as upload_receipt.
+#[axum::debug_handler]
+pub async fn upload_user_receipt(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<DataResponse<Receipt>>), AppError> {
+    if query_account_ids(&state.pool, &user_id).await?.is_empty() {
+        return Err(AppError::NotFound);
+    }
+
+    let upload = read_receipt_upload(multipart).await?;
+    let receipt = store_receipt(&state, &user_id, None, upload).await?;
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: receipt })))
+}
+
+#[axum::debug_handler]
+pub async fn get_unmatched_receipts(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Receipt>>>, AppError> {
+    let receipts = query_unmatched_receipts(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: receipts }))
+}
+
+#[axum::debug_handler]
+pub async fn download_user_receipt(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, receipt_id)): Path<(String, i64)>,
+) -> Result<Response, AppError> {
+    let receipt = find_user_receipt(&state, &user_id, receipt_id).await?;
+
+    let data = get_blob(&state.blob_store, &receipt.storage_key)
+        .await
+        .map_err(|_err| AppError::InternalServerError)?;
+
+    Ok(receipt_response(receipt, data))
+}
+
+#[axum::debug_handler]
+pub async fn remove_user_receipt(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, receipt_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let receipt = find_user_receipt(&state, &user_id, receipt_id).await?;
+
+    delete_stored_receipt(&state, &receipt).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Ranks the user's transactions around the receipt's date (or its upload date) as matches for an
+/// unmatched receipt, best first.
+#[axum::debug_handler]
+pub async fn get_receipt_suggestions(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, receipt_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<Vec<MatchSuggestion>>>, AppError> {
+    let receipt = find_user_receipt(&state, &user_id, receipt_id).await?;
+    if receipt.transaction_id.is_some() {
+        return Err(AppError::BadRequest(String::from(
+            "Receipt is already matched to a transaction",
+        )));
+    }
+
+    let receipt_date = receipt.receipt_date.unwrap_or(receipt.created.date_naive());
+    let account_ids = query_account_ids(&state.pool, &user_id).await?;
+    let transactions = query_transactions_between(
+        &state.pool,
+        &account_ids,
+        (receipt_date - Duration::days(DATE_WINDOW_DAYS))
+            .and_time(NaiveTime::MIN)
+            .and_utc(),
+        (receipt_date + Duration::days(DATE_WINDOW_DAYS + 1))
+            .and_time(NaiveTime::MIN)
+            .and_utc(),
+        false,
+    )
+    .await
+    .inspect_err(|err| {
+        tracing::error!(
+            "Error querying transactions in get_receipt_suggestions: {:#?}",
+            err
+        )
+    })?;
+
+    Ok(Json(DataResponse {
+        data: rank_candidates(&receipt, receipt_date, transactions),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MatchReceiptRequest {
+    pub transaction_id: String,
+}
+
+/// Confirms a suggestion, matching the receipt to the transaction and pushing it to Monzo.
+#[axum::debug_handler]
+pub async fn put_receipt_transaction(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, receipt_id)): Path<(String, i64)>,
+    Json(request): Json<MatchReceiptRequest>,
+) -> Result<Json<DataResponse<Receipt>>, AppError> {
+    find_user_receipt(&state, &user_id, receipt_id).await?;
+    let transaction = find_user_transaction(&state, &user_id, &request.transaction_id).await?;
+
+    let receipt = match_receipt(&state.pool, receipt_id, &transaction.id)
+        .await?
+        .ok_or(AppError::BadRequest(String::from(
+            "Receipt is already matched to a transaction",
+        )))?;
+
+    tracing::info!(
+        "Matched receipt id={} to transaction id={}",
+        receipt.id,
+        &transaction.id
+    );
+
+    spawn_receipt_push(&state, &receipt);
+
+    Ok(Json(DataResponse { data: receipt }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGroupRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupMemberRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareTransactionRequest {
+    pub group_id: i64,
+    /// Defaults to an equal share for every member of the group.
+    pub shares: Option<Vec<Share>>,
+}
+
+#[derive(Serialize)]
+pub struct SharedTransaction {
+    pub transaction_id: String,
+    pub group_id: i64,
+    pub shares: Vec<Share>,
+}
+
+#[derive(Serialize)]
+pub struct GroupBalances {
+    pub group_id: i64,
+    pub balances: Vec<Balance>,
+    /// The fewest payments that would settle everyone up.
+    pub debts: Vec<Debt>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSettlementRequest {
+    pub to_user_id: String,
+    /// Either `amount` in minor units or `amount_decimal`, with the `currency`.
+    #[serde(flatten)]
+    pub money: Money,
+}
+
+async fn find_user_group(
+    state: &AppState,
+    user_id: &str,
+    group_id: i64,
+) -> Result<Group, AppError> {
+    query_user_group(&state.pool, user_id, group_id)
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error querying group id={} for user_id={}: {:#?}",
+                group_id,
+                user_id,
+                err
+            )
+        })?
+        .ok_or(AppError::NotFound)
+}
+
+async fn find_group_balances(
+    state: &AppState,
+    group: &Group,
+) -> Result<(Vec<Balance>, Vec<Debt>), AppError> {
+    group_balances(&state.pool, group).await.map_err(|err| {
+        tracing::error!(
+            "Error working out balances for group id={}: {}",
+            group.id,
+            err
+        );
+        AppError::InternalServerError
+    })
+}
+
+#[axum::debug_handler]
+pub async fn get_groups(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Group>>>, AppError> {
+    let groups = query_user_groups(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: groups }))
+}
+
+#[axum::debug_handler]
+pub async fn create_group(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CreateGroupRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Group>>), AppError> {
+    let name = validate_name(&request.name)?;
+
+    let group_id = insert_group(&state.pool, &user_id, &name).await?;
+    let group = find_user_group(&state, &user_id, group_id).await?;
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: group })))
+}
+
+/// Any member can add someone else to the group.
+#[axum::debug_handler]
+pub async fn add_group_member(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, group_id)): Path<(String, i64)>,
+    Json(request): Json<GroupMemberRequest>,
+) -> Result<Json<DataResponse<Group>>, AppError> {
+    let group = find_user_group(&state, &user_id, group_id).await?;
+    let member_id = validate_name(&request.user_id)?;
+
+    insert_group_member(&state.pool, group.id, &member_id).await?;
+    let group = find_user_group(&state, &user_id, group.id).await?;
+
+    Ok(Json(DataResponse { data: group }))
+}
+
+/// Removes a member, as long as they are settled up. Otherwise whatever they owe, or are owed,
+/// would be left with nobody to settle it.
+#[axum::debug_handler]
+pub async fn remove_group_member(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, group_id, member_id)): Path<(String, i64, String)>,
+) -> Result<StatusCode, AppError> {
+    let group = find_user_group(&state, &user_id, group_id).await?;
+    if !group.members.contains(&member_id) {
+        return Err(AppError::NotFound);
+    }
+
+    let (balances, _) = find_group_balances(&state, &group).await?;
+    if let Some(balance) = balances
+        .iter()
+        .find(|balance| balance.user_id == member_id && !balance.balance.is_zero())
+    {
+        return Err(AppError::BadRequest(format!(
+            "user_id={} has a balance of {} in this group, so they should settle up first",
+            &member_id, balance.balance
+        )));
+    }
+
+    delete_group_member(&state.pool, group.id, &member_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Shares a transaction with one of the user's groups. The user paid for it, and each member in
+/// `shares` owes their part of it in proportion to their weight.
+#[axum::debug_handler]
+pub async fn put_transaction_share(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+    Json(request): Json<ShareTransactionRequest>,
+) -> Result<Json<DataResponse<SharedTransaction>>, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+    let group = find_user_group(&state, &user_id, request.group_id).await?;
+
+    let shares = request.shares.unwrap_or_else(|| {
+        group
+            .members
+            .iter()
+            .map(|member| Share {
+                user_id: member.clone(),
+                weight: 1,
+            })
+            .collect()
+    });
+    if shares.is_empty() {
+        return Err(AppError::BadRequest(String::from(
+            "A transaction should be shared with at least one member",
+        )));
+    }
+    if shares.iter().any(|share| share.weight <= 0) {
+        return Err(AppError::BadRequest(String::from(
+            "Every share should have a positive weight",
+        )));
+    }
+    if let Some(share) = shares
+        .iter()
+        .find(|share| !group.members.contains(&share.user_id))
+    {
+        return Err(AppError::BadRequest(format!(
+            "user_id={} is not a member of group id={}",
+            &share.user_id, group.id
+        )));
+    }
+    if shares.iter().enumerate().any(|(i, share)| {
+        shares[..i]
+            .iter()
+            .any(|other| other.user_id == share.user_id)
+    }) {
+        return Err(AppError::BadRequest(String::from(
+            "Each member should only have one share",
+        )));
+    }
+
+    share_transaction(&state.pool, &transaction.id, group.id, &shares).await?;
+
+    tracing::info!(
+        "Shared transaction id={} with group id={} between {} members",
+        &transaction.id,
+        group.id,
+        shares.len()
+    );
+
+    let shares = query_transaction_shares(&state.pool, &transaction.id).await?;
+
+    Ok(Json(DataResponse {
+        data: SharedTransaction {
+            transaction_id: transaction.id,
+            group_id: group.id,
+            shares,
+        },
+    }))
+}
+
+#[axum::debug_handler]
+pub async fn delete_transaction_share(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, transaction_id)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    let transaction = find_user_transaction(&state, &user_id, &transaction_id).await?;
+
+    let result = unshare_transaction(&state.pool, &transaction.id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+pub async fn get_group_balances(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, group_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<GroupBalances>>, AppError> {
+    let group = find_user_group(&state, &user_id, group_id).await?;
+
+    let (balances, debts) = find_group_balances(&state, &group).await?;
+
+    Ok(Json(DataResponse {
+        data: GroupBalances {
+            group_id: group.id,
+            balances,
+            debts,
+        },
+    }))
+}
+
+#[axum::debug_handler]
+pub async fn get_settlements(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, group_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<Vec<Settlement>>>, AppError> {
+    let group = find_user_group(&state, &user_id, group_id).await?;
+
+    let settlements = query_settlements(&state.pool, group.id).await?;
+
+    Ok(Json(DataResponse { data: settlements }))
+}
+
+/// Records a payment from the user to another member made some other way than between Monzo
+/// accounts, e.g. in cash. Payments between Monzo users are recorded as they arrive.
+#[axum::debug_handler]
+pub async fn create_settlement(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, group_id)): Path<(String, i64)>,
+    Json(request): Json<CreateSettlementRequest>,
+) -> Result<(StatusCode, Json<DataResponse<Settlement>>), AppError> {
+    let group = find_user_group(&state, &user_id, group_id).await?;
+
+    if request.to_user_id == user_id || !group.members.contains(&request.to_user_id) {
+        return Err(AppError::BadRequest(format!(
+            "user_id={} is not another member of group id={}",
+            &request.to_user_id, group.id
+        )));
+    }
+    if request.money.amount <= 0 {
+        return Err(AppError::BadRequest(String::from(
+            "Amount should be positive",
+        )));
+    }
+
+    let settlement = insert_settlement(
+        &state.pool,
+        &Settlement {
+            id: 0,
+            group_id: group.id,
+            from_user_id: user_id,
+            to_user_id: request.to_user_id,
+            money: request.money,
+            payer_transaction_id: None,
+            payee_transaction_id: None,
+            created: Utc::now(),
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(DataResponse { data: settlement })))
+}
+
+#[axum::debug_handler]
+pub async fn get_subscriptions(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Subscription>>>, AppError> {
+    let subscriptions = query_subscriptions(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse {
+        data: subscriptions,
+    }))
+}
+
+/// Detects subscriptions straight away rather than waiting for subscription_detection_task.
+#[axum::debug_handler]
+pub async fn redetect_subscriptions(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<Subscription>>>, AppError> {
+    let subscriptions = detect_user_subscriptions(&state.pool, &user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "Error detecting subscriptions for user_id={}: {}",
+                &user_id,
+                err
+            );
+            AppError::InternalServerError
+        })?;
+
+    Ok(Json(DataResponse {
+        data: subscriptions,
+    }))
+}
+
+async fn find_user_account(
+    state: &AppState,
+    user_id: &str,
+    account_id: &str,
+) -> Result<Account, AppError> {
+    query_account(&state.pool, account_id)
+        .await?
+        .filter(|account| account.user_id == user_id)
+        .ok_or(AppError::NotFound)
+}
+
+/// Projects the account's balance 30, 60 and 90 days ahead from the recurring payments detected
+/// by subscription_detection_task.
+#[axum::debug_handler]
+pub async fn get_account_forecast(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, account_id)): Path<(String, String)>,
+) -> Result<Json<DataResponse<Forecast>>, AppError> {
+    let account = find_user_account(&state, &user_id, &account_id).await?;
+
+    let forecast = forecast_account(&state.pool, &account)
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "Error forecasting balance for account_id={}: {}",
+                &account.id,
+                err
+            );
+            AppError::InternalServerError
+        })?;
+
+    Ok(Json(DataResponse { data: forecast }))
+}
+
+/// How far back the balance history goes when no `from` is given.
+const BALANCE_HISTORY_DAYS: i64 = 90;
+
+#[derive(Debug, Deserialize)]
+pub struct BalanceHistoryParams {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Turns the dates asked for into the start of `from` and the end of `to`.
+fn balance_history_range(
+    params: &BalanceHistoryParams,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), AppError> {
+    let to = params.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = match params.from {
+        Some(from) => from,
+        None => to
+            .checked_sub_signed(Duration::days(BALANCE_HISTORY_DAYS))
+            .ok_or(AppError::BadRequest(String::from(
+                "to is too far in the past",
+            )))?,
+    };
+    if from > to {
+        return Err(AppError::BadRequest(String::from(
+            "from should not be after to",
+        )));
+    }
+
+    Ok((from.and_time(NaiveTime::MIN).and_utc(), end_of_day(to)?))
+}
+
+/// The balances recorded for an account by account_poll_task, oldest first, for charting.
+/// Defaults to the last 90 days.
+#[axum::debug_handler]
+pub async fn get_balance_history(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, account_id)): Path<(String, String)>,
+    Query(params): Query<BalanceHistoryParams>,
+) -> Result<Json<DataResponse<Vec<BalanceSnapshot>>>, AppError> {
+    let account = find_user_account(&state, &user_id, &account_id).await?;
+    let (from, to) = balance_history_range(&params)?;
+
+    let snapshots = query_balance_snapshots(&state.pool, &account.id, from, to).await?;
+
+    Ok(Json(DataResponse { data: snapshots }))
+}
+
+#[derive(Serialize)]
+pub struct AccountOverview {
+    #[serde(flatten)]
+    pub account: Account,
+    /// The balance when the account was last polled.
+    pub balance: Option<BalanceSnapshot>,
+    pub pots: Vec<Pot>,
+}
+
+/// Lists the user's accounts with their latest balance and their pots.
+#[axum::debug_handler]
+pub async fn get_accounts(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<AccountOverview>>>, AppError> {
+    let accounts = query_accounts(&state.pool, &user_id).await?;
+    let account_ids: Vec<String> = accounts.iter().map(|account| account.id.clone()).collect();
+    let mut balances = query_latest_balance_snapshots(&state.pool, &account_ids).await?;
+    let mut pots = query_pots(&state.pool, &account_ids).await?;
+
+    let overviews = accounts
+        .into_iter()
+        .map(|account| {
+            let balance = balances
+                .iter()
+                .position(|balance| balance.account_id == account.id)
+                .map(|index| balances.swap_remove(index));
+            let (account_pots, other_pots) =
+                pots.drain(..).partition(|pot| pot.account_id == account.id);
+            pots = other_pots;
+            AccountOverview {
+                account,
+                balance,
+                pots: account_pots,
+            }
+        })
+        .collect();
+
+    Ok(Json(DataResponse { data: overviews }))
+}
+
+/// The balances recorded for a pot by account_poll_task, oldest first. Defaults to the last 90
+/// days.
+#[axum::debug_handler]
+pub async fn get_pot_balance_history(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, pot_id)): Path<(String, String)>,
+    Query(params): Query<BalanceHistoryParams>,
+) -> Result<Json<DataResponse<Vec<PotBalanceSnapshot>>>, AppError> {
+    let pot = query_user_pot(&state.pool, &user_id, &pot_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let (from, to) = balance_history_range(&params)?;
+
+    let snapshots = query_pot_balance_snapshots(&state.pool, &pot.id, from, to).await?;
+
+    Ok(Json(DataResponse { data: snapshots }))
+}
+
+#[derive(Serialize)]
+pub struct FxImportResponse {
+    pub rates: usize,
+    pub currencies: usize,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Converts the transactions of the given users in the background, which can take a while for
+/// users with a long history.
+fn spawn_conversions(state: &Arc<AppState>, user_ids: Vec<String>) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        for user_id in user_ids.iter() {
+            if let Err(err) = convert_user_transactions(&state.pool, user_id).await {
+                tracing::error!(
+                    "Error converting transactions for user_id={}: {}",
+                    user_id,
+                    err
+                );
+            }
+        }
+    });
+}
+
+#[axum::debug_handler]
+pub async fn get_settings(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<UserSettings>>, AppError> {
+    let settings = query_user_settings(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: settings }))
+}
+
+/// Changing the reporting currency converts all of the user's transactions again.
+#[axum::debug_handler]
+pub async fn put_settings(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<UserSettings>,
+) -> Result<Json<DataResponse<UserSettings>>, AppError> {
+    let reporting_currency =
+        normalise_currency(&request.reporting_currency).ok_or(AppError::BadRequest(
+            String::from("reporting_currency should be a three letter currency code"),
+        ))?;
+    let odd_hours_timezone = request
+        .odd_hours_timezone
+        .parse::<Tz>()
+        .map_err(|_| {
+            AppError::BadRequest(String::from(
+                "odd_hours_timezone should be an IANA timezone name, such as Europe/London",
+            ))
+        })?
+        .name()
+        .to_string();
+
+    upsert_user_settings(
+        &state.pool,
+        &UserSettings {
+            user_id: user_id.clone(),
+            reporting_currency,
+            odd_hours_timezone,
+        },
+    )
+    .await?;
+
+    spawn_conversions(&state, vec![user_id.clone()]);
+
+    let settings = query_user_settings(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: settings }))
+}
+
+/// How many webhooks the Monzo webhook admin endpoint shows.
+const MAX_MONZO_WEBHOOKS: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct MonzoWebhooksParams {
+    pub status: Option<MonzoWebhookStatus>,
+}
+
+/// Lists the most recent Monzo webhooks with a status, newest first. Without one it lists those
+/// that were given up on, with the error from their last attempt.
+#[axum::debug_handler]
+pub async fn get_monzo_webhooks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<MonzoWebhooksParams>,
+) -> Result<Json<DataResponse<Vec<MonzoWebhook>>>, AppError> {
+    ensure_admin(&state, &headers)?;
+
+    let webhooks = query_monzo_webhooks(
+        &state.pool,
+        params.status.unwrap_or(MonzoWebhookStatus::Failed),
+        MAX_MONZO_WEBHOOKS,
+    )
+    .await?;
+
+    Ok(Json(DataResponse { data: webhooks }))
+}
+
+/// Runs Monzo webhooks through processing again, from the inbox or as given, and reports what
+/// each changed about its transaction. With `dry_run` nothing is changed.
+#[axum::debug_handler]
+pub async fn replay_webhooks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<ReplayRequest>,
+) -> Result<Json<DataResponse<Vec<WebhookReplay>>>, AppError> {
+    ensure_admin(&state, &headers)?;
+
+    let replays = replay_monzo_webhooks(
+        &state.pool,
+        &state.blob_store,
+        state.refetch_monzo_callbacks,
+        &request,
+    )
+    .await
+    .map_err(|err| {
+        tracing::error!("Error replaying Monzo webhooks: {}", err);
+        AppError::InternalServerError
+    })?;
+
+    Ok(Json(DataResponse { data: replays }))
+}
+
+/// Imports reference rates from a file in the ECB's CSV format, e.g. eurofxref-hist.csv, and then
+/// converts any transactions that were waiting for them.
+#[axum::debug_handler]
+pub async fn post_fx_rates(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<DataResponse<FxImportResponse>>, AppError> {
+    ensure_admin(&state, &headers)?;
+
+    let text = std::str::from_utf8(&body)
+        .map_err(|_err| AppError::BadRequest(String::from("Rates should be UTF-8 text")))?;
+    let rates = parse_ecb_csv(text).map_err(AppError::BadRequest)?;
+
+    upsert_fx_rates(&state.pool, &rates).await?;
+
+    let mut currencies: Vec<&String> = rates.iter().map(|rate| &rate.currency).collect();
+    currencies.sort();
+    currencies.dedup();
+    let response = FxImportResponse {
+        rates: rates.len(),
+        currencies: currencies.len(),
+        from: rates.iter().map(|rate| rate.date).min(),
+        to: rates.iter().map(|rate| rate.date).max(),
+    };
+
+    tracing::info!(
+        "Imported {} rates for {} currencies",
+        response.rates,
+        response.currencies
+    );
+
+    let user_ids = query_all_tokens(&state.pool)
+        .await?
+        .into_iter()
+        .map(|token| token.user_id)
+        .collect();
+    spawn_conversions(&state, user_ids);
+
+    Ok(Json(DataResponse { data: response }))
+}
+
+#[derive(Deserialize)]
+pub struct DuplicatesParams {
+    pub status: Option<DuplicateStatus>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateDetails {
+    #[serde(flatten)]
+    pub duplicate: Duplicate,
+    pub transaction: Option<Transaction>,
+    pub duplicate_transaction: Option<Transaction>,
+}
+
+async fn duplicate_details(
+    state: &AppState,
+    duplicates: Vec<Duplicate>,
+) -> Result<Vec<DuplicateDetails>, AppError> {
+    let transaction_ids: Vec<String> = duplicates
+        .iter()
+        .flat_map(|duplicate| {
+            [
+                duplicate.transaction_id.clone(),
+                duplicate.duplicate_id.clone(),
+            ]
+        })
+        .collect();
+    let transactions = query_transactions_by_ids(&state.pool, &transaction_ids).await?;
+    let find = |id: &str| {
+        transactions
+            .iter()
+            .find(|transaction| transaction.id == id)
+            .cloned()
+    };
+
+    Ok(duplicates
+        .into_iter()
+        .map(|duplicate| DuplicateDetails {
+            transaction: find(&duplicate.transaction_id),
+            duplicate_transaction: find(&duplicate.duplicate_id),
+            duplicate,
+        })
+        .collect())
+}
+
+/// Lists the pairs of the user's transactions flagged as probable duplicates, optionally only
+/// those with the given status.
+#[axum::debug_handler]
+pub async fn get_duplicates(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Query(params): Query<DuplicatesParams>,
+) -> Result<Json<DataResponse<Vec<DuplicateDetails>>>, AppError> {
+    let duplicates = query_user_duplicates(&state.pool, &user_id, params.status).await?;
+
+    Ok(Json(DataResponse {
+        data: duplicate_details(&state, duplicates).await?,
+    }))
+}
+
+/// Looks for duplicates straight away rather than waiting for the next poll, and lists the ones
+/// still waiting for the user.
+#[axum::debug_handler]
+pub async fn redetect_duplicates(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<DuplicateDetails>>>, AppError> {
+    detect_user_duplicates(&state.pool, &user_id)
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "Error detecting duplicates for user_id={}: {}",
+                &user_id,
+                err
+            );
+            AppError::InternalServerError
+        })?;
+
+    let duplicates =
+        query_user_duplicates(&state.pool, &user_id, Some(DuplicateStatus::Pending)).await?;
+
+    Ok(Json(DataResponse {
+        data: duplicate_details(&state, duplicates).await?,
+    }))
+}
+
+async fn find_user_duplicate(
+    state: &AppState,
+    user_id: &str,
+    duplicate_id: i64,
+) -> Result<Duplicate, AppError> {
+    query_user_duplicate(&state.pool, user_id, duplicate_id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+#[derive(Deserialize)]
+pub struct MergeDuplicateRequest {
+    /// Which of the two transactions to keep, the flagged `transaction_id` if not given.
+    pub keep: Option<String>,
+}
+
+/// Merges a pair of duplicates, which leaves the one not kept out of summaries. The one not kept
+/// cannot be on a claim or split, since it would still count towards claims.
+#[axum::debug_handler]
+pub async fn merge_transaction_duplicate(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, duplicate_id)): Path<(String, i64)>,
+    request: Option<Json<MergeDuplicateRequest>>,
+) -> Result<Json<DataResponse<Duplicate>>, AppError> {
+    let duplicate = find_user_duplicate(&state, &user_id, duplicate_id).await?;
+
+    let keep = request.and_then(|Json(request)| request.keep);
+    let (kept, merged) = match keep.as_deref() {
+        None => (&duplicate.transaction_id, &duplicate.duplicate_id),
+        Some(id) if id == duplicate.transaction_id => {
+            (&duplicate.transaction_id, &duplicate.duplicate_id)
+        }
+        Some(id) if id == duplicate.duplicate_id => {
+            (&duplicate.duplicate_id, &duplicate.transaction_id)
+        }
+        Some(id) => {
+            return Err(AppError::BadRequest(format!(
+                "Transaction id={} is not one of this pair",
+                id
+            )));
+        }
+    };
+
+    let transaction = find_user_transaction(&state, &user_id, merged).await?;
+    if let Some(claim_id) = transaction.claim_id {
+        return Err(AppError::BadRequest(format!(
+            "Transaction id={} is part of claim id={}, so it cannot be merged away",
+            merged, claim_id
+        )));
+    }
+    if transaction.is_split {
+        return Err(AppError::BadRequest(format!(
+            "Transaction id={} is split, so it cannot be merged away",
+            merged
+        )));
+    }
+
+    let duplicate = merge_duplicate(&state.pool, duplicate.id, kept, merged).await?;
+
+    Ok(Json(DataResponse { data: duplicate }))
+}
+
+/// Dismisses a pair as not being duplicates, which also undoes merging it.
+#[axum::debug_handler]
+pub async fn dismiss_transaction_duplicate(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, duplicate_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<Duplicate>>, AppError> {
+    let duplicate = find_user_duplicate(&state, &user_id, duplicate_id).await?;
+
+    let duplicate = dismiss_duplicate(&state.pool, &duplicate).await?;
+
+    Ok(Json(DataResponse { data: duplicate }))
+}
+
+#[derive(Deserialize)]
+pub struct AlertsParams {
+    #[serde(default)]
+    pub include_dismissed: bool,
+}
+
+/// Lists alerts about unusual transactions, newest first. Dismissed ones are left out unless
+/// asked for.
+#[axum::debug_handler]
+pub async fn get_alerts(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Query(params): Query<AlertsParams>,
+) -> Result<Json<DataResponse<Vec<Alert>>>, AppError> {
+    let alerts = query_alerts(&state.pool, &user_id, params.include_dismissed).await?;
+
+    Ok(Json(DataResponse { data: alerts }))
+}
+
+#[axum::debug_handler]
+pub async fn dismiss_user_alert(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, alert_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<Alert>>, AppError> {
+    let alert = dismiss_alert(&state.pool, &user_id, alert_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(DataResponse { data: alert }))
+}
+
+/// Streams the user's transactions as server-sent events whenever they are stored or change,
+/// whether that came from a webhook or the poll. Events are named `transaction.created` or
+/// `transaction.updated` and carry the transaction. A `lagged` event means some were missed, so
+/// the client should fetch the transactions again.
+#[axum::debug_handler]
+pub async fn get_events(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.transaction_events.subscribe();
+
+    let events =
+        stream::unfold(
+            (receiver, state, user_id),
+            |(mut receiver, state, user_id)| async move {
+                loop {
+                    let event = match receiver.recv().await {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::warn!(
+                                "Event stream for user_id={} missed {} events",
+                                &user_id,
+                                missed
+                            );
+                            let lagged = Event::default().event("lagged").data(missed.to_string());
+                            return Some((lagged, (receiver, state, user_id)));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    };
+                    if event.user_id.as_deref() != Some(user_id.as_str()) {
+                        continue;
+                    }
+
+                    let transaction =
+                        match query_user_transaction(&state.pool, &user_id, &event.transaction_id)
+                            .await
+                        {
+                            Ok(Some(transaction)) => transaction,
+                            Ok(None) => continue,
+                            Err(err) => {
+                                tracing::error!(
+                                    "Error querying transaction id={} for an event: {:#?}",
+                                    &event.transaction_id,
+                                    err
+                                );
+                                continue;
+                            }
+                        };
+                    let name = match event.kind {
+                        TransactionEventKind::Created => "transaction.created",
+                        TransactionEventKind::Updated => "transaction.updated",
+                    };
+                    match Event::default()
+                        .event(name)
+                        .id(&event.transaction_id)
+                        .json_data(&transaction)
+                    {
+                        Ok(sse_event) => return Some((sse_event, (receiver, state, user_id))),
+                        Err(err) => tracing::error!(
+                            "Error serialising transaction id={} for an event: {}",
+                            &event.transaction_id,
+                            err
+                        ),
+                    }
+                }
+            },
+        );
+
+    Sse::new(events.map(Ok)).keep_alive(KeepAlive::default())
+}
+
+/// How many deliveries the delivery log shows.
+const MAX_WEBHOOK_DELIVERIES: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// The events to send, or all of them when empty.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// A newly registered endpoint along with the secret its deliveries are signed with, which is
+/// not shown again.
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpoint,
+    pub secret: String,
+}
+
+#[axum::debug_handler]
+pub async fn get_webhooks(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<DataResponse<Vec<WebhookEndpoint>>>, AppError> {
+    let endpoints = query_webhook_endpoints(&state.pool, &user_id).await?;
+
+    Ok(Json(DataResponse { data: endpoints }))
+}
+
+/// Registers an endpoint to be sent the user's events, signed with a secret only shown in the
+/// response.
+#[axum::debug_handler]
+pub async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<DataResponse<CreatedWebhook>>), AppError> {
+    let url = url::Url::parse(&request.url)
+        .map_err(|err| AppError::BadRequest(format!("Invalid url: {}", err)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(String::from(
+            "The url should be http or https",
+        )));
+    }
+
+    if let Some(event) = request
+        .events
+        .iter()
+        .find(|event| !WEBHOOK_EVENTS.contains(&event.as_str()))
+    {
+        return Err(AppError::BadRequest(format!(
+            "Unknown event {}, expected one of {}",
+            event,
+            WEBHOOK_EVENTS.join(", ")
+        )));
+    }
+
+    let endpoint =
+        insert_webhook_endpoint(&state.pool, &user_id, url.as_str(), &request.events).await?;
+
+    tracing::info!(
+        "Registered webhook id={} for user_id={}",
+        endpoint.id,
+        &user_id
+    );
+
+    let secret = endpoint.secret.clone();
+    Ok((
+        StatusCode::CREATED,
+        Json(DataResponse {
+            data: CreatedWebhook { endpoint, secret },
+        }),
+    ))
+}
+
+#[axum::debug_handler]
+pub async fn remove_webhook(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, webhook_id)): Path<(String, i64)>,
+) -> Result<StatusCode, AppError> {
+    let result = delete_webhook_endpoint(&state.pool, &user_id, webhook_id).await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    tracing::info!("Deleted webhook id={} for user_id={}", webhook_id, &user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The most recent deliveries to an endpoint, newest first, with how each attempt went.
+#[axum::debug_handler]
+pub async fn get_webhook_deliveries(
+    State(state): State<Arc<AppState>>,
+    Path((user_id, webhook_id)): Path<(String, i64)>,
+) -> Result<Json<DataResponse<Vec<WebhookDelivery>>>, AppError> {
+    let endpoint = query_webhook_endpoint(&state.pool, &user_id, webhook_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let deliveries =
+        query_webhook_deliveries(&state.pool, endpoint.id, MAX_WEBHOOK_DELIVERIES).await?;
 
-    Ok(StatusCode::CREATED)
+    Ok(Json(DataResponse { data: deliveries }))
 }
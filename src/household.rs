@@ -0,0 +1,156 @@
+use crate::{
+    domain::{Balance, Debt, Settlement, SharedExpense},
+    money::{Money, MoneyError},
+};
+
+/// Divides `total` in proportion to `weights`. Rounding leftovers go to the largest remainders,
+/// earliest first, so the shares always add up to `total`.
+pub fn share_amounts(total: i64, weights: &[i32]) -> Vec<i64> {
+    let weight_sum: i128 = weights.iter().map(|weight| *weight as i128).sum();
+    if weight_sum == 0 {
+        return vec![0; weights.len()];
+    }
+
+    let exact: Vec<(i64, i128)> = weights
+        .iter()
+        .map(|weight| {
+            let numerator = total as i128 * *weight as i128;
+            (
+                numerator.div_euclid(weight_sum) as i64,
+                numerator.rem_euclid(weight_sum),
+            )
+        })
+        .collect();
+
+    let mut shares: Vec<i64> = exact.iter().map(|(share, _)| *share).collect();
+    let leftover = total - shares.iter().sum::<i64>();
+
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    order.sort_by(|a, b| exact[*b].1.cmp(&exact[*a].1).then(a.cmp(b)));
+    for index in order.into_iter().take(leftover as usize) {
+        shares[index] += 1;
+    }
+    shares
+}
+
+fn add_to_balance(
+    balances: &mut Vec<Balance>,
+    user_id: &str,
+    amount: &Money,
+) -> Result<(), MoneyError> {
+    match balances
+        .iter_mut()
+        .find(|balance| balance.user_id == user_id && balance.balance.currency == amount.currency)
+    {
+        Some(balance) => balance.balance = balance.balance.checked_add(amount)?,
+        None => balances.push(Balance {
+            user_id: user_id.to_string(),
+            balance: amount.clone(),
+        }),
+    }
+    Ok(())
+}
+
+/// Works out each member's running balance per currency. Whoever paid for a shared transaction is
+/// owed its cost less their own share, and settlements move money back the other way.
+pub fn balances(
+    members: &[String],
+    expenses: &[SharedExpense],
+    settlements: &[Settlement],
+) -> Result<Vec<Balance>, MoneyError> {
+    let mut balances = vec![];
+
+    for expense in expenses.iter() {
+        // Spending is negative, so this is positive for spending and negative for refunds.
+        let cost = expense.money.checked_neg()?;
+        add_to_balance(&mut balances, &expense.paid_by, &cost)?;
+        let shares = share_amounts(cost.amount, &expense.weights);
+        for (user_id, share) in expense.user_ids.iter().zip(shares) {
+            add_to_balance(&mut balances, user_id, &Money::new(-share, &cost.currency))?;
+        }
+    }
+
+    for settlement in settlements.iter() {
+        add_to_balance(&mut balances, &settlement.from_user_id, &settlement.money)?;
+        add_to_balance(
+            &mut balances,
+            &settlement.to_user_id,
+            &settlement.money.checked_neg()?,
+        )?;
+    }
+
+    // Members with nothing shared yet still show up, as long as there is a currency to show.
+    let mut currencies: Vec<String> = balances
+        .iter()
+        .map(|balance| balance.balance.currency.clone())
+        .collect();
+    currencies.sort();
+    currencies.dedup();
+    for member in members.iter() {
+        for currency in currencies.iter() {
+            add_to_balance(&mut balances, member, &Money::zero(currency))?;
+        }
+    }
+
+    balances
+        .sort_by(|a, b| (&a.balance.currency, &a.user_id).cmp(&(&b.balance.currency, &b.user_id)));
+    Ok(balances)
+}
+
+/// Turns balances into a short list of payments that would settle everyone up, by repeatedly
+/// having the member who owes the most pay the member who is owed the most.
+pub fn simplify_debts(balances: &[Balance]) -> Vec<Debt> {
+    let mut debts = vec![];
+
+    let mut currencies: Vec<&String> = balances
+        .iter()
+        .map(|balance| &balance.balance.currency)
+        .collect();
+    currencies.sort();
+    currencies.dedup();
+
+    for currency in currencies {
+        let mut remaining: Vec<(&String, i64)> = balances
+            .iter()
+            .filter(|balance| &balance.balance.currency == currency && !balance.balance.is_zero())
+            .map(|balance| (&balance.user_id, balance.balance.amount))
+            .collect();
+
+        loop {
+            remaining.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+            let (Some(debtor), Some(creditor)) = (remaining.first(), remaining.last()) else {
+                break;
+            };
+            if debtor.1 >= 0 || creditor.1 <= 0 {
+                break;
+            }
+
+            let amount = (-debtor.1).min(creditor.1);
+            debts.push(Debt {
+                from_user_id: debtor.0.clone(),
+                to_user_id: creditor.0.clone(),
+                money: Money::new(amount, currency),
+            });
+
+            let last = remaining.len() - 1;
+            remaining[0].1 += amount;
+            remaining[last].1 -= amount;
+            remaining.retain(|(_, balance)| *balance != 0);
+        }
+    }
+
+    debts
+}
+
+/// How much `from_user_id` owes `to_user_id` in `currency` according to the simplified debts.
+pub fn amount_owed(debts: &[Debt], from_user_id: &str, to_user_id: &str, currency: &str) -> i64 {
+    debts
+        .iter()
+        .filter(|debt| {
+            debt.from_user_id == from_user_id
+                && debt.to_user_id == to_user_id
+                && debt.money.currency == currency
+        })
+        .map(|debt| debt.money.amount)
+        .sum()
+}
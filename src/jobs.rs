@@ -4,12 +4,22 @@ use chrono::{Duration, Utc};
 
 use crate::{
     AppState,
-    db::{query_account_ids, query_all_tokens, query_tokens_expiring_before, upsert_token},
-    domain::Token,
-    model::{list_and_update_accounts, list_and_update_transactions, register_webhook},
+    db::{
+        listen_transaction_events, query_account_webhook_secrets, query_all_tokens,
+        query_tokens_expiring_before, upsert_token,
+    },
+    domain::{Token, TransactionEvent},
+    model::{
+        deliver_webhooks, detect_user_subscriptions, list_and_update_accounts,
+        list_and_update_pots, list_and_update_transactions, monzo_callback_url,
+        process_monzo_webhooks, register_webhook, snapshot_balances, sync_to_monzo,
+    },
     monzo::refresh_tokens,
 };
 
+/// How long to wait before listening for transaction events again after it failed.
+const LISTEN_RETRY_SECONDS: u64 = 5;
+
 pub async fn token_refresh_task(state: Arc<AppState>) {
     // Create a Tokio interval. The first tick fires immediately.
     let mut interval =
@@ -86,17 +96,39 @@ pub async fn account_poll_task(state: Arc<AppState>) {
         tracing::info!("Found {} tokens to poll accounts for", tokens.len());
 
         for token in tokens.iter() {
-            let _ = list_and_update_accounts(&state.pool, &token).await;
-            let _ = list_and_update_transactions(&state.pool, &token).await;
-            for account_id in query_account_ids(&state.pool, &token.user_id)
-                .await
-                .unwrap()
-                .iter()
-            {
+            let _ = list_and_update_accounts(&state.pool, token).await;
+            let _ = list_and_update_transactions(&state.pool, token).await;
+            if let Err(err) = list_and_update_pots(&state.pool, token).await {
+                tracing::error!(
+                    "An error occurred while updating pots for user_id={}: {:#?}",
+                    &token.user_id,
+                    err
+                );
+            }
+            if let Err(err) = snapshot_balances(&state.pool, token).await {
+                tracing::error!(
+                    "An error occurred while recording balances for user_id={}: {:#?}",
+                    &token.user_id,
+                    err
+                );
+            }
+            let webhook_secrets =
+                match query_account_webhook_secrets(&state.pool, &token.user_id).await {
+                    Ok(webhook_secrets) => webhook_secrets,
+                    Err(err) => {
+                        tracing::error!(
+                            "An error occurred while querying accounts for user_id={}: {:#?}",
+                            &token.user_id,
+                            err
+                        );
+                        continue;
+                    }
+                };
+            for (account_id, webhook_secret) in webhook_secrets.iter() {
                 let _ = register_webhook(
                     &token.access_token,
                     account_id,
-                    "https://expenses.sebastiancoetzee.com/api/monzo-callback",
+                    &monzo_callback_url(&state.base_url, webhook_secret),
                 )
                 .await;
             }
@@ -105,3 +137,138 @@ pub async fn account_poll_task(state: Arc<AppState>) {
         tracing::info!("Finished running account_poll_task...");
     }
 }
+
+pub async fn monzo_sync_task(state: Arc<AppState>) {
+    // Create a Tokio interval. The first tick fires immediately.
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(state.monzo_sync_interval));
+
+    loop {
+        // Wait for the next interval tick
+        interval.tick().await;
+        tracing::info!("Running monzo_sync_task...");
+
+        if let Err(err) = sync_to_monzo(&state.pool, &state.blob_store).await {
+            tracing::error!("An error occurred while syncing to Monzo: {:#?}", err);
+        }
+
+        tracing::info!("Finished running monzo_sync_task...");
+    }
+}
+
+pub async fn webhook_delivery_task(state: Arc<AppState>) {
+    // Create a Tokio interval. The first tick fires immediately.
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.webhook_delivery_interval,
+    ));
+
+    loop {
+        // Wait for the next interval tick
+        interval.tick().await;
+
+        if let Err(err) = deliver_webhooks(&state.pool).await {
+            tracing::error!("An error occurred while delivering webhooks: {:#?}", err);
+        }
+    }
+}
+
+/// Processes the Monzo webhooks in the inbox as soon as one arrives, and retries the ones that
+/// failed on an interval.
+pub async fn monzo_webhook_task(state: Arc<AppState>) {
+    // Create a Tokio interval. The first tick fires immediately.
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(state.monzo_webhook_interval));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = state.monzo_webhooks_received.notified() => {}
+        }
+
+        if let Err(err) = process_monzo_webhooks(
+            &state.pool,
+            &state.blob_store,
+            state.refetch_monzo_callbacks,
+        )
+        .await
+        {
+            tracing::error!(
+                "An error occurred while processing Monzo webhooks: {:#?}",
+                err
+            );
+        }
+    }
+}
+
+pub async fn subscription_detection_task(state: Arc<AppState>) {
+    // Create a Tokio interval. The first tick fires immediately.
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        state.subscription_detection_interval,
+    ));
+
+    loop {
+        // Wait for the next interval tick
+        interval.tick().await;
+        tracing::info!("Running subscription_detection_task...");
+
+        let tokens = match query_all_tokens(&state.pool).await {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                tracing::error!("An error occurred while querying tokens: {:#?}", err);
+                continue;
+            }
+        };
+
+        for token in tokens.iter() {
+            if let Err(err) = detect_user_subscriptions(&state.pool, &token.user_id).await {
+                tracing::error!(
+                    "An error occurred while detecting subscriptions for user_id={}: {:#?}",
+                    &token.user_id,
+                    err
+                );
+            }
+        }
+
+        tracing::info!("Finished running subscription_detection_task...");
+    }
+}
+
+/// Relays the transaction changes Postgres announces to the event streams served by this instance.
+/// Every instance listens, so clients hear about changes whichever instance made them.
+pub async fn transaction_event_task(state: Arc<AppState>) {
+    loop {
+        let mut listener = match listen_transaction_events(&state.pool).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                tracing::error!("Error listening for transaction events: {:#?}", err);
+                tokio::time::sleep(std::time::Duration::from_secs(LISTEN_RETRY_SECONDS)).await;
+                continue;
+            }
+        };
+        tracing::info!("Listening for transaction events...");
+
+        // The listener reconnects by itself when the connection drops, so errors are unexpected.
+        loop {
+            let notification = match listener.recv().await {
+                Ok(notification) => notification,
+                Err(err) => {
+                    tracing::error!("Error receiving transaction events: {:#?}", err);
+                    break;
+                }
+            };
+            match serde_json::from_str::<TransactionEvent>(notification.payload()) {
+                // Sending only fails when nobody is listening, which is fine.
+                Ok(event) => {
+                    let _ = state.transaction_events.send(event);
+                }
+                Err(err) => tracing::error!(
+                    "Error parsing transaction event {}: {}",
+                    notification.payload(),
+                    err
+                ),
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(LISTEN_RETRY_SECONDS)).await;
+    }
+}
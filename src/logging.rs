@@ -5,22 +5,31 @@ use tracing_subscriber::{
         self,
         format::{Format, Full},
         time::SystemTime,
+        writer::BoxMakeWriter,
     },
     prelude::*,
 };
 
 fn build_base_log_format() -> Format<Full, SystemTime> {
-    return fmt::format()
+    fmt::format()
         .with_level(true)
         .with_ansi(false)
         .with_file(true)
         .with_target(true)
-        .with_thread_names(true);
+        .with_thread_names(true)
 }
 
-pub fn setup_logging(base_log_dir: &str) {
-    let stdout_layer =
-        tracing_subscriber::fmt::layer().event_format(build_base_log_format().with_ansi(true));
+/// Sets up logging to the console and, given a directory, to files. Console logs go to stderr
+/// instead of stdout when `to_stderr` is set, so that a command can print its output to stdout.
+pub fn setup_logging(base_log_dir: &str, to_stderr: bool) {
+    let console_writer = if to_stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let stdout_layer = tracing_subscriber::fmt::layer()
+        .event_format(build_base_log_format().with_ansi(true))
+        .with_writer(console_writer);
 
     let filter = Targets::new()
         .with_target("sqlx", Level::INFO)
@@ -32,7 +41,7 @@ pub fn setup_logging(base_log_dir: &str) {
         .with(filter)
         .with(stdout_layer);
 
-    if base_log_dir != "" {
+    if !base_log_dir.is_empty() {
         let log_file_layer = tracing_subscriber::fmt::layer()
             .event_format(build_base_log_format())
             .with_writer(tracing_appender::rolling::daily(
@@ -1,24 +1,72 @@
+mod anomalies;
 mod args;
+mod budgets;
 mod db;
 mod domain;
+mod duplicates;
+mod forecast;
+mod fx;
 mod handlers;
+mod household;
 mod jobs;
 mod logging;
+mod matching;
 mod model;
+mod money;
 mod monzo;
+mod report;
+mod rules;
+mod splits;
+mod storage;
+mod subscriptions;
+mod vat;
+mod webhooks;
 
-use std::sync::Arc;
+use std::{error::Error, sync::Arc};
 
-use args::parse_args;
+use args::{Command, ReplayArgs, parse_args};
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{delete, get, post, put},
 };
 use db::create_pool;
-use handlers::{authorise, callback, get_transactions, monzo_callback};
-use jobs::{account_poll_task, token_refresh_task};
+use domain::{ReplayRequest, TransactionEvent};
+use handlers::{
+    MAX_FX_RATES_SIZE, MAX_RECEIPT_SIZE, add_group_member, add_transaction_tag, authorise,
+    callback, create_budget, create_category, create_claim, create_group, create_rule,
+    create_settlement, create_tag, create_webhook, delete_approver, delete_claim_split,
+    delete_claim_transaction, delete_transaction_category, delete_transaction_share,
+    delete_transaction_splits, dismiss_transaction_duplicate, dismiss_user_alert, download_receipt,
+    download_user_receipt, edit_category, edit_tag, get_account_forecast, get_accounts, get_alerts,
+    get_approver_claim, get_approver_claim_report, get_approvers, get_balance_history, get_budgets,
+    get_categories, get_claim, get_claim_report, get_claim_transitions, get_claims, get_duplicates,
+    get_events, get_expense_report, get_group_balances, get_groups, get_monzo_webhooks,
+    get_pending_claims, get_pot_balance_history, get_receipt_suggestions, get_receipts, get_rules,
+    get_settings, get_settlements, get_subscriptions, get_tags, get_transaction_splits,
+    get_transactions, get_unmatched_receipts, get_vat_report, get_webhook_deliveries, get_webhooks,
+    merge_transaction_duplicate, monzo_callback, post_approver_claim_transition, post_claim_splits,
+    post_claim_transactions, post_claim_transition, post_fx_rates, put_approver,
+    put_receipt_transaction, put_settings, put_transaction_category, put_transaction_notes,
+    put_transaction_reimbursable, put_transaction_share, put_transaction_splits,
+    put_transaction_vat, reapply_rules, redetect_duplicates, redetect_subscriptions, remove_budget,
+    remove_category, remove_claim, remove_group_member, remove_receipt, remove_rule, remove_tag,
+    remove_transaction_tag, remove_user_receipt, remove_webhook, replay_webhooks, upload_receipt,
+    upload_user_receipt,
+};
+use jobs::{
+    account_poll_task, monzo_sync_task, monzo_webhook_task, subscription_detection_task,
+    token_refresh_task, transaction_event_task, webhook_delivery_task,
+};
 use logging::setup_logging;
+use model::replay_monzo_webhooks;
+use serde_json::Value;
 use sqlx::PgPool;
+use storage::{BlobStore, create_blob_store};
+use tokio::sync::{Notify, broadcast};
+
+/// Events waiting for a slow event stream beyond this many are dropped for it.
+const TRANSACTION_EVENTS_CAPACITY: usize = 1024;
 
 pub struct AppState {
     base_url: String,
@@ -28,42 +76,342 @@ pub struct AppState {
     token_refresh_interval: u64,
     token_refresh_threshold: u64,
     account_poll_interval: u64,
+    monzo_sync_interval: u64,
+    subscription_detection_interval: u64,
+    webhook_delivery_interval: u64,
+    refetch_monzo_callbacks: bool,
+    monzo_webhook_interval: u64,
+    /// Wakes the Monzo webhook task as soon as a webhook arrives.
+    monzo_webhooks_received: Notify,
+    admin_token: Option<String>,
+    blob_store: BlobStore,
+    transaction_events: broadcast::Sender<TransactionEvent>,
+}
+
+/// Replays the Monzo webhooks asked for on the command line and prints what changed.
+async fn replay(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    refetch: bool,
+    replay_args: &ReplayArgs,
+) -> Result<(), Box<dyn Error>> {
+    let payloads = match &replay_args.file {
+        Some(path) => match serde_json::from_str::<Value>(&std::fs::read_to_string(path)?)? {
+            Value::Array(payloads) => payloads,
+            payload => vec![payload],
+        },
+        None => vec![],
+    };
+    let request = ReplayRequest {
+        webhook_ids: replay_args.webhook_ids.clone(),
+        status: replay_args.status,
+        payloads,
+        dry_run: replay_args.dry_run,
+    };
+
+    let replays = replay_monzo_webhooks(pool, blob_store, refetch, &request).await?;
+    println!("{}", serde_json::to_string_pretty(&replays)?);
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
     let args = parse_args();
 
-    setup_logging(&args.base_log_dir);
+    // Commands print their output to stdout, so their logs go to stderr.
+    setup_logging(&args.base_log_dir, args.command.is_some());
 
     let pool = create_pool(&args.database_url)
         .await
         .expect("Failed to create PostgreSQL pool");
 
+    let blob_store = create_blob_store(&args.receipt_store).expect("Failed to create blob store");
+
+    if let Some(Command::Replay(replay_args)) = &args.command {
+        if let Err(err) = replay(
+            &pool,
+            &blob_store,
+            args.refetch_monzo_callbacks,
+            replay_args,
+        )
+        .await
+        {
+            tracing::error!("Error replaying Monzo webhooks: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let app_state = Arc::new(AppState {
-        base_url: args.base_url,
-        client_id: args.client_id,
-        client_secret: args.client_secret,
+        base_url: args.base_url.expect("base_url is required to serve"),
+        client_id: args.client_id.expect("client_id is required to serve"),
+        client_secret: args
+            .client_secret
+            .expect("client_secret is required to serve"),
         pool,
         token_refresh_interval: args.token_refresh_interval,
         token_refresh_threshold: args.token_refresh_threshold,
         account_poll_interval: args.account_poll_interval,
+        monzo_sync_interval: args.monzo_sync_interval,
+        subscription_detection_interval: args.subscription_detection_interval,
+        webhook_delivery_interval: args.webhook_delivery_interval,
+        refetch_monzo_callbacks: args.refetch_monzo_callbacks,
+        monzo_webhook_interval: args.monzo_webhook_interval,
+        monzo_webhooks_received: Notify::new(),
+        admin_token: args.admin_token,
+        blob_store,
+        transaction_events: broadcast::channel(TRANSACTION_EVENTS_CAPACITY).0,
     });
 
     tracing::info!("Spawning background tasks...");
     tokio::spawn(token_refresh_task(app_state.clone()));
     tokio::spawn(account_poll_task(app_state.clone()));
+    tokio::spawn(monzo_sync_task(app_state.clone()));
+    tokio::spawn(subscription_detection_task(app_state.clone()));
+    tokio::spawn(transaction_event_task(app_state.clone()));
+    tokio::spawn(webhook_delivery_task(app_state.clone()));
+    tokio::spawn(monzo_webhook_task(app_state.clone()));
 
     // build our application with a single route
     let app = Router::new()
         .route("/api/transactions/{user_id}", get(get_transactions))
-        .route("/api/monzo-callback", post(monzo_callback))
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/receipts",
+            get(get_receipts)
+                .post(upload_receipt)
+                .layer(DefaultBodyLimit::max(MAX_RECEIPT_SIZE)),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/receipts/{receipt_id}",
+            get(download_receipt).delete(remove_receipt),
+        )
+        .route(
+            "/api/users/{user_id}/receipts",
+            post(upload_user_receipt).layer(DefaultBodyLimit::max(MAX_RECEIPT_SIZE)),
+        )
+        .route(
+            "/api/users/{user_id}/receipts/unmatched",
+            get(get_unmatched_receipts),
+        )
+        .route(
+            "/api/users/{user_id}/receipts/{receipt_id}",
+            get(download_user_receipt).delete(remove_user_receipt),
+        )
+        .route(
+            "/api/users/{user_id}/receipts/{receipt_id}/suggestions",
+            get(get_receipt_suggestions),
+        )
+        .route(
+            "/api/users/{user_id}/receipts/{receipt_id}/transaction",
+            put(put_receipt_transaction),
+        )
+        .route("/api/monzo-callback/{webhook_secret}", post(monzo_callback))
+        .route(
+            "/api/users/{user_id}/rules",
+            get(get_rules).post(create_rule),
+        )
+        .route("/api/users/{user_id}/rules/{rule_id}", delete(remove_rule))
+        .route("/api/users/{user_id}/rules/apply", post(reapply_rules))
+        .route(
+            "/api/users/{user_id}/categories",
+            get(get_categories).post(create_category),
+        )
+        .route(
+            "/api/users/{user_id}/categories/{category_id}",
+            put(edit_category).delete(remove_category),
+        )
+        .route(
+            "/api/users/{user_id}/budgets",
+            get(get_budgets).post(create_budget),
+        )
+        .route(
+            "/api/users/{user_id}/budgets/{budget_id}",
+            delete(remove_budget),
+        )
+        .route("/api/users/{user_id}/tags", get(get_tags).post(create_tag))
+        .route(
+            "/api/users/{user_id}/tags/{tag_id}",
+            put(edit_tag).delete(remove_tag),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/category",
+            put(put_transaction_category).delete(delete_transaction_category),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/tags",
+            post(add_transaction_tag),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/tags/{tag_id}",
+            delete(remove_transaction_tag),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/notes",
+            put(put_transaction_notes),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/splits",
+            get(get_transaction_splits)
+                .put(put_transaction_splits)
+                .delete(delete_transaction_splits),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/vat",
+            put(put_transaction_vat),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/reimbursable",
+            put(put_transaction_reimbursable),
+        )
+        .route(
+            "/api/users/{user_id}/claims",
+            get(get_claims).post(create_claim),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}",
+            get(get_claim).delete(remove_claim),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}/transactions",
+            post(post_claim_transactions),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}/transactions/{transaction_id}",
+            delete(delete_claim_transaction),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}/splits",
+            post(post_claim_splits),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}/splits/{split_id}",
+            delete(delete_claim_split),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}/transitions",
+            get(get_claim_transitions).post(post_claim_transition),
+        )
+        .route(
+            "/api/users/{user_id}/claims/{claim_id}/report.pdf",
+            get(get_claim_report),
+        )
+        .route(
+            "/api/users/{user_id}/transactions/{transaction_id}/share",
+            put(put_transaction_share).delete(delete_transaction_share),
+        )
+        .route(
+            "/api/users/{user_id}/groups",
+            get(get_groups).post(create_group),
+        )
+        .route(
+            "/api/users/{user_id}/groups/{group_id}/members",
+            post(add_group_member),
+        )
+        .route(
+            "/api/users/{user_id}/groups/{group_id}/members/{member_id}",
+            delete(remove_group_member),
+        )
+        .route(
+            "/api/users/{user_id}/groups/{group_id}/balances",
+            get(get_group_balances),
+        )
+        .route(
+            "/api/users/{user_id}/groups/{group_id}/settlements",
+            get(get_settlements).post(create_settlement),
+        )
+        .route(
+            "/api/users/{user_id}/settings",
+            get(get_settings).put(put_settings),
+        )
+        .route("/api/users/{user_id}/accounts", get(get_accounts))
+        .route(
+            "/api/users/{user_id}/pots/{pot_id}/balances",
+            get(get_pot_balance_history),
+        )
+        .route(
+            "/api/users/{user_id}/accounts/{account_id}/balances",
+            get(get_balance_history),
+        )
+        .route(
+            "/api/users/{user_id}/accounts/{account_id}/forecast",
+            get(get_account_forecast),
+        )
+        .route("/api/users/{user_id}/events", get(get_events))
+        .route("/api/users/{user_id}/alerts", get(get_alerts))
+        .route(
+            "/api/users/{user_id}/alerts/{alert_id}/dismiss",
+            post(dismiss_user_alert),
+        )
+        .route(
+            "/api/users/{user_id}/webhooks",
+            get(get_webhooks).post(create_webhook),
+        )
+        .route(
+            "/api/users/{user_id}/webhooks/{webhook_id}",
+            delete(remove_webhook),
+        )
+        .route(
+            "/api/users/{user_id}/webhooks/{webhook_id}/deliveries",
+            get(get_webhook_deliveries),
+        )
+        .route("/api/users/{user_id}/duplicates", get(get_duplicates))
+        .route(
+            "/api/users/{user_id}/duplicates/detect",
+            post(redetect_duplicates),
+        )
+        .route(
+            "/api/users/{user_id}/duplicates/{duplicate_id}/merge",
+            post(merge_transaction_duplicate),
+        )
+        .route(
+            "/api/users/{user_id}/duplicates/{duplicate_id}/dismiss",
+            post(dismiss_transaction_duplicate),
+        )
+        .route("/api/users/{user_id}/subscriptions", get(get_subscriptions))
+        .route(
+            "/api/users/{user_id}/subscriptions/detect",
+            post(redetect_subscriptions),
+        )
+        .route("/api/users/{user_id}/reports/vat", get(get_vat_report))
+        .route(
+            "/api/users/{user_id}/reports/expenses.pdf",
+            get(get_expense_report),
+        )
+        .route(
+            "/api/approvers/{approver_id}/claims/pending",
+            get(get_pending_claims),
+        )
+        .route(
+            "/api/approvers/{approver_id}/claims/{claim_id}",
+            get(get_approver_claim),
+        )
+        .route(
+            "/api/approvers/{approver_id}/claims/{claim_id}/transitions",
+            post(post_approver_claim_transition),
+        )
+        .route(
+            "/api/approvers/{approver_id}/claims/{claim_id}/report.pdf",
+            get(get_approver_claim_report),
+        )
+        .route("/api/admin/approvers", get(get_approvers))
+        .route("/api/admin/monzo-webhooks", get(get_monzo_webhooks))
+        .route("/api/admin/monzo-webhooks/replay", post(replay_webhooks))
+        .route(
+            "/api/admin/fx-rates",
+            post(post_fx_rates).layer(DefaultBodyLimit::max(MAX_FX_RATES_SIZE)),
+        )
+        .route(
+            "/api/admin/approvers/{approver_id}",
+            put(put_approver).delete(delete_approver),
+        )
         .route("/authorise", get(authorise))
         .route("/oauth/callback", get(callback))
         .route("/", get(|| async { "Hello, World!" }))
         .with_state(app_state);
 
-    let bind_address = format! {"0.0.0.0:{}", args.port};
+    let port = args.port.expect("port is required to serve");
+    let bind_address = format! {"0.0.0.0:{}", port};
     tracing::info!("Server listening on {}...", bind_address);
 
     let listener = tokio::net::TcpListener::bind(bind_address).await.unwrap();
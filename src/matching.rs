@@ -0,0 +1,211 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{
+    domain::{Receipt, Transaction},
+    money::Money,
+};
+
+/// How many days either side of the receipt date a transaction may be. Card payments can take a
+/// few days to show up and receipts are often dated by hand.
+pub const DATE_WINDOW_DAYS: i64 = 7;
+/// Amounts further apart than this fraction of the receipt amount, e.g. because of a tip, do not
+/// match at all.
+const AMOUNT_TOLERANCE: f64 = 0.2;
+const AMOUNT_WEIGHT: f64 = 0.5;
+const DATE_WEIGHT: f64 = 0.3;
+const MERCHANT_WEIGHT: f64 = 0.2;
+const MAX_SUGGESTIONS: usize = 10;
+
+#[derive(Serialize)]
+pub struct MatchSuggestion {
+    pub transaction: Transaction,
+    /// Between 0 and 1, weighing only the scores for which the receipt has something to match on.
+    pub score: f64,
+    pub amount_score: Option<f64>,
+    pub date_score: f64,
+    pub merchant_score: Option<f64>,
+}
+
+/// The amount of a transaction in the currency of a receipt: what was charged, or what was paid
+/// abroad for a receipt from there. Amounts in other currencies cannot be compared.
+fn amount_in_currency<'a>(transaction: &'a Transaction, currency: &str) -> Option<&'a Money> {
+    [Some(&transaction.money), transaction.local_amount.as_ref()]
+        .into_iter()
+        .flatten()
+        .find(|money| money.currency == currency)
+}
+
+/// Receipts record what was paid, so the transaction amount is expected to be negative. Amounts so
+/// large that the difference cannot be worked out do not match.
+fn amount_score(receipt_amount: &Money, transaction: &Transaction) -> Option<f64> {
+    let transaction_amount = amount_in_currency(transaction, &receipt_amount.currency)?.amount;
+    let difference = transaction_amount
+        .checked_neg()?
+        .checked_sub(receipt_amount.amount)?
+        .checked_abs()? as f64;
+    let difference = difference / receipt_amount.amount.max(1) as f64;
+    if difference > AMOUNT_TOLERANCE {
+        None
+    } else {
+        Some(1.0 - difference / AMOUNT_TOLERANCE)
+    }
+}
+
+fn date_score(receipt_date: NaiveDate, transaction_date: NaiveDate) -> Option<f64> {
+    let days = (transaction_date - receipt_date).num_days().abs();
+    if days > DATE_WINDOW_DAYS {
+        None
+    } else {
+        Some(1.0 - days as f64 / (DATE_WINDOW_DAYS + 1) as f64)
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn bigrams(text: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+/// Compares merchant names, which are written very differently on receipts and in bank statements
+/// ("Pret" vs "PRET A MANGER LONDON GBR"). This takes the better of the share of the receipt's
+/// words found in the other name and the bigram (Dice) similarity of the two, which copes with
+/// small spelling differences.
+pub fn merchant_similarity(receipt_merchant: &str, name: &str) -> f64 {
+    let receipt_words = words(receipt_merchant);
+    let name_words = words(name);
+    if receipt_words.is_empty() || name_words.is_empty() {
+        return 0.0;
+    }
+
+    let found = receipt_words
+        .iter()
+        .filter(|word| name_words.contains(word))
+        .count();
+    let word_score = found as f64 / receipt_words.len() as f64;
+
+    let a = bigrams(&receipt_words.join(" "));
+    let mut b = bigrams(&name_words.join(" "));
+    let total = a.len() + b.len();
+    let mut shared = 0;
+    for bigram in a.iter() {
+        if let Some(index) = b.iter().position(|other| other == bigram) {
+            b.swap_remove(index);
+            shared += 1;
+        }
+    }
+    let bigram_score = if total == 0 {
+        0.0
+    } else {
+        2.0 * shared as f64 / total as f64
+    };
+
+    word_score.max(bigram_score)
+}
+
+/// Ranks spending transactions as candidates for an unmatched receipt. Transactions outside the
+/// date window around `receipt_date`, or with an amount too far from the receipt's, are left out.
+pub fn rank_candidates(
+    receipt: &Receipt,
+    receipt_date: NaiveDate,
+    transactions: Vec<Transaction>,
+) -> Vec<MatchSuggestion> {
+    let mut suggestions: Vec<MatchSuggestion> = transactions
+        .into_iter()
+        .filter(|transaction| transaction.money.amount < 0)
+        .filter_map(|transaction| {
+            let date_score = date_score(receipt_date, transaction.created.date_naive())?;
+            let amount_score = match &receipt.amount {
+                Some(amount) => Some(amount_score(amount, &transaction)?),
+                None => None,
+            };
+            let merchant_score = receipt.merchant.as_ref().map(|merchant| {
+                let by_description = merchant_similarity(merchant, &transaction.description);
+                let by_name = transaction
+                    .merchant_name
+                    .as_ref()
+                    .map(|name| merchant_similarity(merchant, name))
+                    .unwrap_or(0.0);
+                by_description.max(by_name)
+            });
+
+            let mut score = DATE_WEIGHT * date_score;
+            let mut weights = DATE_WEIGHT;
+            if let Some(amount_score) = amount_score {
+                score += AMOUNT_WEIGHT * amount_score;
+                weights += AMOUNT_WEIGHT;
+            }
+            if let Some(merchant_score) = merchant_score {
+                score += MERCHANT_WEIGHT * merchant_score;
+                weights += MERCHANT_WEIGHT;
+            }
+
+            Some(MatchSuggestion {
+                transaction,
+                score: score / weights,
+                amount_score,
+                date_score,
+                merchant_score,
+            })
+        })
+        .collect();
+
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score));
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(money: Money, local_amount: Option<Money>) -> Transaction {
+        Transaction {
+            money,
+            local_amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn amount_score_compares_the_same_currency() {
+        let receipt = Money::new(1000, "GBP");
+
+        assert_eq!(
+            amount_score(&receipt, &transaction(Money::new(-1000, "GBP"), None)),
+            Some(1.0)
+        );
+        assert_eq!(
+            amount_score(&receipt, &transaction(Money::new(-1000, "JPY"), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn amount_score_uses_the_local_amount_for_receipts_from_abroad() {
+        let receipt = Money::new(1100, "EUR");
+        let paid = transaction(Money::new(-950, "GBP"), Some(Money::new(-1100, "EUR")));
+
+        assert_eq!(amount_score(&receipt, &paid), Some(1.0));
+    }
+
+    #[test]
+    fn amount_score_does_not_overflow() {
+        let receipt = Money::new(i64::MAX, "GBP");
+
+        assert_eq!(
+            amount_score(&receipt, &transaction(Money::new(i64::MIN, "GBP"), None)),
+            None
+        );
+        assert_eq!(
+            amount_score(&receipt, &transaction(Money::new(-1, "GBP"), None)),
+            None
+        );
+    }
+}
@@ -1,19 +1,63 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    sync::Arc,
+};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveTime, Utc};
 use sqlx::PgPool;
 
 use futures::stream::{self, StreamExt};
 
 use crate::{
     AppState,
-    db::{query_account_ids, upsert_account, upsert_transaction},
-    domain::{Account, Token, Transaction},
+    anomalies::{HISTORY_DAYS, RECENT_DAYS, detect_anomalies, odd_hours_timezone},
+    budgets::exceeded_budgets,
+    db::{
+        claim_due_monzo_webhooks, claim_due_webhook_deliveries, claim_receipt_for_sync,
+        delete_transaction, insert_alerts, insert_balance_snapshot, insert_duplicates,
+        insert_pot_balance_snapshot, insert_settlement, mark_receipt_synced, mark_receipts_removed,
+        mark_transaction_notes_synced, pair_settlement, preview_upsert_transaction, query_account,
+        query_account_ids, query_budgets, query_claim_by_id, query_claim_matching_reimbursement,
+        query_fx_rate, query_monzo_webhooks, query_monzo_webhooks_by_ids,
+        query_newer_processed_monzo_webhook, query_receipts, query_receipts_with_sync_status,
+        query_rules, query_settlement_by_transaction, query_settlements, query_shared_expenses,
+        query_shared_group_ids, query_splits, query_subscriptions, query_token_for_account,
+        query_transaction, query_transactions, query_transactions_between,
+        query_transactions_to_convert, query_transactions_with_pending_notes, query_user_group,
+        query_user_settings, record_budget_exceeded, record_monzo_webhook_attempt,
+        record_webhook_attempt, release_receipt_sync, release_stale_receipt_syncs,
+        replace_rule_assignments, replace_subscriptions, set_transaction_conversion,
+        set_webhook_delivery_payload, transition_claim_status, upsert_account, upsert_pot,
+        upsert_transaction,
+    },
+    domain::{
+        Account, Balance, BalanceSnapshot, CategoryTotal, Claim, ClaimAction, ClaimStatus, Debt,
+        DueWebhookDelivery, FieldChange, FxRate, Group, MonzoWebhookStatus, Pot, Receipt,
+        ReceiptSyncStatus, ReplayRequest, Settlement, Split, Subscription, Token, Transaction,
+        WebhookDeliveryStatus, WebhookReplay,
+    },
+    duplicates::{DuplicateCandidate, MAX_HOURS_APART, find_duplicates},
+    forecast::{Forecast, forecast},
+    fx::{BASE_CURRENCY, MAX_RATE_AGE_DAYS, convert_transaction},
+    household::{amount_owed, balances, simplify_debts},
+    money::{Money, MoneyError},
     monzo::{
-        WebhookResponse, delete_webhook, list_accounts, list_all_transactions, list_webhooks,
-        register_webhook as register_webhook_with_monzo,
+        DeletedTransactionRequest, MonzoEvent, TransactionRequest, WebhookRequest, WebhookResponse,
+        annotate_transaction, delete_webhook, deregister_attachment, get_balance, get_transaction,
+        list_accounts, list_all_transactions, list_pots, list_webhooks, pot_transfer_id,
+        register_attachment, register_webhook as register_webhook_with_monzo, upload_attachment,
+    },
+    rules::{compile_rules, evaluate},
+    splits::split_lines,
+    storage::{BlobStore, delete_blob, get_blob},
+    subscriptions::detect_subscriptions,
+    webhooks::{
+        DELIVERY_BATCH_SIZE, DELIVERY_CONCURRENCY, DELIVERY_HEADER, DELIVERY_LEASE_SECONDS,
+        DELIVERY_TIMEOUT_SECONDS, EVENT_HEADER, MAX_ATTEMPTS, SIGNATURE_HEADER, retry_delay, sign,
     },
 };
+use serde_json::{Value, json};
 
 pub async fn list_and_update_accounts(pool: &PgPool, token: &Token) -> Result<(), Box<dyn Error>> {
     let result = list_accounts(&token.access_token).await?;
@@ -38,6 +82,11 @@ pub async fn list_and_update_accounts(pool: &PgPool, token: &Token) -> Result<()
     Ok(())
 }
 
+/// Where Monzo sends an account's webhooks to.
+pub fn monzo_callback_url(base_url: &str, webhook_secret: &str) -> String {
+    format!("{}/api/monzo-callback/{}", base_url, webhook_secret)
+}
+
 pub async fn register_webhook(
     access_token: &str,
     account_id: &str,
@@ -56,11 +105,11 @@ pub async fn register_webhook(
             url: existing_url,
             account_id,
         }) => {
+            // The URL ends in the account's webhook secret, so it is left out of the logs.
             tracing::info!(
-                "Existing webhook found for account_id={}, id={}, url={}",
+                "Existing webhook found for account_id={}, id={}",
                 &account_id,
-                &id,
-                &existing_url
+                &id
             );
             if url != existing_url {
                 delete_webhook(access_token, id).await?;
@@ -75,17 +124,16 @@ pub async fn register_webhook(
     Ok(())
 }
 
+/// Parses a date from Monzo, which sends an empty string for dates it does not have yet. Dates
+/// it cannot parse are logged and treated the same.
 pub fn parse_monzo_date(date_str: &str) -> Option<DateTime<Utc>> {
-    if date_str == "" {
-        None
-    } else {
-        Some(
-            DateTime::parse_from_rfc3339(date_str)
-                .inspect_err(|err| tracing::error!("Error parsing date: {}", err))
-                .unwrap()
-                .to_utc(),
-        )
+    if date_str.is_empty() {
+        return None;
     }
+    DateTime::parse_from_rfc3339(date_str)
+        .inspect_err(|err| tracing::error!("Error parsing date {}: {}", date_str, err))
+        .ok()
+        .map(|date| date.to_utc())
 }
 
 pub async fn list_and_update_transactions(
@@ -119,33 +167,783 @@ pub async fn list_and_update_transactions(
         .into_iter()
         .filter(|(_, res)| res.is_ok())
         .map(|(account_id, res)| (account_id, res.unwrap()))
-        .map(|(account_id, responses)| -> Vec<_> {
+        .flat_map(|(account_id, responses)| -> Vec<_> {
             responses
                 .iter()
-                .map(|res| Transaction {
-                    id: res.id.clone(),
-                    account_id: account_id.clone(),
-                    amount: res.amount.clone(),
-                    currency: res.currency.clone(),
-                    description: res.description.clone(),
-                    notes: res.notes.clone(),
-                    merchant: res.merchant.clone(),
-                    category: res.category.clone(),
-                    created: parse_monzo_date(&res.created).unwrap(),
-                    settled: parse_monzo_date(&res.settled),
+                .filter_map(|res| {
+                    Some(Transaction {
+                        id: res.id.clone(),
+                        account_id: account_id.clone(),
+                        money: Money::new(res.amount, &res.currency),
+                        description: res.description.clone(),
+                        notes: res.notes.clone(),
+                        merchant: res.merchant.clone(),
+                        counterparty_user_id: res.counterparty.user_id.clone(),
+                        counterparty_name: res.counterparty.name.clone(),
+                        pot_id: pot_transfer_id(&res.metadata, &res.description),
+                        local_amount: res
+                            .local_amount
+                            .zip(res.local_currency.as_ref())
+                            .map(|(amount, currency)| Money::new(amount, currency)),
+                        category: res.category.clone(),
+                        created: parse_monzo_date(&res.created)?,
+                        settled: parse_monzo_date(&res.settled),
+                        ..Default::default()
+                    })
                 })
                 .collect()
         })
-        .flatten()
         .collect();
 
     tracing::info!("Upserting {} transactions...", transactions.len());
 
-    stream::iter(transactions)
-        .map(async |transaction| upsert_transaction(pool, &transaction).await)
-        .buffered(1)
-        .collect::<Vec<_>>()
-        .await;
+    for transaction in transactions.iter() {
+        let _ = upsert_transaction(pool, transaction).await;
+    }
+
+    for transaction in transactions
+        .iter()
+        .filter(|transaction| transaction.counterparty_user_id.is_some())
+    {
+        if let Err(err) = record_settlement(pool, transaction).await {
+            tracing::error!(
+                "Error recording settlement for transaction id={}: {}",
+                &transaction.id,
+                err
+            );
+        }
+    }
+
+    apply_rules(pool, &token.user_id, &transactions).await?;
+    convert_user_transactions(pool, &token.user_id).await?;
+    detect_user_duplicates(pool, &token.user_id).await?;
+    detect_recent_anomalies(pool, &token.user_id).await?;
+
+    Ok(())
+}
+
+/// Stores the pots of each of the user's accounts and records their balances.
+pub async fn list_and_update_pots(pool: &PgPool, token: &Token) -> Result<(), Box<dyn Error>> {
+    for account_id in query_account_ids(pool, &token.user_id).await?.iter() {
+        let pots = list_pots(&token.access_token, account_id).await?;
+        tracing::info!("Found {} pots for account_id={}", pots.len(), account_id);
+        for pot_response in pots.into_iter() {
+            let pot = Pot {
+                id: pot_response.id,
+                account_id: account_id.clone(),
+                name: pot_response.name,
+                balance: Money::new(pot_response.balance, &pot_response.currency),
+                goal_amount: pot_response
+                    .goal_amount
+                    .map(|amount| Money::new(amount, &pot_response.currency)),
+                deleted: pot_response.deleted,
+                created: DateTime::parse_from_rfc3339(&pot_response.created)?.to_utc(),
+                updated: Utc::now(),
+            };
+            upsert_pot(pool, &pot).await?;
+            if !pot.deleted {
+                insert_pot_balance_snapshot(pool, &pot).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the current balance of each of the user's accounts.
+pub async fn snapshot_balances(pool: &PgPool, token: &Token) -> Result<(), Box<dyn Error>> {
+    for account_id in query_account_ids(pool, &token.user_id).await?.iter() {
+        let balance = get_balance(&token.access_token, account_id).await?;
+        insert_balance_snapshot(
+            pool,
+            &BalanceSnapshot {
+                id: 0,
+                account_id: account_id.clone(),
+                balance: Money::new(balance.balance, &balance.currency),
+                total_balance: Money::new(balance.total_balance, &balance.currency),
+                spend_today: Money::new(balance.spend_today, &balance.currency),
+                created: Utc::now(),
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Converts transactions to the user's reporting currency and records the rate used. Rates are
+/// looked up once per currency and day.
+pub async fn convert_transactions(
+    pool: &PgPool,
+    user_id: &str,
+    transactions: &[Transaction],
+) -> Result<usize, Box<dyn Error>> {
+    let settings = query_user_settings(pool, user_id).await?;
+    let reporting_currency = &settings.reporting_currency;
+
+    let mut rates: HashMap<(String, NaiveDate), Option<FxRate>> = HashMap::new();
+    let mut converted = 0;
+    for transaction in transactions.iter() {
+        let date = transaction.created.date_naive();
+        let mut euro_rates = vec![];
+        for currency in [&transaction.money.currency, reporting_currency] {
+            let rate = match rates.get(&(currency.clone(), date)) {
+                Some(rate) => rate.clone(),
+                None if currency == BASE_CURRENCY => Some(FxRate {
+                    date,
+                    currency: currency.clone(),
+                    rate: 1.0,
+                }),
+                None => {
+                    let rate =
+                        query_fx_rate(pool, currency, date, Duration::days(MAX_RATE_AGE_DAYS))
+                            .await?;
+                    rates.insert((currency.clone(), date), rate.clone());
+                    rate
+                }
+            };
+            euro_rates.push(rate);
+        }
+
+        let conversion = convert_transaction(
+            transaction,
+            reporting_currency,
+            euro_rates[0].as_ref(),
+            euro_rates[1].as_ref(),
+        );
+        if conversion.is_some() {
+            converted += 1;
+        }
+        set_transaction_conversion(pool, &transaction.id, conversion.as_ref()).await?;
+    }
+
+    Ok(converted)
+}
+
+pub async fn convert_to_reporting_currency(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    let account = query_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", &transaction.account_id))?;
+
+    convert_transactions(pool, &account.user_id, std::slice::from_ref(transaction)).await?;
+
+    Ok(())
+}
+
+/// Converts any of the user's transactions that have not been converted to their reporting
+/// currency yet, e.g. because the rates for them were only just imported.
+pub async fn convert_user_transactions(pool: &PgPool, user_id: &str) -> Result<(), Box<dyn Error>> {
+    let settings = query_user_settings(pool, user_id).await?;
+    let transactions =
+        query_transactions_to_convert(pool, user_id, &settings.reporting_currency).await?;
+    if transactions.is_empty() {
+        return Ok(());
+    }
+
+    let converted = convert_transactions(pool, user_id, &transactions).await?;
+
+    tracing::info!(
+        "Converted {} of {} transactions to {} for user_id={}",
+        converted,
+        transactions.len(),
+        &settings.reporting_currency,
+        user_id
+    );
+
+    Ok(())
+}
+
+/// Evaluates the user's rules against each transaction and stores the resulting category and tag
+/// assignments. Returns the number of transactions that matched at least one rule.
+pub async fn apply_rules(
+    pool: &PgPool,
+    user_id: &str,
+    transactions: &[Transaction],
+) -> Result<usize, Box<dyn Error>> {
+    let rules = compile_rules(query_rules(pool, user_id).await?);
+
+    tracing::info!(
+        "Applying {} rules to {} transactions for user_id={}",
+        rules.len(),
+        transactions.len(),
+        user_id
+    );
+
+    let mut matched = 0;
+    for transaction in transactions.iter() {
+        let outcome = evaluate(&rules, transaction);
+        if outcome.category.is_some() || !outcome.tags.is_empty() {
+            matched += 1;
+        }
+        replace_rule_assignments(pool, &transaction.id, &outcome).await?;
+    }
+
+    Ok(matched)
+}
+
+pub async fn apply_rules_to_transaction(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    let account = query_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", &transaction.account_id))?;
+
+    apply_rules(pool, &account.user_id, std::slice::from_ref(transaction)).await?;
+
+    Ok(())
+}
+
+/// Marks the matching outstanding claim as paid when `transaction` is the credit reimbursing it.
+pub async fn reconcile_reimbursement(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    if transaction.money.amount <= 0 {
+        return Ok(());
+    }
+
+    let account = query_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", &transaction.account_id))?;
+
+    let claim =
+        query_claim_matching_reimbursement(pool, &account.user_id, &transaction.money).await?;
+
+    if let Some(claim) = claim {
+        let status = claim
+            .status
+            .transition(ClaimAction::Reconcile)
+            .ok_or_else(|| {
+                format!(
+                    "Claim id={} cannot be reconciled from status={:?}",
+                    claim.id, claim.status
+                )
+            })?;
+        tracing::info!(
+            "Reconciling claim id={} with reimbursement transaction id={}",
+            claim.id,
+            &transaction.id
+        );
+        transition_claim_status(
+            pool,
+            claim.id,
+            claim.status,
+            status,
+            None,
+            Some(&format!(
+                "Reconciled with reimbursement transaction id={}",
+                &transaction.id
+            )),
+            Some(&transaction.id),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Works out the user's subscriptions again from all of their transactions.
+pub async fn detect_user_subscriptions(
+    pool: &PgPool,
+    user_id: &str,
+) -> Result<Vec<Subscription>, Box<dyn Error>> {
+    let account_ids = query_account_ids(pool, user_id).await?;
+    let transactions = query_transactions(pool, &account_ids).await?;
+
+    let subscriptions = detect_subscriptions(user_id, &transactions, Utc::now().date_naive());
+
+    tracing::info!(
+        "Detected {} subscriptions in {} transactions for user_id={}",
+        subscriptions.len(),
+        transactions.len(),
+        user_id
+    );
+
+    replace_subscriptions(pool, user_id, &subscriptions).await?;
+
+    Ok(query_subscriptions(pool, user_id).await?)
+}
+
+/// Raises alerts for anything unusual about a transaction that has just arrived.
+pub async fn detect_transaction_anomalies(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    let account = query_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", &transaction.account_id))?;
+    let account_ids = query_account_ids(pool, &account.user_id).await?;
+    let settings = query_user_settings(pool, &account.user_id).await?;
+    let history = query_transactions_between(
+        pool,
+        &account_ids,
+        transaction.created - Duration::days(HISTORY_DAYS),
+        transaction.created,
+        false,
+    )
+    .await?;
+
+    let anomalies = detect_anomalies(
+        transaction,
+        &history,
+        &odd_hours_timezone(&settings.odd_hours_timezone),
+    );
+    let raised = insert_alerts(pool, &account.user_id, &transaction.id, &anomalies).await?;
+    if raised > 0 {
+        tracing::info!(
+            "Raised {} alerts for transaction id={}",
+            raised,
+            &transaction.id
+        );
+    }
+
+    Ok(())
+}
+
+/// Reports each of the user's budgets that the month of a transaction that has just arrived has
+/// gone over. A budget is only reported once a month.
+pub async fn check_transaction_budgets(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    let account = query_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", &transaction.account_id))?;
+    let budgets = query_budgets(pool, &account.user_id).await?;
+    if budgets.is_empty() {
+        return Ok(());
+    }
+
+    let month = transaction
+        .created
+        .date_naive()
+        .with_day(1)
+        .ok_or("Every month has a first day")?;
+    let next_month = month
+        .checked_add_months(Months::new(1))
+        .ok_or("The month after is out of range")?;
+    let account_ids = query_account_ids(pool, &account.user_id).await?;
+    let transactions = query_transactions_between(
+        pool,
+        &account_ids,
+        month.and_time(NaiveTime::MIN).and_utc(),
+        next_month.and_time(NaiveTime::MIN).and_utc(),
+        false,
+    )
+    .await?;
+    let split_ids: Vec<String> = transactions
+        .iter()
+        .filter(|transaction| transaction.is_split)
+        .map(|transaction| transaction.id.clone())
+        .collect();
+    let splits = if split_ids.is_empty() {
+        vec![]
+    } else {
+        query_splits(pool, &split_ids).await?
+    };
+    let lines = split_lines(transactions, &splits)?;
+
+    for (budget, spent) in exceeded_budgets(&budgets, &lines)? {
+        if record_budget_exceeded(pool, budget, month, &spent).await? {
+            tracing::info!(
+                "Budget id={} for category={} was exceeded in {}",
+                budget.id,
+                &budget.category,
+                month.format("%Y-%m")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Raises alerts for the user's recent transactions, which covers any whose webhook never came.
+/// Alerts already raised are not raised again.
+pub async fn detect_recent_anomalies(pool: &PgPool, user_id: &str) -> Result<(), Box<dyn Error>> {
+    let account_ids = query_account_ids(pool, user_id).await?;
+    let settings = query_user_settings(pool, user_id).await?;
+    let timezone = odd_hours_timezone(&settings.odd_hours_timezone);
+    let now = Utc::now();
+    let recent = now - Duration::days(RECENT_DAYS);
+    let history = query_transactions_between(
+        pool,
+        &account_ids,
+        recent - Duration::days(HISTORY_DAYS),
+        now,
+        false,
+    )
+    .await?;
+
+    let mut raised = 0;
+    for transaction in history
+        .iter()
+        .filter(|transaction| transaction.created >= recent)
+    {
+        let anomalies = detect_anomalies(transaction, &history, &timezone);
+        raised += insert_alerts(pool, user_id, &transaction.id, &anomalies).await?;
+    }
+
+    tracing::info!("Raised {} new alerts for user_id={}", raised, user_id);
+
+    Ok(())
+}
+
+/// Flags probable duplicates among all of the user's transactions. Returns how many pairs were
+/// newly flagged.
+pub async fn detect_user_duplicates(pool: &PgPool, user_id: &str) -> Result<u64, Box<dyn Error>> {
+    let account_ids = query_account_ids(pool, user_id).await?;
+    let transactions = query_transactions(pool, &account_ids).await?;
+
+    let flagged = insert_duplicates(pool, &find_duplicates(&transactions)).await?;
+
+    tracing::info!(
+        "Flagged {} new duplicates in {} transactions for user_id={}",
+        flagged,
+        transactions.len(),
+        user_id
+    );
+
+    Ok(flagged)
+}
+
+/// Flags probable duplicates of a transaction that has just arrived, among the transactions on
+/// its account around the same time.
+pub async fn detect_transaction_duplicates(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    let window = Duration::hours(MAX_HOURS_APART + 1);
+    let transactions = query_transactions_between(
+        pool,
+        &vec![transaction.account_id.clone()],
+        transaction.created - window,
+        transaction.created + window,
+        false,
+    )
+    .await?;
+
+    let duplicates: Vec<DuplicateCandidate> = find_duplicates(&transactions)
+        .into_iter()
+        .filter(|duplicate| {
+            duplicate.transaction_id == transaction.id || duplicate.duplicate_id == transaction.id
+        })
+        .collect();
+    let flagged = insert_duplicates(pool, &duplicates).await?;
+    if flagged > 0 {
+        tracing::info!(
+            "Flagged {} duplicates of transaction id={}",
+            flagged,
+            &transaction.id
+        );
+    }
+
+    Ok(())
+}
+
+/// Projects the account's balance forward from what Monzo says it is now, using the recurring
+/// payments detected for its owner.
+pub async fn forecast_account(
+    pool: &PgPool,
+    account: &Account,
+) -> Result<Forecast, Box<dyn Error>> {
+    let token = find_account_token(pool, &account.id).await?;
+    let balance = get_balance(&token.access_token, &account.id).await?;
+    let subscriptions = query_subscriptions(pool, &account.user_id).await?;
+
+    Ok(forecast(
+        &account.id,
+        &Money::new(balance.balance, &balance.currency),
+        Utc::now().date_naive(),
+        &subscriptions,
+    )
+    .ok_or_else(|| format!("Unable to forecast account_id={}", &account.id))?)
+}
+
+/// How far apart the two sides of a payment between members may arrive and still be paired.
+const SETTLEMENT_WINDOW_DAYS: i64 = 3;
+
+/// Works out the balances of a group's members and the payments that would settle them up.
+pub async fn group_balances(
+    pool: &PgPool,
+    group: &Group,
+) -> Result<(Vec<Balance>, Vec<Debt>), Box<dyn Error>> {
+    let expenses = query_shared_expenses(pool, group.id).await?;
+    let settlements = query_settlements(pool, group.id).await?;
+
+    let balances = balances(&group.members, &expenses, &settlements)?;
+    let debts = simplify_debts(&balances);
+
+    Ok((balances, debts))
+}
+
+/// Records a payment to or from another Monzo user as a settlement, if both users are in a group
+/// together. Each side of the payment arrives as its own transaction, so the second one is paired
+/// with the settlement recorded for the first rather than counted twice.
+pub async fn record_settlement(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<Option<Settlement>, Box<dyn Error>> {
+    let Some(counterparty_user_id) = &transaction.counterparty_user_id else {
+        return Ok(None);
+    };
+    if transaction.money.amount == 0 {
+        return Ok(None);
+    }
+    if query_settlement_by_transaction(pool, &transaction.id)
+        .await?
+        .is_some()
+    {
+        return Ok(None);
+    }
+
+    let account = query_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", &transaction.account_id))?;
+
+    let outgoing = transaction.money.amount < 0;
+    let (from_user_id, to_user_id) = if outgoing {
+        (account.user_id.clone(), counterparty_user_id.clone())
+    } else {
+        (counterparty_user_id.clone(), account.user_id.clone())
+    };
+    let mut settlement = Settlement {
+        id: 0,
+        group_id: 0,
+        from_user_id,
+        to_user_id,
+        money: Money::new(transaction.money.amount.abs(), &transaction.money.currency),
+        payer_transaction_id: outgoing.then(|| transaction.id.clone()),
+        payee_transaction_id: (!outgoing).then(|| transaction.id.clone()),
+        created: transaction.created,
+    };
+
+    if let Some(paired) =
+        pair_settlement(pool, &settlement, Duration::days(SETTLEMENT_WINDOW_DAYS)).await?
+    {
+        tracing::info!(
+            "Paired transaction id={} with settlement id={}",
+            &transaction.id,
+            paired.id
+        );
+        return Ok(Some(paired));
+    }
+
+    let group_ids =
+        query_shared_group_ids(pool, &settlement.from_user_id, &settlement.to_user_id).await?;
+    if group_ids.is_empty() {
+        return Ok(None);
+    }
+
+    // With more than one group in common, the payment most likely settles the largest debt.
+    let mut best = (group_ids[0], 0);
+    for group_id in group_ids.into_iter() {
+        let Some(group) = query_user_group(pool, &settlement.from_user_id, group_id).await? else {
+            continue;
+        };
+        let (_, debts) = group_balances(pool, &group).await?;
+        let owed = amount_owed(
+            &debts,
+            &settlement.from_user_id,
+            &settlement.to_user_id,
+            &settlement.money.currency,
+        );
+        if owed > best.1 {
+            best = (group_id, owed);
+        }
+    }
+    settlement.group_id = best.0;
+
+    let settlement = insert_settlement(pool, &settlement).await?;
+
+    tracing::info!(
+        "Recorded settlement id={} in group id={} for transaction id={}",
+        settlement.id,
+        settlement.group_id,
+        &transaction.id
+    );
+
+    Ok(Some(settlement))
+}
+
+/// Sums transactions per category and currency, preferring the custom category over Monzo's and
+/// the amount in the reporting currency once the transaction has been converted. Pot transfers are
+/// left out, as moving money into a pot is not spending it, and so are merged duplicates.
+pub fn category_totals(transactions: &[Transaction]) -> Result<Vec<CategoryTotal>, MoneyError> {
+    let mut totals: Vec<CategoryTotal> = vec![];
+    for transaction in transactions
+        .iter()
+        .filter(|transaction| transaction.pot_id.is_none() && transaction.duplicate_of.is_none())
+    {
+        let category = transaction
+            .custom_category
+            .as_ref()
+            .unwrap_or(&transaction.category);
+        let amount = transaction
+            .reporting_amount
+            .clone()
+            .unwrap_or(transaction.money.clone());
+        match totals
+            .iter_mut()
+            .find(|total| &total.category == category && total.total.currency == amount.currency)
+        {
+            Some(total) => total.total = total.total.checked_add(&amount)?,
+            None => totals.push(CategoryTotal {
+                category: category.clone(),
+                total: amount,
+            }),
+        }
+    }
+    totals.sort_by(|a, b| (&a.category, &a.total.currency).cmp(&(&b.category, &b.total.currency)));
+    Ok(totals)
+}
+
+async fn find_account_token(pool: &PgPool, account_id: &str) -> Result<Token, Box<dyn Error>> {
+    Ok(query_token_for_account(pool, account_id)
+        .await?
+        .ok_or_else(|| format!("No token for account_id={}", account_id))?)
+}
+
+/// Pushes the locally edited notes of a transaction to Monzo.
+pub async fn push_transaction_notes(
+    pool: &PgPool,
+    transaction: &Transaction,
+) -> Result<(), Box<dyn Error>> {
+    let token = find_account_token(pool, &transaction.account_id).await?;
+
+    annotate_transaction(&token.access_token, &transaction.id, &transaction.notes).await?;
+    mark_transaction_notes_synced(pool, &transaction.id, &transaction.notes).await?;
+
+    tracing::info!("Pushed notes for transaction id={}", &transaction.id);
+
+    Ok(())
+}
+
+/// Uploads a receipt to Monzo and attaches it to its transaction. The receipt is claimed first, so
+/// a receipt that another push is already uploading is left alone.
+pub async fn push_receipt(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    receipt: &Receipt,
+) -> Result<(), Box<dyn Error>> {
+    let Some(receipt) = claim_receipt_for_sync(pool, receipt.id).await? else {
+        tracing::info!("Receipt id={} is not pending a push, skipping", receipt.id);
+        return Ok(());
+    };
+
+    // The error is not Send, so it is turned into a message before releasing the claim.
+    if let Err(err) = push_claimed_receipt(pool, blob_store, &receipt)
+        .await
+        .map_err(|err| err.to_string())
+    {
+        release_receipt_sync(pool, receipt.id).await?;
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+async fn push_claimed_receipt(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    receipt: &Receipt,
+) -> Result<(), Box<dyn Error>> {
+    let transaction_id = receipt
+        .transaction_id
+        .as_ref()
+        .ok_or_else(|| format!("Receipt id={} is not matched to a transaction", receipt.id))?;
+    let transaction = query_transaction(pool, transaction_id)
+        .await?
+        .ok_or_else(|| format!("Unknown transaction id={}", transaction_id))?;
+    let token = find_account_token(pool, &transaction.account_id).await?;
+
+    let data = get_blob(blob_store, &receipt.storage_key).await?;
+    let file_url = upload_attachment(
+        &token.access_token,
+        &receipt.file_name,
+        &receipt.content_type,
+        data,
+    )
+    .await?;
+    let attachment = register_attachment(
+        &token.access_token,
+        &transaction.id,
+        &file_url,
+        &receipt.content_type,
+    )
+    .await?;
+
+    mark_receipt_synced(pool, receipt.id, &attachment.id).await?;
+
+    tracing::info!(
+        "Pushed receipt id={} to transaction id={} as attachment id={}",
+        receipt.id,
+        &transaction.id,
+        &attachment.id
+    );
+
+    Ok(())
+}
+
+/// Removes the Monzo attachment of a receipt that is being deleted locally.
+pub async fn remove_receipt_from_monzo(
+    pool: &PgPool,
+    receipt: &Receipt,
+) -> Result<(), Box<dyn Error>> {
+    let (Some(attachment_id), Some(transaction_id)) =
+        (&receipt.monzo_attachment_id, &receipt.transaction_id)
+    else {
+        return Ok(());
+    };
+    let transaction = query_transaction(pool, transaction_id)
+        .await?
+        .ok_or_else(|| format!("Unknown transaction id={}", transaction_id))?;
+    let token = find_account_token(pool, &transaction.account_id).await?;
+
+    deregister_attachment(&token.access_token, attachment_id).await?;
+
+    Ok(())
+}
+
+/// How long a receipt can stay claimed by a push before it is assumed to have been abandoned.
+const RECEIPT_SYNC_TIMEOUT_MINUTES: i64 = 15;
+
+/// Retries pushing every note and receipt that has not made it to Monzo yet.
+pub async fn sync_to_monzo(pool: &PgPool, blob_store: &BlobStore) -> Result<(), Box<dyn Error>> {
+    release_stale_receipt_syncs(
+        pool,
+        Utc::now() - Duration::minutes(RECEIPT_SYNC_TIMEOUT_MINUTES),
+    )
+    .await?;
+
+    let transactions = query_transactions_with_pending_notes(pool).await?;
+    let receipts = query_receipts_with_sync_status(pool, ReceiptSyncStatus::Pending).await?;
+
+    tracing::info!(
+        "Syncing notes for {} transactions and {} receipts to Monzo",
+        transactions.len(),
+        receipts.len()
+    );
+
+    for transaction in transactions.iter() {
+        if let Err(err) = push_transaction_notes(pool, transaction).await {
+            tracing::error!(
+                "Error pushing notes for transaction id={}: {}",
+                &transaction.id,
+                err
+            );
+        }
+    }
+
+    for receipt in receipts.iter() {
+        if let Err(err) = push_receipt(pool, blob_store, receipt).await {
+            tracing::error!("Error pushing receipt id={}: {}", receipt.id, err);
+        }
+    }
+
     Ok(())
 }
 
@@ -153,3 +951,773 @@ pub async fn initial_load_data(state: Arc<AppState>, token: Token) -> () {
     tracing::info!("Loading initial data for user_id={}", &token.user_id);
     let _ = list_and_update_accounts(&state.pool, &token).await;
 }
+
+/// Sends a due webhook delivery and records how it went. Transaction events get the transaction
+/// as it is when first sent.
+async fn send_webhook(
+    pool: &PgPool,
+    client: &reqwest::Client,
+    due: &DueWebhookDelivery,
+) -> Result<(), Box<dyn Error>> {
+    let delivery = &due.delivery;
+    let payload = match (&delivery.payload, &delivery.transaction_id) {
+        (Some(payload), _) => Some(payload.clone()),
+        (None, Some(transaction_id)) => match query_transaction(pool, transaction_id).await? {
+            Some(transaction) => {
+                let payload = serde_json::to_value(&transaction)?;
+                set_webhook_delivery_payload(pool, delivery.id, &payload).await?;
+                Some(payload)
+            }
+            None => None,
+        },
+        (None, None) => None,
+    };
+    let Some(payload) = payload else {
+        record_webhook_attempt(
+            pool,
+            delivery.id,
+            WebhookDeliveryStatus::Failed,
+            None,
+            Some("There is nothing left to send"),
+            Utc::now(),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let body = serde_json::to_vec(&json!({
+        "id": delivery.id,
+        "event": delivery.event,
+        "created": delivery.created,
+        "data": payload,
+    }))?;
+    let signature = sign(&due.secret, Utc::now().timestamp(), &body);
+    let response = client
+        .post(&due.url)
+        .timeout(std::time::Duration::from_secs(DELIVERY_TIMEOUT_SECONDS))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(SIGNATURE_HEADER, signature)
+        .body(body)
+        .send()
+        .await;
+
+    let (response_status, error) = match response {
+        Ok(response) if response.status().is_success() => (Some(response.status()), None),
+        Ok(response) => (
+            Some(response.status()),
+            Some(format!("The endpoint responded with {}", response.status())),
+        ),
+        Err(err) => (err.status(), Some(err.to_string())),
+    };
+    let attempts = delivery.attempts + 1;
+    let status = if error.is_none() {
+        WebhookDeliveryStatus::Delivered
+    } else if attempts >= MAX_ATTEMPTS {
+        WebhookDeliveryStatus::Failed
+    } else {
+        WebhookDeliveryStatus::Pending
+    };
+    if let Some(error) = &error {
+        tracing::warn!(
+            "Webhook delivery id={} to {} failed on attempt {}: {}",
+            delivery.id,
+            &due.url,
+            attempts,
+            error
+        );
+    }
+
+    record_webhook_attempt(
+        pool,
+        delivery.id,
+        status,
+        response_status.map(|status| status.as_u16().into()),
+        error.as_deref(),
+        Utc::now() + retry_delay(attempts),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sends the webhook deliveries that are due, batch by batch until none are left.
+pub async fn deliver_webhooks(pool: &PgPool) -> Result<(), Box<dyn Error>> {
+    let client = reqwest::Client::new();
+    loop {
+        let due = claim_due_webhook_deliveries(
+            pool,
+            DELIVERY_BATCH_SIZE,
+            Duration::seconds(DELIVERY_LEASE_SECONDS),
+        )
+        .await?;
+        if !due.is_empty() {
+            tracing::info!("Sending {} webhook deliveries", due.len());
+        }
+
+        stream::iter(due.iter())
+            .for_each_concurrent(DELIVERY_CONCURRENCY, async |delivery| {
+                if let Err(err) = send_webhook(pool, &client, delivery).await {
+                    tracing::error!(
+                        "Error sending webhook delivery id={}: {}",
+                        delivery.delivery.id,
+                        err
+                    );
+                }
+            })
+            .await;
+
+        if (due.len() as i64) < DELIVERY_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// How many Monzo webhooks are taken to process at a time.
+const MONZO_WEBHOOK_BATCH_SIZE: i64 = 50;
+/// How long taken webhooks are held back from other instances, long enough for a batch to be
+/// processed.
+const MONZO_WEBHOOK_LEASE_SECONDS: i64 = 15 * 60;
+/// Webhooks that still fail after this many attempts are left for someone to look at.
+const MONZO_WEBHOOK_ATTEMPTS: i32 = 5;
+/// How many inbox entries with a status are replayed at most.
+const MAX_REPLAYED_WEBHOOKS: i64 = 1000;
+
+/// How a Monzo webhook is being processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProcessingMode {
+    /// As it arrives.
+    Live,
+    /// Again, reporting what it changed.
+    Replay,
+    /// Reporting what it would change, without changing anything.
+    DryRun,
+}
+
+/// What processing a Monzo webhook did to the transactions.
+struct WebhookOutcome {
+    status: MonzoWebhookStatus,
+    transaction_id: Option<String>,
+    changes: Vec<FieldChange>,
+}
+
+/// The fields that differ between a transaction before and after a change, as they are shown in
+/// the API.
+fn transaction_changes(
+    before: Option<&Transaction>,
+    after: Option<&Transaction>,
+) -> Result<Vec<FieldChange>, serde_json::Error> {
+    let before = serde_json::to_value(before)?;
+    let after = serde_json::to_value(after)?;
+    let fields: BTreeSet<&String> = [&before, &after]
+        .into_iter()
+        .filter_map(Value::as_object)
+        .flat_map(|fields| fields.keys())
+        .collect();
+
+    Ok(fields
+        .into_iter()
+        .filter_map(|field| {
+            let before = before.get(field).unwrap_or(&Value::Null);
+            let after = after.get(field).unwrap_or(&Value::Null);
+            (before != after).then(|| FieldChange {
+                field: field.clone(),
+                before: before.clone(),
+                after: after.clone(),
+            })
+        })
+        .collect())
+}
+
+/// Gets a transaction Monzo sent us from Monzo itself, so what is stored is what Monzo has rather
+/// than what the webhook said.
+async fn refetch_monzo_transaction(
+    pool: &PgPool,
+    transaction: &TransactionRequest,
+) -> Result<TransactionRequest, Box<dyn Error>> {
+    let token = query_token_for_account(pool, &transaction.account_id)
+        .await?
+        .ok_or_else(|| {
+            format!(
+                "There is no token to confirm transactions for account_id={}",
+                &transaction.account_id
+            )
+        })?;
+
+    let fetched = get_transaction(&token.access_token, &transaction.id).await?;
+    if fetched.account_id != transaction.account_id {
+        return Err(format!(
+            "Monzo has transaction id={} on another account",
+            &transaction.id
+        )
+        .into());
+    }
+
+    Ok(fetched)
+}
+
+/// Stores the transaction a Monzo transaction webhook is about, and does everything that follows
+/// from a new or changed transaction.
+async fn process_transaction_event(
+    pool: &PgPool,
+    refetch: bool,
+    account_id: &str,
+    transaction: TransactionRequest,
+    updated: bool,
+    mode: ProcessingMode,
+) -> Result<WebhookOutcome, Box<dyn Error>> {
+    if transaction.account_id != account_id {
+        return Err(format!(
+            "The webhook for account_id={} cannot send transactions for account_id={}",
+            account_id, &transaction.account_id
+        )
+        .into());
+    }
+
+    let transaction = if refetch {
+        refetch_monzo_transaction(pool, &transaction).await?
+    } else {
+        transaction
+    };
+
+    let attachments = transaction.attachments;
+    let pot_id = pot_transfer_id(&transaction.metadata, &transaction.description);
+    let created = parse_monzo_date(&transaction.created).ok_or_else(|| {
+        format!(
+            "Transaction id={} has an invalid created date {}",
+            &transaction.id, &transaction.created
+        )
+    })?;
+    let transaction = Transaction {
+        id: transaction.id,
+        account_id: transaction.account_id,
+        money: Money::new(transaction.amount, &transaction.currency),
+        description: transaction.description,
+        notes: transaction.notes,
+        merchant_name: transaction
+            .merchant
+            .as_ref()
+            .map(|merchant| merchant.name.clone()),
+        merchant: transaction.merchant.map(|merchant| merchant.id),
+        counterparty_user_id: transaction.counterparty.user_id,
+        counterparty_name: transaction.counterparty.name,
+        pot_id,
+        local_amount: transaction
+            .local_amount
+            .zip(transaction.local_currency)
+            .map(|(amount, currency)| Money::new(amount, &currency)),
+        category: transaction.category,
+        created,
+        settled: parse_monzo_date(&transaction.settled),
+        ..Default::default()
+    };
+
+    let before = match mode {
+        ProcessingMode::Live => None,
+        ProcessingMode::Replay | ProcessingMode::DryRun => {
+            query_transaction(pool, &transaction.id).await?
+        }
+    };
+    if mode == ProcessingMode::DryRun {
+        let after = preview_upsert_transaction(pool, &transaction).await?;
+        return Ok(WebhookOutcome {
+            status: MonzoWebhookStatus::Processed,
+            transaction_id: Some(transaction.id),
+            changes: transaction_changes(before.as_ref(), after.as_ref())?,
+        });
+    }
+
+    upsert_transaction(pool, &transaction).await?;
+
+    let changes = match mode {
+        ProcessingMode::Live => vec![],
+        ProcessingMode::Replay | ProcessingMode::DryRun => {
+            let after = query_transaction(pool, &transaction.id).await?;
+            transaction_changes(before.as_ref(), after.as_ref())?
+        }
+    };
+
+    // Attachments deleted in the Monzo app should not be uploaded again from here. Payloads that
+    // leave out the attachments say nothing about them, so those are not reconciled.
+    if updated && let Some(attachments) = attachments {
+        let attachment_ids: Vec<String> = attachments
+            .into_iter()
+            .map(|attachment| attachment.id)
+            .collect();
+        mark_receipts_removed(pool, &transaction.id, &attachment_ids).await?;
+    }
+
+    if let Err(err) = apply_rules_to_transaction(pool, &transaction).await {
+        tracing::error!(
+            "Error applying rules to transaction id={}: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    if let Err(err) = convert_to_reporting_currency(pool, &transaction).await {
+        tracing::error!(
+            "Error converting transaction id={} to the reporting currency: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    if let Err(err) = detect_transaction_anomalies(pool, &transaction).await {
+        tracing::error!(
+            "Error checking transaction id={} for anomalies: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    if let Err(err) = check_transaction_budgets(pool, &transaction).await {
+        tracing::error!(
+            "Error checking budgets for transaction id={}: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    if let Err(err) = detect_transaction_duplicates(pool, &transaction).await {
+        tracing::error!(
+            "Error detecting duplicates of transaction id={}: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    if let Err(err) = reconcile_reimbursement(pool, &transaction).await {
+        tracing::error!(
+            "Error reconciling reimbursement for transaction id={}: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    if let Err(err) = record_settlement(pool, &transaction).await {
+        tracing::error!(
+            "Error recording settlement for transaction id={}: {}",
+            &transaction.id,
+            err
+        );
+    }
+
+    Ok(WebhookOutcome {
+        status: MonzoWebhookStatus::Processed,
+        transaction_id: Some(transaction.id),
+        changes,
+    })
+}
+
+/// The claims past being a draft that a transaction or one of its splits is on. Their totals must
+/// not change under whoever is approving or paying them.
+fn locked_claim_ids(transaction: &Transaction, splits: &[Split], claims: &[Claim]) -> Vec<i64> {
+    claims
+        .iter()
+        .filter(|claim| claim.status != ClaimStatus::Draft)
+        .filter(|claim| {
+            transaction.claim_id == Some(claim.id)
+                || splits.iter().any(|split| split.claim_id == Some(claim.id))
+        })
+        .map(|claim| claim.id)
+        .collect()
+}
+
+/// Removes a transaction Monzo has deleted, along with its splits, receipts and everything else
+/// hanging off it. A transaction that was never stored has nothing to remove. One on a claim that
+/// has been submitted is refused, so the webhook fails and can be replayed once the claim is sorted
+/// out.
+async fn process_transaction_deleted(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    account_id: &str,
+    transaction: DeletedTransactionRequest,
+    mode: ProcessingMode,
+) -> Result<WebhookOutcome, Box<dyn Error>> {
+    if transaction.account_id != account_id {
+        return Err(format!(
+            "The webhook for account_id={} cannot delete transactions for account_id={}",
+            account_id, &transaction.account_id
+        )
+        .into());
+    }
+
+    let Some(stored) = query_transaction(pool, &transaction.id).await? else {
+        return Ok(WebhookOutcome {
+            status: MonzoWebhookStatus::Processed,
+            transaction_id: Some(transaction.id),
+            changes: vec![],
+        });
+    };
+
+    let splits = query_splits(pool, std::slice::from_ref(&stored.id)).await?;
+    let mut claims = vec![];
+    for claim_id in stored
+        .claim_id
+        .iter()
+        .chain(splits.iter().filter_map(|split| split.claim_id.as_ref()))
+    {
+        claims.extend(query_claim_by_id(pool, *claim_id).await?);
+    }
+    let locked = locked_claim_ids(&stored, &splits, &claims);
+    if !locked.is_empty() {
+        return Err(format!(
+            "Transaction id={} is on submitted claims {:?}, so it is not deleted",
+            &stored.id, locked
+        )
+        .into());
+    }
+
+    let changes = match mode {
+        ProcessingMode::Live => vec![],
+        ProcessingMode::Replay | ProcessingMode::DryRun => {
+            transaction_changes(Some(&stored), None)?
+        }
+    };
+    if mode == ProcessingMode::DryRun {
+        return Ok(WebhookOutcome {
+            status: MonzoWebhookStatus::Processed,
+            transaction_id: Some(stored.id),
+            changes,
+        });
+    }
+
+    // The attachments go first, while the transaction is still there to find the token by.
+    let receipts = query_receipts(pool, std::slice::from_ref(&stored.id)).await?;
+    for receipt in receipts.iter() {
+        if let Err(err) = remove_receipt_from_monzo(pool, receipt).await {
+            tracing::error!(
+                "Error removing receipt id={} of deleted transaction id={} from Monzo: {}",
+                receipt.id,
+                &stored.id,
+                err
+            );
+        }
+    }
+
+    delete_transaction(pool, &stored.account_id, &stored.id).await?;
+
+    // The receipt rows went with the transaction, so a blob left behind is only wasted space.
+    for receipt in receipts.iter() {
+        let _ = delete_blob(blob_store, &receipt.storage_key).await;
+    }
+
+    tracing::info!(
+        "Deleted transaction id={} with {} receipts, as Monzo deleted it",
+        &stored.id,
+        receipts.len()
+    );
+
+    Ok(WebhookOutcome {
+        status: MonzoWebhookStatus::Processed,
+        transaction_id: Some(stored.id),
+        changes,
+    })
+}
+
+/// Processes a Monzo webhook by its type. Types there is nothing to do for are ignored rather
+/// than failed, since they are not going to work on another attempt. Given the inbox id of the
+/// webhook, it is also ignored when a webhook for the same transaction received after it has been
+/// processed already, so that a retry does not put back what that one changed.
+async fn process_monzo_webhook(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    refetch: bool,
+    account_id: &str,
+    body: &str,
+    webhook_id: Option<i64>,
+    mode: ProcessingMode,
+) -> Result<WebhookOutcome, Box<dyn Error>> {
+    let request = serde_json::from_str::<WebhookRequest>(body)?;
+    let event = MonzoEvent::try_from(request)?;
+
+    if let Some(webhook_id) = webhook_id
+        && let Some(transaction_id) = event.transaction_id()
+        && let Some(newer_id) =
+            query_newer_processed_monzo_webhook(pool, transaction_id, webhook_id).await?
+    {
+        tracing::info!(
+            "Ignoring Monzo webhook id={} for transaction id={}, since webhook id={} is newer",
+            webhook_id,
+            transaction_id,
+            newer_id
+        );
+        return Ok(WebhookOutcome {
+            status: MonzoWebhookStatus::Ignored,
+            transaction_id: Some(transaction_id.to_string()),
+            changes: vec![],
+        });
+    }
+
+    match event {
+        MonzoEvent::TransactionCreated(transaction) => {
+            process_transaction_event(pool, refetch, account_id, transaction, false, mode).await
+        }
+        MonzoEvent::TransactionUpdated(transaction) => {
+            process_transaction_event(pool, refetch, account_id, transaction, true, mode).await
+        }
+        MonzoEvent::TransactionDeleted(transaction) => {
+            process_transaction_deleted(pool, blob_store, account_id, transaction, mode).await
+        }
+        MonzoEvent::Unknown(event_type) => {
+            tracing::info!(
+                "Ignoring Monzo webhook for account_id={} of type={}",
+                account_id,
+                event_type
+            );
+            Ok(WebhookOutcome {
+                status: MonzoWebhookStatus::Ignored,
+                transaction_id: None,
+                changes: vec![],
+            })
+        }
+    }
+}
+
+/// Processes the Monzo webhooks that are due, batch by batch until none are left. Ones that fail
+/// are tried again later, and given up on after a few attempts.
+pub async fn process_monzo_webhooks(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    refetch: bool,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let due = claim_due_monzo_webhooks(
+            pool,
+            MONZO_WEBHOOK_BATCH_SIZE,
+            Duration::seconds(MONZO_WEBHOOK_LEASE_SECONDS),
+        )
+        .await?;
+
+        for webhook in due.iter() {
+            let result = process_monzo_webhook(
+                pool,
+                blob_store,
+                refetch,
+                &webhook.account_id,
+                &webhook.body,
+                Some(webhook.id),
+                ProcessingMode::Live,
+            )
+            .await
+            .map_err(|err| err.to_string());
+            let attempts = webhook.attempts + 1;
+            let status = match &result {
+                Ok(outcome) => outcome.status,
+                Err(_) if attempts >= MONZO_WEBHOOK_ATTEMPTS => MonzoWebhookStatus::Failed,
+                Err(_) => MonzoWebhookStatus::Pending,
+            };
+            if let Err(err) = &result {
+                tracing::error!(
+                    "Error processing Monzo webhook id={} on attempt {}: {}",
+                    webhook.id,
+                    attempts,
+                    err
+                );
+            }
+
+            record_monzo_webhook_attempt(
+                pool,
+                webhook.id,
+                status,
+                result
+                    .as_ref()
+                    .ok()
+                    .and_then(|outcome| outcome.transaction_id.as_deref()),
+                result.as_ref().err().map(String::as_str),
+                Utc::now() + retry_delay(attempts),
+            )
+            .await?;
+        }
+
+        if (due.len() as i64) < MONZO_WEBHOOK_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+fn webhook_replay(
+    webhook_id: Option<i64>,
+    result: Result<WebhookOutcome, String>,
+) -> WebhookReplay {
+    match result {
+        Ok(outcome) => WebhookReplay {
+            webhook_id,
+            status: outcome.status,
+            transaction_id: outcome.transaction_id,
+            changes: outcome.changes,
+            error: None,
+        },
+        Err(error) => WebhookReplay {
+            webhook_id,
+            status: MonzoWebhookStatus::Failed,
+            transaction_id: None,
+            changes: vec![],
+            error: Some(error),
+        },
+    }
+}
+
+/// Processes a webhook body from outside the inbox, for the account it says it is for.
+async fn replay_monzo_payload(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    refetch: bool,
+    payload: &Value,
+    mode: ProcessingMode,
+) -> Result<WebhookOutcome, Box<dyn Error>> {
+    let account_id = payload
+        .pointer("/data/account_id")
+        .and_then(Value::as_str)
+        .ok_or("The payload has no data.account_id")?;
+    let account = query_account(pool, account_id)
+        .await?
+        .ok_or_else(|| format!("Unknown account_id={}", account_id))?;
+
+    process_monzo_webhook(
+        pool,
+        blob_store,
+        refetch,
+        &account.id,
+        &payload.to_string(),
+        None,
+        mode,
+    )
+    .await
+}
+
+/// Runs Monzo webhooks through the same processing as when they arrive, and reports what each
+/// changed about its transaction. Inbox entries are marked with how it went, so a failed one that
+/// works now is no longer listed as failed. In a dry run nothing is changed.
+pub async fn replay_monzo_webhooks(
+    pool: &PgPool,
+    blob_store: &BlobStore,
+    refetch: bool,
+    request: &ReplayRequest,
+) -> Result<Vec<WebhookReplay>, Box<dyn Error>> {
+    let mode = if request.dry_run {
+        ProcessingMode::DryRun
+    } else {
+        ProcessingMode::Replay
+    };
+
+    let mut webhooks = query_monzo_webhooks_by_ids(pool, &request.webhook_ids).await?;
+    if let Some(status) = request.status {
+        webhooks.extend(query_monzo_webhooks(pool, status, MAX_REPLAYED_WEBHOOKS).await?);
+    }
+    webhooks.sort_by_key(|webhook| webhook.id);
+    webhooks.dedup_by_key(|webhook| webhook.id);
+
+    tracing::info!(
+        "Replaying {} Monzo webhooks and {} payloads, dry_run={}",
+        webhooks.len(),
+        request.payloads.len(),
+        request.dry_run
+    );
+
+    let mut replays = vec![];
+    for webhook in webhooks.iter() {
+        // A replay is asked for, so it goes ahead even when newer webhooks have been processed.
+        let result = process_monzo_webhook(
+            pool,
+            blob_store,
+            refetch,
+            &webhook.account_id,
+            &webhook.body,
+            None,
+            mode,
+        )
+        .await
+        .map_err(|err| err.to_string());
+        if mode == ProcessingMode::Replay {
+            let status = match &result {
+                Ok(outcome) => outcome.status,
+                Err(_) => MonzoWebhookStatus::Failed,
+            };
+            record_monzo_webhook_attempt(
+                pool,
+                webhook.id,
+                status,
+                result
+                    .as_ref()
+                    .ok()
+                    .and_then(|outcome| outcome.transaction_id.as_deref()),
+                result.as_ref().err().map(String::as_str),
+                Utc::now(),
+            )
+            .await?;
+        }
+        replays.push(webhook_replay(Some(webhook.id), result));
+    }
+
+    for payload in request.payloads.iter() {
+        let result = replay_monzo_payload(pool, blob_store, refetch, payload, mode)
+            .await
+            .map_err(|err| err.to_string());
+        replays.push(webhook_replay(None, result));
+    }
+
+    Ok(replays)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    fn claim(id: i64, status: ClaimStatus) -> Claim {
+        Claim {
+            id,
+            user_id: String::from("user"),
+            title: String::from("Trip"),
+            status,
+            created: Utc::now(),
+            updated: Utc::now(),
+            reimbursement_transaction_id: None,
+            transaction_count: 1,
+            totals: vec![],
+        }
+    }
+
+    fn split(claim_id: Option<i64>) -> Split {
+        Split {
+            id: 1,
+            transaction_id: String::from("tx_1"),
+            money: Money::new(-2000, "GBP"),
+            note: None,
+            category_id: None,
+            category: None,
+            tags: vec![],
+            category_vat_rate: None,
+            reimbursable: true,
+            claim_id,
+            created: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn deleting_a_transaction_split_onto_a_submitted_claim_is_refused() {
+        let transaction = Transaction {
+            id: String::from("tx_1"),
+            is_split: true,
+            ..Default::default()
+        };
+        let splits = vec![split(None), split(Some(7))];
+        let claims = vec![claim(7, ClaimStatus::Submitted)];
+
+        assert_eq!(locked_claim_ids(&transaction, &splits, &claims), vec![7]);
+    }
+
+    #[test]
+    fn deleting_a_transaction_on_a_draft_claim_is_allowed() {
+        let transaction = Transaction {
+            id: String::from("tx_1"),
+            claim_id: Some(3),
+            ..Default::default()
+        };
+        let claims = vec![claim(3, ClaimStatus::Draft)];
+
+        assert!(locked_claim_ids(&transaction, &[], &claims).is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser::SerializeStruct};
+use sqlx::{Row, postgres::PgRow};
+
+use crate::domain::try_get_or_default;
+
+/// Decimal places in the minor unit of an ISO 4217 currency, for the ones that do not have two.
+pub fn minor_unit_exponent(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        "CLF" | "UYW" => 4,
+        _ => 2,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MoneyError {
+    CurrencyMismatch(String, String),
+    Overflow,
+    InvalidAmount(String),
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch(left, right) => {
+                write!(f, "Cannot combine amounts in {} and {}", left, right)
+            }
+            MoneyError::Overflow => write!(f, "Amount out of range"),
+            MoneyError::InvalidAmount(amount) => write!(f, "Invalid amount '{}'", amount),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// An amount of money in the minor units of its currency, e.g. pence for GBP or yen for JPY.
+///
+/// Arithmetic is checked and refuses to mix currencies. It serialises as the amount in minor units
+/// alongside the same amount as a decimal string, and either can be given when deserialising.
+#[derive(Debug, Clone, Default, PartialEq, Eq, sqlx::FromRow)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: &str) -> Money {
+        Money {
+            amount,
+            currency: currency.to_string(),
+        }
+    }
+
+    pub fn zero(currency: &str) -> Money {
+        Money::new(0, currency)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.amount == 0
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(
+                self.currency.clone(),
+                other.currency.clone(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let amount = self
+            .amount
+            .checked_add(other.amount)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, &self.currency))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.checked_add(&other.checked_neg()?)
+    }
+
+    pub fn checked_neg(&self) -> Result<Money, MoneyError> {
+        let amount = self.amount.checked_neg().ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount, &self.currency))
+    }
+
+    /// Adds up amounts that should all be in `currency`, which is what an empty sum is in.
+    pub fn sum<'a>(
+        currency: &str,
+        amounts: impl IntoIterator<Item = &'a Money>,
+    ) -> Result<Money, MoneyError> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), |total, amount| {
+                total.checked_add(amount)
+            })
+    }
+
+    /// The amount in major units, e.g. "-12.34" for -1234 pence.
+    pub fn to_decimal(&self) -> String {
+        let exponent = minor_unit_exponent(&self.currency);
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let amount = self.amount.unsigned_abs();
+        if exponent == 0 {
+            return format!("{}{}", sign, amount);
+        }
+        let scale = 10u64.pow(exponent);
+        format!(
+            "{}{}.{:0width$}",
+            sign,
+            amount / scale,
+            amount % scale,
+            width = exponent as usize
+        )
+    }
+
+    /// Parses an amount in major units, e.g. "-12.34" or "12.5" for GBP. Amounts with more decimal
+    /// places than the currency has are refused rather than rounded.
+    pub fn from_decimal(decimal: &str, currency: &str) -> Result<Money, MoneyError> {
+        let invalid = || MoneyError::InvalidAmount(decimal.to_string());
+
+        let text = decimal.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let exponent = minor_unit_exponent(currency) as usize;
+        if whole.is_empty()
+            || fraction.len() > exponent
+            || !whole.chars().all(|c| c.is_ascii_digit())
+            || !fraction.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let minor_units = format!("{}{:0<width$}", whole, fraction, width = exponent)
+            .parse::<i64>()
+            .map_err(|_| MoneyError::Overflow)?;
+        let amount = if negative { -minor_units } else { minor_units };
+        Ok(Money::new(amount, currency))
+    }
+
+    /// Reads an amount from `amount_column` of a row, in the currency in `currency_column`.
+    pub fn from_columns(
+        row: &PgRow,
+        amount_column: &str,
+        currency_column: &str,
+    ) -> Result<Money, sqlx::Error> {
+        let currency: String = row.try_get(currency_column)?;
+        Ok(Money::new(row.try_get(amount_column)?, &currency))
+    }
+
+    /// Like from_columns, for an amount that can be NULL. Columns missing from the row are read as
+    /// NULL too, so queries that do not select the amount still work.
+    pub fn from_nullable_columns(
+        row: &PgRow,
+        amount_column: &str,
+        currency_column: &str,
+    ) -> Result<Option<Money>, sqlx::Error> {
+        let amount: Option<i64> = try_get_or_default(row, amount_column)?;
+        let currency: Option<String> = try_get_or_default(row, currency_column)?;
+        Ok(amount
+            .zip(currency)
+            .map(|(amount, currency)| Money::new(amount, &currency)))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.to_decimal(), self.currency)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Money", 3)?;
+        state.serialize_field("amount", &self.amount)?;
+        state.serialize_field("currency", &self.currency)?;
+        state.serialize_field("amount_decimal", &self.to_decimal())?;
+        state.end()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AmountValue {
+    MinorUnits(i64),
+    Decimal(String),
+}
+
+#[derive(Deserialize)]
+struct MoneyValue {
+    amount: Option<AmountValue>,
+    amount_decimal: Option<String>,
+    currency: String,
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        let value = MoneyValue::deserialize(deserializer)?;
+        let currency = value.currency.trim().to_ascii_uppercase();
+        let money = match (value.amount, value.amount_decimal) {
+            (Some(AmountValue::MinorUnits(amount)), _) => Ok(Money::new(amount, &currency)),
+            (Some(AmountValue::Decimal(decimal)), _) | (None, Some(decimal)) => {
+                Money::from_decimal(&decimal, &currency)
+            }
+            (None, None) => return Err(serde::de::Error::missing_field("amount")),
+        };
+        money.map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_decimal_uses_the_currency_exponent() {
+        assert_eq!(Money::new(-1234, "GBP").to_decimal(), "-12.34");
+        assert_eq!(Money::new(5, "GBP").to_decimal(), "0.05");
+        assert_eq!(Money::new(1234, "JPY").to_decimal(), "1234");
+        assert_eq!(Money::new(1234, "KWD").to_decimal(), "1.234");
+        assert_eq!(
+            Money::new(i64::MIN, "GBP").to_decimal(),
+            "-92233720368547758.08"
+        );
+    }
+
+    #[test]
+    fn from_decimal_parses_major_units() {
+        assert_eq!(
+            Money::from_decimal("-12.34", "GBP"),
+            Ok(Money::new(-1234, "GBP"))
+        );
+        assert_eq!(
+            Money::from_decimal("12.5", "GBP"),
+            Ok(Money::new(1250, "GBP"))
+        );
+        assert_eq!(Money::from_decimal("+7", "GBP"), Ok(Money::new(700, "GBP")));
+        assert_eq!(
+            Money::from_decimal(" 1234 ", "JPY"),
+            Ok(Money::new(1234, "JPY"))
+        );
+        assert_eq!(
+            Money::from_decimal("1.234", "KWD"),
+            Ok(Money::new(1234, "KWD"))
+        );
+    }
+
+    #[test]
+    fn from_decimal_refuses_invalid_amounts() {
+        for decimal in ["", ".5", "1.234", "1,00", "abc", "1.2.3", "--1"] {
+            assert_eq!(
+                Money::from_decimal(decimal, "GBP"),
+                Err(MoneyError::InvalidAmount(decimal.to_string())),
+                "{decimal}"
+            );
+        }
+        assert_eq!(
+            Money::from_decimal("1.5", "JPY"),
+            Err(MoneyError::InvalidAmount(String::from("1.5")))
+        );
+        assert_eq!(
+            Money::from_decimal("999999999999999999", "GBP"),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn decimal_round_trips() {
+        for money in [
+            Money::new(-1234, "GBP"),
+            Money::new(0, "EUR"),
+            Money::new(1, "USD"),
+            Money::new(-5, "JPY"),
+            Money::new(1001, "BHD"),
+        ] {
+            assert_eq!(
+                Money::from_decimal(&money.to_decimal(), &money.currency),
+                Ok(money)
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_refuses_mixed_currencies() {
+        let pounds = Money::new(100, "GBP");
+        let euros = Money::new(100, "EUR");
+        assert_eq!(
+            pounds.checked_add(&euros),
+            Err(MoneyError::CurrencyMismatch(
+                String::from("GBP"),
+                String::from("EUR")
+            ))
+        );
+        assert!(pounds.checked_sub(&euros).is_err());
+        assert!(Money::sum("GBP", [&pounds, &euros]).is_err());
+        assert_eq!(
+            Money::sum("GBP", [&pounds, &pounds]),
+            Ok(Money::new(200, "GBP"))
+        );
+        assert_eq!(Money::sum("GBP", []), Ok(Money::zero("GBP")));
+    }
+
+    #[test]
+    fn arithmetic_refuses_overflow() {
+        let max = Money::new(i64::MAX, "GBP");
+        assert_eq!(
+            max.checked_add(&Money::new(1, "GBP")),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            Money::new(i64::MIN, "GBP").checked_neg(),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn serde_round_trips() {
+        let money = Money::new(-1234, "GBP");
+        let json = serde_json::to_value(&money).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"amount": -1234, "currency": "GBP", "amount_decimal": "-12.34"})
+        );
+        assert_eq!(serde_json::from_value::<Money>(json).unwrap(), money);
+    }
+
+    #[test]
+    fn deserialises_either_form_of_the_amount() {
+        let parse = |json| serde_json::from_value::<Money>(json);
+        assert_eq!(
+            parse(serde_json::json!({"amount": 1250, "currency": "gbp"})).unwrap(),
+            Money::new(1250, "GBP")
+        );
+        assert_eq!(
+            parse(serde_json::json!({"amount": "12.50", "currency": "GBP"})).unwrap(),
+            Money::new(1250, "GBP")
+        );
+        assert_eq!(
+            parse(serde_json::json!({"amount_decimal": "12.5", "currency": "GBP"})).unwrap(),
+            Money::new(1250, "GBP")
+        );
+        assert!(parse(serde_json::json!({"currency": "GBP"})).is_err());
+        assert!(parse(serde_json::json!({"amount_decimal": "12.345", "currency": "GBP"})).is_err());
+    }
+}
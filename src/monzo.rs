@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 
+use axum::body::Bytes;
 use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::domain::Token;
 
@@ -32,6 +34,15 @@ pub struct Merchant {
     pub name: String,
 }
 
+/// The other side of a payment. Monzo sends an empty object for card payments, and only payments
+/// between Monzo users have a `user_id`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Counterparty {
+    pub user_id: Option<String>,
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TransactionResponse {
     pub id: String,
@@ -44,6 +55,14 @@ pub struct TransactionResponse {
     pub settled: String,
     pub category: String,
     pub merchant: Option<String>,
+    #[serde(default)]
+    pub counterparty: Counterparty,
+    #[serde(default)]
+    pub metadata: HashMap<String, Value>,
+    #[serde(default)]
+    pub local_amount: Option<i64>,
+    #[serde(default)]
+    pub local_currency: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -59,6 +78,118 @@ pub struct TransactionRequest {
     pub category: String,
     pub account_id: String,
     pub merchant: Option<Merchant>,
+    #[serde(default)]
+    pub counterparty: Counterparty,
+    #[serde(default)]
+    pub metadata: HashMap<String, Value>,
+    #[serde(default)]
+    pub local_amount: Option<i64>,
+    #[serde(default)]
+    pub local_currency: Option<String>,
+    #[serde(default)]
+    pub attachments: Option<Vec<AttachmentResponse>>,
+}
+
+/// The body of a webhook Monzo sends.
+#[derive(Debug, Deserialize)]
+pub struct WebhookRequest {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub data: Value,
+}
+
+/// The body of a webhook for a transaction Monzo has deleted, such as a reversed authorisation.
+#[derive(Debug, Deserialize)]
+pub struct DeletedTransactionRequest {
+    pub id: String,
+    pub account_id: String,
+}
+
+/// The webhooks Monzo sends, by their type.
+#[derive(Debug)]
+pub enum MonzoEvent {
+    TransactionCreated(TransactionRequest),
+    TransactionUpdated(TransactionRequest),
+    TransactionDeleted(DeletedTransactionRequest),
+    /// A type there is nothing to do for yet, kept so it can be handled later.
+    Unknown(String),
+}
+
+impl MonzoEvent {
+    /// The transaction the event is about, if it is about one.
+    pub fn transaction_id(&self) -> Option<&str> {
+        match self {
+            MonzoEvent::TransactionCreated(transaction)
+            | MonzoEvent::TransactionUpdated(transaction) => Some(&transaction.id),
+            MonzoEvent::TransactionDeleted(transaction) => Some(&transaction.id),
+            MonzoEvent::Unknown(_) => None,
+        }
+    }
+}
+
+impl TryFrom<WebhookRequest> for MonzoEvent {
+    type Error = serde_json::Error;
+
+    fn try_from(request: WebhookRequest) -> Result<Self, Self::Error> {
+        Ok(match request.event_type.as_str() {
+            "transaction.created" => {
+                MonzoEvent::TransactionCreated(serde_json::from_value(request.data)?)
+            }
+            "transaction.updated" => {
+                MonzoEvent::TransactionUpdated(serde_json::from_value(request.data)?)
+            }
+            "transaction.deleted" => {
+                MonzoEvent::TransactionDeleted(serde_json::from_value(request.data)?)
+            }
+            _ => MonzoEvent::Unknown(request.event_type),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AttachmentResponse {
+    pub id: String,
+    pub external_id: String,
+    pub file_url: String,
+    pub file_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PotResponse {
+    pub id: String,
+    pub name: String,
+    pub balance: i64,
+    pub currency: String,
+    #[serde(default)]
+    pub goal_amount: Option<i64>,
+    pub deleted: bool,
+    pub created: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListPotsResponse {
+    pots: Vec<PotResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BalanceResponse {
+    pub balance: i64,
+    pub total_balance: i64,
+    pub currency: String,
+    pub spend_today: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterAttachmentResponse {
+    attachment: AttachmentResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadAttachmentResponse {
+    file_url: String,
+    upload_url: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -88,10 +219,10 @@ pub async fn exchange_auth_code(
 
     let mut params = HashMap::new();
     params.insert("grant_type", "authorization_code");
-    params.insert("client_id", &client_id);
-    params.insert("client_secret", &client_secret);
-    params.insert("redirect_uri", &redirect_uri);
-    params.insert("code", &code);
+    params.insert("client_id", client_id);
+    params.insert("client_secret", client_secret);
+    params.insert("redirect_uri", redirect_uri);
+    params.insert("code", code);
 
     let res = client
         .post("https://api.monzo.com/oauth2/token")
@@ -119,11 +250,7 @@ pub async fn register_webhook(
 ) -> Result<reqwest::Response, reqwest::Error> {
     let client = reqwest::Client::new();
 
-    tracing::info!(
-        "Registering webhook for account_id={} url={}",
-        account_id,
-        url
-    );
+    tracing::info!("Registering webhook for account_id={}", account_id);
 
     let mut params = HashMap::new();
     params.insert("account_id", account_id);
@@ -170,8 +297,8 @@ pub async fn refresh_tokens(
 
         let mut params = HashMap::new();
         params.insert("grant_type", "refresh_token");
-        params.insert("client_id", &client_id);
-        params.insert("client_secret", &client_secret);
+        params.insert("client_id", client_id);
+        params.insert("client_secret", client_secret);
         params.insert("refresh_token", &token.refresh_token);
 
         let res = match client
@@ -200,7 +327,7 @@ pub async fn refresh_tokens(
         }
     }
 
-    return token_responses;
+    token_responses
 }
 
 pub async fn list_webhooks(
@@ -274,8 +401,8 @@ pub async fn list_transactions(
 
     let mut params = vec![("account_id", account_id), ("limit", "100")];
 
-    if before.is_some() {
-        params.push(("before", before.unwrap()));
+    if let Some(before) = before {
+        params.push(("before", before));
     }
 
     let res = client
@@ -318,9 +445,9 @@ pub async fn list_all_transactions(
     let mut before: Option<String> = Some((Utc::now() + Duration::days(1)).to_rfc3339());
     loop {
         let batch = list_transactions(access_token, account_id, before.as_deref()).await?;
-        if let Some(transaction) = batch.get(0) {
+        if let Some(transaction) = batch.first() {
             let created = &transaction.created;
-            if created == "" {
+            if created.is_empty() {
                 before = None;
             } else {
                 before = Some(created.clone());
@@ -328,7 +455,268 @@ pub async fn list_all_transactions(
         } else {
             break;
         }
-        transactions.extend(batch.into_iter());
+        transactions.extend(batch);
     }
     Ok(transactions)
 }
+
+/// Lists the pots of an account, including deleted ones, with their current balances.
+pub async fn list_pots(
+    access_token: &str,
+    account_id: &str,
+) -> Result<Vec<PotResponse>, reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!("Listing pots for account_id={}", account_id);
+
+    client
+        .get("https://api.monzo.com/pots")
+        .bearer_auth(access_token)
+        .query(&[("current_account_id", account_id)])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!("Error occurred in request to Monzo pots API: {:#?}", err)
+        })?
+        .json::<ListPotsResponse>()
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred while deserialising pots response: {:#?}",
+                err
+            )
+        })
+        .map(|res| res.pots)
+}
+
+/// The pot a transaction moved money into or out of. Monzo puts the pot id in the metadata of pot
+/// transfers, and older ones only have it as their description.
+pub fn pot_transfer_id(metadata: &HashMap<String, Value>, description: &str) -> Option<String> {
+    metadata
+        .get("pot_id")
+        .and_then(|pot_id| pot_id.as_str())
+        .map(|pot_id| pot_id.to_string())
+        .or_else(|| {
+            description
+                .starts_with("pot_")
+                .then(|| description.to_string())
+        })
+}
+
+#[derive(Debug, Deserialize)]
+struct GetTransactionResponse {
+    transaction: TransactionRequest,
+}
+
+/// Gets a transaction the way Monzo sends it in webhooks, with the merchant expanded.
+pub async fn get_transaction(
+    access_token: &str,
+    transaction_id: &str,
+) -> Result<TransactionRequest, reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!("Getting transaction id={}", transaction_id);
+
+    client
+        .get(format!(
+            "https://api.monzo.com/transactions/{}",
+            transaction_id
+        ))
+        .bearer_auth(access_token)
+        .query(&[("expand[]", "merchant")])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred in request to Monzo transaction API: {:#?}",
+                err
+            )
+        })?
+        .json::<GetTransactionResponse>()
+        .await
+        .map(|response| response.transaction)
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred while deserialising transaction response: {:#?}",
+                err
+            )
+        })
+}
+
+/// Gets the balance of an account and how much has been spent from it today.
+pub async fn get_balance(
+    access_token: &str,
+    account_id: &str,
+) -> Result<BalanceResponse, reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!("Getting balance for account_id={}", account_id);
+
+    client
+        .get("https://api.monzo.com/balance")
+        .bearer_auth(access_token)
+        .query(&[("account_id", account_id)])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!("Error occurred in request to Monzo balance API: {:#?}", err)
+        })?
+        .json::<BalanceResponse>()
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred while deserialising balance response: {:#?}",
+                err
+            )
+        })
+}
+
+/// Sets the notes on a transaction, replacing whatever is there.
+pub async fn annotate_transaction(
+    access_token: &str,
+    transaction_id: &str,
+    notes: &str,
+) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!("Annotating transaction id={}", transaction_id);
+
+    client
+        .patch(format!(
+            "https://api.monzo.com/transactions/{}",
+            transaction_id
+        ))
+        .bearer_auth(access_token)
+        .form(&[("metadata[notes]", notes)])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred in request to Monzo transaction API: {:#?}",
+                err
+            )
+        })?;
+
+    Ok(())
+}
+
+/// Uploads a file to Monzo's attachment storage and returns the URL to register it with.
+pub async fn upload_attachment(
+    access_token: &str,
+    file_name: &str,
+    file_type: &str,
+    data: Bytes,
+) -> Result<String, reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!("Uploading attachment file_name={}", file_name);
+
+    let content_length = data.len().to_string();
+    let upload = client
+        .post("https://api.monzo.com/attachment/upload")
+        .bearer_auth(access_token)
+        .form(&[
+            ("file_name", file_name),
+            ("file_type", file_type),
+            ("content_length", &content_length),
+        ])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred in request to Monzo attachment API: {:#?}",
+                err
+            )
+        })?
+        .json::<UploadAttachmentResponse>()
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred while deserialising attachment upload response: {:#?}",
+                err
+            )
+        })?;
+
+    // The upload URL is pre-signed, so it must not be sent the access token.
+    client
+        .put(&upload.upload_url)
+        .header(reqwest::header::CONTENT_TYPE, file_type)
+        .body(data)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| tracing::error!("Error occurred uploading attachment: {:#?}", err))?;
+
+    Ok(upload.file_url)
+}
+
+pub async fn register_attachment(
+    access_token: &str,
+    transaction_id: &str,
+    file_url: &str,
+    file_type: &str,
+) -> Result<AttachmentResponse, reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!(
+        "Registering attachment for transaction id={}",
+        transaction_id
+    );
+
+    client
+        .post("https://api.monzo.com/attachment/register")
+        .bearer_auth(access_token)
+        .form(&[
+            ("external_id", transaction_id),
+            ("file_url", file_url),
+            ("file_type", file_type),
+        ])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred in request to Monzo attachment API: {:#?}",
+                err
+            )
+        })?
+        .json::<RegisterAttachmentResponse>()
+        .await
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred while deserialising attachment response: {:#?}",
+                err
+            )
+        })
+        .map(|res| res.attachment)
+}
+
+pub async fn deregister_attachment(
+    access_token: &str,
+    attachment_id: &str,
+) -> Result<(), reqwest::Error> {
+    let client = reqwest::Client::new();
+
+    tracing::info!("Deregistering attachment id={}", attachment_id);
+
+    client
+        .post("https://api.monzo.com/attachment/deregister")
+        .bearer_auth(access_token)
+        .form(&[("id", attachment_id)])
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .inspect_err(|err| {
+            tracing::error!(
+                "Error occurred in request to Monzo attachment API: {:#?}",
+                err
+            )
+        })?;
+
+    Ok(())
+}
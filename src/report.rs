@@ -0,0 +1,317 @@
+use std::error::Error;
+
+use axum::body::Bytes;
+use printpdf::{
+    BuiltinFont, Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocument,
+    PdfDocumentReference, PdfLayerReference, Point,
+    image_crate::{self, DynamicImage},
+};
+
+use crate::{
+    domain::{CategoryTotal, Transaction},
+    money::Money,
+    vat::vat_breakdown,
+};
+
+// A4 landscape
+const PAGE_WIDTH: f32 = 297.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 15.0;
+const ROW_HEIGHT: f32 = 5.5;
+const FONT_SIZE: f32 = 9.0;
+const THUMBNAIL_SIZE: f32 = 55.0;
+const THUMBNAIL_PIXELS: u32 = 400;
+const THUMBNAIL_CELL_WIDTH: f32 = 66.0;
+
+/// Left edge and maximum number of characters of each transaction column.
+const COLUMNS: [(&str, f32, usize); 8] = [
+    ("Date", MARGIN, 10),
+    ("Description", 38.0, 28),
+    ("Merchant", 92.0, 24),
+    ("Category", 137.0, 19),
+    ("Amount", 174.0, 14),
+    ("VAT", 200.0, 12),
+    ("Reported", 222.0, 14),
+    ("Notes", 248.0, 18),
+];
+
+/// Index of the amount column, which the totals line up under.
+const AMOUNT_COLUMN: usize = 4;
+
+pub struct ReportReceipt {
+    pub caption: String,
+    pub file_name: String,
+    pub data: Bytes,
+}
+
+pub struct Report {
+    pub title: String,
+    pub subtitle: String,
+    pub transactions: Vec<Transaction>,
+    pub totals: Vec<CategoryTotal>,
+    pub receipts: Vec<ReportReceipt>,
+}
+
+struct ReportWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    bold: IndirectFontRef,
+    y: f32,
+    page: usize,
+}
+
+impl ReportWriter {
+    fn new(title: &str) -> Result<Self, printpdf::Error> {
+        let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Page 1");
+        let font = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let layer = doc.get_page(page).get_layer(layer);
+        Ok(ReportWriter {
+            doc,
+            layer,
+            font,
+            bold,
+            y: PAGE_HEIGHT - MARGIN,
+            page: 1,
+        })
+    }
+
+    fn new_page(&mut self) {
+        self.page += 1;
+        let (page, layer) = self.doc.add_page(
+            Mm(PAGE_WIDTH),
+            Mm(PAGE_HEIGHT),
+            format!("Page {}", self.page),
+        );
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Starts a new page when fewer than `rows` rows fit on the current one. Returns true if it did.
+    fn ensure_rows(&mut self, rows: usize) -> bool {
+        if self.y - rows as f32 * ROW_HEIGHT < MARGIN {
+            self.new_page();
+            return true;
+        }
+        false
+    }
+
+    fn text(&self, text: &str, size: f32, x: f32, bold: bool) {
+        let font = if bold { &self.bold } else { &self.font };
+        self.layer.use_text(text, size, Mm(x), Mm(self.y), font);
+    }
+
+    fn rule(&self) {
+        let y = self.y + ROW_HEIGHT - 1.5;
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(y)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn row(&mut self, cells: &[String], bold: bool) {
+        for ((_, x, width), cell) in COLUMNS.iter().zip(cells.iter()) {
+            self.text(&truncate(cell, *width), FONT_SIZE, *x, bold);
+        }
+        self.y -= ROW_HEIGHT;
+    }
+
+    fn header_row(&mut self) {
+        let headers: Vec<_> = COLUMNS
+            .iter()
+            .map(|(name, _, _)| name.to_string())
+            .collect();
+        self.row(&headers, true);
+        self.rule();
+    }
+
+    fn line(&mut self, text: &str, size: f32, bold: bool) {
+        self.text(text, size, MARGIN, bold);
+        self.y -= ROW_HEIGHT * size / FONT_SIZE;
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    // The builtin fonts only cover Latin-1, so anything else is replaced.
+    let text: String = text
+        .chars()
+        .map(|c| if (c as u32) < 256 { c } else { '?' })
+        .collect();
+    if text.chars().count() <= max_chars {
+        text
+    } else {
+        let mut truncated: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+        truncated.push_str("...");
+        truncated
+    }
+}
+
+/// The amount in the reporting currency, left blank when it is the same as the amount.
+fn reported_amount(transaction: &Transaction) -> String {
+    match &transaction.reporting_amount {
+        Some(amount) if amount.currency != transaction.money.currency => amount.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// The VAT included in the amount, left blank when none is recorded or implied by the category.
+fn vat_amount(transaction: &Transaction) -> String {
+    match vat_breakdown(transaction) {
+        Some(breakdown) => breakdown.vat.to_string(),
+        None => String::new(),
+    }
+}
+
+fn thumbnail(data: &[u8]) -> Option<DynamicImage> {
+    let image = image_crate::load_from_memory(data).ok()?;
+    let thumbnail = image.thumbnail(THUMBNAIL_PIXELS, THUMBNAIL_PIXELS);
+    Some(DynamicImage::ImageRgb8(thumbnail.to_rgb8()))
+}
+
+/// Lays receipts out in a grid, with a thumbnail for images and just the caption for anything
+/// that cannot be decoded, such as PDFs.
+fn write_receipts(writer: &mut ReportWriter, receipts: &[ReportReceipt]) {
+    let per_row = ((PAGE_WIDTH - 2.0 * MARGIN) / THUMBNAIL_CELL_WIDTH) as usize;
+    let cell_rows = ((THUMBNAIL_SIZE + 3.0 * ROW_HEIGHT) / ROW_HEIGHT).ceil() as usize;
+
+    writer.y -= ROW_HEIGHT;
+    writer.ensure_rows(cell_rows + 2);
+    writer.line("Receipts", 11.0, true);
+
+    for chunk in receipts.chunks(per_row) {
+        writer.ensure_rows(cell_rows);
+        let top = writer.y;
+        for (index, receipt) in chunk.iter().enumerate() {
+            let x = MARGIN + index as f32 * THUMBNAIL_CELL_WIDTH;
+            let max_chars = 36;
+
+            writer.y = top;
+            writer.text(
+                &truncate(&receipt.caption, max_chars),
+                FONT_SIZE - 1.0,
+                x,
+                false,
+            );
+            writer.y -= ROW_HEIGHT * 0.8;
+            writer.text(
+                &truncate(&receipt.file_name, max_chars),
+                FONT_SIZE - 1.0,
+                x,
+                false,
+            );
+
+            match thumbnail(&receipt.data) {
+                Some(image) => {
+                    let largest = image.width().max(image.height()) as f32;
+                    let height = THUMBNAIL_SIZE * image.height() as f32 / largest;
+                    Image::from_dynamic_image(&image).add_to_layer(
+                        writer.layer.clone(),
+                        ImageTransform {
+                            translate_x: Some(Mm(x)),
+                            translate_y: Some(Mm(writer.y - ROW_HEIGHT * 0.5 - height)),
+                            dpi: Some(largest * 25.4 / THUMBNAIL_SIZE),
+                            ..Default::default()
+                        },
+                    );
+                }
+                None => {
+                    writer.y -= ROW_HEIGHT;
+                    writer.text("(no preview)", FONT_SIZE - 1.0, x, false);
+                }
+            }
+        }
+        writer.y = top - cell_rows as f32 * ROW_HEIGHT;
+    }
+}
+
+/// Renders an expense report listing the transactions, the totals and then the receipts.
+pub fn render_report(report: &Report) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut writer = ReportWriter::new(&report.title)?;
+
+    writer.line(&report.title, 16.0, true);
+    writer.line(&report.subtitle, FONT_SIZE, false);
+    writer.y -= ROW_HEIGHT;
+
+    writer.header_row();
+    for transaction in report.transactions.iter() {
+        if writer.ensure_rows(1) {
+            writer.header_row();
+        }
+        writer.row(
+            &[
+                transaction.created.format("%Y-%m-%d").to_string(),
+                transaction.description.clone(),
+                transaction
+                    .merchant_name
+                    .clone()
+                    .unwrap_or(transaction.description.clone()),
+                transaction
+                    .custom_category
+                    .clone()
+                    .unwrap_or(transaction.category.clone()),
+                transaction.money.to_string(),
+                vat_amount(transaction),
+                reported_amount(transaction),
+                transaction.notes.clone(),
+            ],
+            false,
+        );
+    }
+
+    writer.y -= ROW_HEIGHT;
+    writer.ensure_rows(3);
+    writer.line("Totals per category", 11.0, true);
+    for total in report.totals.iter() {
+        writer.ensure_rows(1);
+        writer.text(
+            &truncate(&total.category, 44),
+            FONT_SIZE,
+            COLUMNS[1].1,
+            false,
+        );
+        writer.text(
+            &total.total.to_string(),
+            FONT_SIZE,
+            COLUMNS[AMOUNT_COLUMN].1,
+            false,
+        );
+        writer.y -= ROW_HEIGHT;
+    }
+
+    let mut currencies: Vec<&String> = report
+        .totals
+        .iter()
+        .map(|total| &total.total.currency)
+        .collect();
+    currencies.sort();
+    currencies.dedup();
+    for currency in currencies {
+        writer.ensure_rows(1);
+        let total = Money::sum(
+            currency,
+            report
+                .totals
+                .iter()
+                .filter(|total| &total.total.currency == currency)
+                .map(|total| &total.total),
+        )?;
+        writer.text("Total", FONT_SIZE, COLUMNS[1].1, true);
+        writer.text(
+            &total.to_string(),
+            FONT_SIZE,
+            COLUMNS[AMOUNT_COLUMN].1,
+            true,
+        );
+        writer.y -= ROW_HEIGHT;
+    }
+
+    if !report.receipts.is_empty() {
+        write_receipts(&mut writer, &report.receipts);
+    }
+
+    Ok(writer.doc.save_to_bytes()?)
+}
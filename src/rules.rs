@@ -0,0 +1,153 @@
+use regex::Regex;
+
+use crate::domain::{Rule, Transaction};
+
+pub struct CompiledRule {
+    pub rule: Rule,
+    description_regex: Option<Regex>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RuleOutcome {
+    /// (rule_id, category_id) of the highest priority matching rule that assigns a category.
+    pub category: Option<(i64, i64)>,
+    /// (rule_id, tag_id) for every tag assigned by any matching rule.
+    pub tags: Vec<(i64, i64)>,
+}
+
+pub fn compile_rule(rule: Rule) -> Result<CompiledRule, regex::Error> {
+    let description_regex = match &rule.description_pattern {
+        Some(pattern) => Some(Regex::new(pattern)?),
+        None => None,
+    };
+    Ok(CompiledRule {
+        rule,
+        description_regex,
+    })
+}
+
+/// Compiles the rules, dropping any with an invalid pattern. Rules are expected to be ordered by
+/// descending priority already.
+pub fn compile_rules(rules: Vec<Rule>) -> Vec<CompiledRule> {
+    rules
+        .into_iter()
+        .filter_map(|rule| {
+            let rule_id = rule.id;
+            compile_rule(rule)
+                .inspect_err(|err| {
+                    tracing::error!("Skipping rule id={} with invalid pattern: {}", rule_id, err)
+                })
+                .ok()
+        })
+        .collect()
+}
+
+impl CompiledRule {
+    /// A rule matches when every condition that is set matches the transaction.
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        let rule = &self.rule;
+
+        if let Some(merchant) = &rule.merchant
+            && transaction.merchant.as_ref() != Some(merchant)
+        {
+            return false;
+        }
+        if let Some(regex) = &self.description_regex
+            && !regex.is_match(&transaction.description)
+        {
+            return false;
+        }
+        // Amounts in another currency are not comparable, so they never match.
+        if let Some(min_amount) = &rule.min_amount
+            && (transaction.money.currency != min_amount.currency
+                || transaction.money.amount < min_amount.amount)
+        {
+            return false;
+        }
+        if let Some(max_amount) = &rule.max_amount
+            && (transaction.money.currency != max_amount.currency
+                || transaction.money.amount > max_amount.amount)
+        {
+            return false;
+        }
+        if let Some(account_id) = &rule.account_id
+            && &transaction.account_id != account_id
+        {
+            return false;
+        }
+        true
+    }
+}
+
+pub fn evaluate(rules: &[CompiledRule], transaction: &Transaction) -> RuleOutcome {
+    let mut outcome = RuleOutcome::default();
+
+    for compiled in rules.iter().filter(|rule| rule.matches(transaction)) {
+        let rule = &compiled.rule;
+        if outcome.category.is_none()
+            && let Some(category_id) = rule.category_id
+        {
+            outcome.category = Some((rule.id, category_id));
+        }
+        for tag_id in rule.tag_ids.iter() {
+            if !outcome.tags.iter().any(|(_, existing)| existing == tag_id) {
+                outcome.tags.push((rule.id, *tag_id));
+            }
+        }
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::money::Money;
+
+    fn amount_rule(min_amount: Option<Money>, max_amount: Option<Money>) -> CompiledRule {
+        compile_rule(Rule {
+            id: 1,
+            user_id: String::from("user"),
+            name: String::from("Amounts"),
+            priority: 0,
+            merchant: None,
+            description_pattern: None,
+            min_amount,
+            max_amount,
+            account_id: None,
+            category_id: Some(1),
+            category: None,
+            tag_ids: vec![],
+            tags: vec![],
+            created: Utc::now(),
+        })
+        .unwrap()
+    }
+
+    fn transaction(money: Money) -> Transaction {
+        Transaction {
+            money,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn amounts_only_match_in_their_currency() {
+        let rule = amount_rule(Some(Money::new(1000, "GBP")), None);
+
+        assert!(rule.matches(&transaction(Money::new(1000, "GBP"))));
+        assert!(!rule.matches(&transaction(Money::new(999, "GBP"))));
+        assert!(!rule.matches(&transaction(Money::new(1000, "JPY"))));
+    }
+
+    #[test]
+    fn max_amount_only_matches_in_its_currency() {
+        let rule = amount_rule(None, Some(Money::new(-1000, "GBP")));
+
+        assert!(rule.matches(&transaction(Money::new(-1500, "GBP"))));
+        assert!(!rule.matches(&transaction(Money::new(-500, "GBP"))));
+        assert!(!rule.matches(&transaction(Money::new(-1500, "EUR"))));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{
+    domain::{Split, Transaction},
+    money::{Money, MoneyError},
+};
+
+/// Replaces every transaction that has splits with one line per split, so totals and reports can
+/// treat the parts of a transaction separately. Lines keep the transaction's details, with the
+/// split's amount, claim and reimbursable flag, and its category and tags when it sets them.
+///
+/// If Monzo has changed the amount since the transaction was split, upsert_transaction drops the
+/// splits that are not on a claim. Whatever the remaining splits do not cover is kept as a line for
+/// the transaction itself, and amounts shared out in proportion are left to that line when the
+/// transaction amount has become zero.
+pub fn split_lines(
+    transactions: Vec<Transaction>,
+    splits: &[Split],
+) -> Result<Vec<Transaction>, MoneyError> {
+    let mut lines = vec![];
+    for transaction in transactions.into_iter() {
+        let transaction_splits: Vec<&Split> = splits
+            .iter()
+            .filter(|split| split.transaction_id == transaction.id)
+            .collect();
+        if transaction_splits.is_empty() {
+            lines.push(transaction);
+            continue;
+        }
+
+        let mut allocated = Money::zero(&transaction.money.currency);
+        let mut allocated_reporting = transaction
+            .reporting_amount
+            .as_ref()
+            .map(|reporting_amount| Money::zero(&reporting_amount.currency));
+        for split in transaction_splits.into_iter() {
+            allocated = allocated.checked_add(&split.money)?;
+
+            let mut line = transaction.clone();
+            line.split_id = Some(split.id);
+            line.money = split.money.clone();
+            line.reimbursable = split.reimbursable;
+            line.claim_id = split.claim_id;
+            // VAT recorded as an amount is shared out in proportion to the split.
+            line.vat_amount = transaction
+                .vat_amount
+                .as_ref()
+                .and_then(|vat_amount| share(vat_amount, &split.money, &transaction.money));
+            // So is the amount in the reporting currency, with the remainder making up the rest.
+            line.reporting_amount =
+                transaction
+                    .reporting_amount
+                    .as_ref()
+                    .and_then(|reporting_amount| {
+                        share(reporting_amount, &split.money, &transaction.money)
+                    });
+            if let (Some(total), Some(reporting_amount)) =
+                (&mut allocated_reporting, &line.reporting_amount)
+            {
+                *total = total.checked_add(reporting_amount)?;
+            }
+            if let Some(note) = &split.note {
+                line.notes = note.clone();
+            }
+            if split.category.is_some() {
+                line.custom_category = split.category.clone();
+                line.category_vat_rate = split.category_vat_rate;
+            }
+            if !split.tags.is_empty() {
+                line.tags = split.tags.clone();
+            }
+            lines.push(line);
+        }
+
+        if allocated != transaction.money {
+            let mut remainder = transaction;
+            remainder.money = remainder.money.checked_sub(&allocated)?;
+            remainder.vat_amount = None;
+            remainder.reporting_amount = remainder
+                .reporting_amount
+                .zip(allocated_reporting)
+                .map(|(reporting_amount, allocated)| reporting_amount.checked_sub(&allocated))
+                .transpose()?;
+            lines.push(remainder);
+        }
+    }
+    Ok(lines)
+}
+
+/// The part of `value` that `part` of `total` accounts for, or None if the total is zero.
+fn share(value: &Money, part: &Money, total: &Money) -> Option<Money> {
+    if total.is_zero() {
+        return None;
+    }
+    let amount = value.amount as i128 * part.amount as i128 / total.amount as i128;
+    Some(Money::new(i64::try_from(amount).ok()?, &value.currency))
+}
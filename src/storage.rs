@@ -0,0 +1,77 @@
+use std::{error::Error, sync::Arc};
+
+use axum::body::Bytes;
+use object_store::{ObjectStore, local::LocalFileSystem, path::Path};
+
+pub type BlobStore = Arc<dyn ObjectStore>;
+
+/// Creates the store receipts are kept in. `location` is a local directory, or an
+/// `s3://bucket/prefix` URL when built with the `s3` feature.
+pub fn create_blob_store(location: &str) -> Result<BlobStore, Box<dyn Error>> {
+    if location.starts_with("s3://") {
+        return create_s3_store(location);
+    }
+
+    std::fs::create_dir_all(location)?;
+    tracing::info!("Storing blobs in local directory {}", location);
+    Ok(Arc::new(LocalFileSystem::new_with_prefix(location)?))
+}
+
+/// The bucket is taken from the URL and the credentials, region and endpoint (for S3-compatible
+/// services) from the usual AWS_* environment variables.
+#[cfg(feature = "s3")]
+fn create_s3_store(location: &str) -> Result<BlobStore, Box<dyn Error>> {
+    use object_store::{aws::AmazonS3Builder, prefix::PrefixStore};
+
+    let url = url::Url::parse(location)?;
+    let bucket = url.host_str().ok_or("S3 location is missing a bucket")?;
+    let store = AmazonS3Builder::from_env()
+        .with_bucket_name(bucket)
+        .build()?;
+
+    tracing::info!("Storing blobs in S3 location {}", location);
+
+    let prefix = url.path().trim_matches('/');
+    if prefix.is_empty() {
+        Ok(Arc::new(store))
+    } else {
+        Ok(Arc::new(PrefixStore::new(store, prefix)))
+    }
+}
+
+#[cfg(not(feature = "s3"))]
+fn create_s3_store(location: &str) -> Result<BlobStore, Box<dyn Error>> {
+    Err(format!(
+        "Cannot use {}: expenses was built without the s3 feature",
+        location
+    )
+    .into())
+}
+
+pub async fn put_blob(
+    store: &BlobStore,
+    key: &str,
+    data: Bytes,
+) -> Result<(), object_store::Error> {
+    store
+        .put(&Path::from(key), data.into())
+        .await
+        .map(|_| ())
+        .inspect_err(|err| tracing::error!("Error storing blob key={}: {:#?}", key, err))
+}
+
+pub async fn get_blob(store: &BlobStore, key: &str) -> Result<Bytes, object_store::Error> {
+    store
+        .get(&Path::from(key))
+        .await
+        .inspect_err(|err| tracing::error!("Error fetching blob key={}: {:#?}", key, err))?
+        .bytes()
+        .await
+}
+
+pub async fn delete_blob(store: &BlobStore, key: &str) -> Result<(), object_store::Error> {
+    store
+        .delete(&Path::from(key))
+        .await
+        .inspect_err(|err| tracing::error!("Error deleting blob key={}: {:#?}", key, err))
+}
@@ -0,0 +1,191 @@
+use chrono::{Days, Months, NaiveDate, Utc};
+
+use crate::{
+    domain::{Cadence, Subscription, Transaction},
+    money::Money,
+};
+
+/// A payment has to have been seen this many times before it counts as recurring.
+const MIN_OCCURRENCES: usize = 3;
+/// Payments further than this fraction from the typical amount are not the same subscription.
+const AMOUNT_TOLERANCE: f64 = 0.3;
+
+impl Cadence {
+    /// The usual number of days between payments, and how many days either side still counts.
+    fn days(&self) -> (i64, i64) {
+        match self {
+            Cadence::Weekly => (7, 1),
+            Cadence::Fortnightly => (14, 2),
+            Cadence::Monthly => (30, 4),
+            Cadence::Quarterly => (91, 10),
+            Cadence::Yearly => (365, 15),
+        }
+    }
+
+    /// The date `n` payments after one on `date`. Months are added in one go, so that a payment
+    /// on the 31st comes back on the 31st whenever the month has one.
+    pub fn date_after(&self, date: NaiveDate, n: u32) -> Option<NaiveDate> {
+        match self {
+            Cadence::Weekly => date.checked_add_days(Days::new(7 * n as u64)),
+            Cadence::Fortnightly => date.checked_add_days(Days::new(14 * n as u64)),
+            Cadence::Monthly => date.checked_add_months(Months::new(n)),
+            Cadence::Quarterly => date.checked_add_months(Months::new(3 * n)),
+            Cadence::Yearly => date.checked_add_months(Months::new(12 * n)),
+        }
+    }
+
+    fn from_interval(days: i64) -> Option<Cadence> {
+        [
+            Cadence::Weekly,
+            Cadence::Fortnightly,
+            Cadence::Monthly,
+            Cadence::Quarterly,
+            Cadence::Yearly,
+        ]
+        .into_iter()
+        .find(|cadence| {
+            let (nominal, tolerance) = cadence.days();
+            (days - nominal).abs() <= tolerance
+        })
+    }
+}
+
+/// Groups payments from the same merchant. Descriptions are used when there is no merchant, with
+/// digits left out because they tend to be references that change every time.
+fn merchant_key(transaction: &Transaction) -> String {
+    match &transaction.merchant {
+        Some(merchant) if !merchant.is_empty() => merchant.clone(),
+        _ => transaction
+            .description
+            .chars()
+            .filter(|c| !c.is_ascii_digit())
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase(),
+    }
+}
+
+fn median(values: &mut [i64]) -> i64 {
+    values.sort();
+    values[values.len() / 2]
+}
+
+/// Checks whether the payments, oldest first, recur on a regular cadence for a similar amount.
+fn detect(
+    user_id: &str,
+    key: &str,
+    payments: &[&Transaction],
+    today: NaiveDate,
+) -> Option<Subscription> {
+    if payments.len() < MIN_OCCURRENCES {
+        return None;
+    }
+
+    let dates: Vec<NaiveDate> = payments
+        .iter()
+        .map(|transaction| transaction.created.date_naive())
+        .collect();
+    let mut intervals: Vec<i64> = dates
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_days())
+        .collect();
+    let cadence = Cadence::from_interval(median(&mut intervals))?;
+    let (nominal, tolerance) = cadence.days();
+    if intervals
+        .iter()
+        .any(|interval| (interval - nominal).abs() > tolerance)
+    {
+        return None;
+    }
+
+    let mut amounts: Vec<i64> = payments
+        .iter()
+        .map(|transaction| transaction.money.amount.abs())
+        .collect();
+    let typical = median(&mut amounts.clone()) as f64;
+    if amounts
+        .iter()
+        .any(|amount| (*amount as f64 - typical).abs() > typical * AMOUNT_TOLERANCE)
+    {
+        return None;
+    }
+
+    let latest = payments.last()?;
+    let last_date = *dates.last()?;
+    let next_date = cadence.date_after(last_date, 1)?;
+    // A subscription that has not been paid for two whole cycles has most likely been cancelled.
+    if (today - next_date).num_days() > nominal * 2 {
+        return None;
+    }
+
+    let amount = amounts.pop()?;
+    let previous_amount = amounts.pop()?;
+    let price_changed = amount != previous_amount;
+
+    Some(Subscription {
+        id: 0,
+        user_id: user_id.to_string(),
+        account_id: latest.account_id.clone(),
+        merchant_key: key.to_string(),
+        merchant: latest.merchant.clone(),
+        description: latest
+            .merchant_name
+            .clone()
+            .unwrap_or_else(|| latest.description.clone()),
+        cadence,
+        income: latest.money.amount > 0,
+        money: Money::new(amount, &latest.money.currency),
+        previous_amount: price_changed.then(|| Money::new(previous_amount, &latest.money.currency)),
+        occurrences: payments.len() as i32,
+        first_date: dates[0],
+        last_date,
+        next_date,
+        last_transaction_id: latest.id.clone(),
+        missed: (today - next_date).num_days() > tolerance,
+        price_changed,
+        updated: Utc::now(),
+    })
+}
+
+/// Finds recurring payments in and out of a user's accounts: at least three from the same merchant,
+/// in the same currency and direction, for a similar amount and at a regular cadence.
+pub fn detect_subscriptions(
+    user_id: &str,
+    transactions: &[Transaction],
+    today: NaiveDate,
+) -> Vec<Subscription> {
+    let mut groups: Vec<(String, &String, bool, Vec<&Transaction>)> = vec![];
+    for transaction in transactions.iter().filter(|transaction| {
+        transaction.money.amount != 0
+            && transaction.counterparty_user_id.is_none()
+            && transaction.pot_id.is_none()
+            && transaction.duplicate_of.is_none()
+    }) {
+        let key = merchant_key(transaction);
+        let income = transaction.money.amount > 0;
+        match groups
+            .iter_mut()
+            .find(|(group_key, currency, group_income, _)| {
+                *group_key == key
+                    && **currency == transaction.money.currency
+                    && *group_income == income
+            }) {
+            Some((_, _, _, payments)) => payments.push(transaction),
+            None => groups.push((key, &transaction.money.currency, income, vec![transaction])),
+        }
+    }
+
+    let mut subscriptions: Vec<Subscription> = groups
+        .into_iter()
+        .filter_map(|(key, _, _, mut payments)| {
+            payments.sort_by_key(|transaction| transaction.created);
+            // Payments from the same merchant on the same day count once.
+            payments.dedup_by_key(|transaction| transaction.created.date_naive());
+            detect(user_id, &key, &payments, today)
+        })
+        .collect();
+    subscriptions.sort_by_key(|subscription| subscription.next_date);
+    subscriptions
+}
@@ -0,0 +1,181 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{
+    domain::Transaction,
+    money::{Money, MoneyError},
+};
+
+/// VAT rates are stored in basis points, so 20% is 2000.
+pub const MAX_VAT_RATE: i32 = 10000;
+
+#[derive(Debug, Serialize)]
+pub struct VatBreakdown {
+    pub rate: Option<i32>,
+    pub gross: Money,
+    pub vat: Money,
+    pub net: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VatReportLine {
+    pub transaction_id: String,
+    pub date: NaiveDate,
+    pub description: String,
+    pub supplier_vat_number: Option<String>,
+    #[serde(flatten)]
+    pub breakdown: VatBreakdown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VatRateTotal {
+    pub currency: String,
+    pub rate: Option<i32>,
+    pub count: usize,
+    pub gross: Money,
+    pub vat: Money,
+    pub net: Money,
+    /// VAT on the transactions that have a supplier VAT number, without which it cannot be
+    /// reclaimed.
+    pub reclaimable_vat: Money,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VatReport {
+    pub year: i32,
+    pub quarter: u32,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub totals: Vec<VatRateTotal>,
+    pub lines: Vec<VatReportLine>,
+}
+
+/// VAT included in a gross amount, rounded half up.
+fn vat_from_gross(gross: i64, rate: i32) -> i64 {
+    let rate = rate as i128;
+    let denominator = MAX_VAT_RATE as i128 + rate;
+    ((gross as i128 * rate + denominator / 2) / denominator) as i64
+}
+
+/// Works out the VAT on a spending transaction. A recorded amount wins over the transaction's own
+/// rate, which wins over the default rate of its category.
+pub fn vat_breakdown(transaction: &Transaction) -> Option<VatBreakdown> {
+    if transaction.money.amount >= 0 {
+        return None;
+    }
+
+    let gross = transaction.money.checked_neg().ok()?;
+    let rate = transaction.vat_rate.or(transaction.category_vat_rate);
+    let vat = match (&transaction.vat_amount, rate) {
+        (Some(vat), _) => vat.clone(),
+        (None, Some(rate)) => Money::new(vat_from_gross(gross.amount, rate), &gross.currency),
+        (None, None) => return None,
+    };
+    let net = gross.checked_sub(&vat).ok()?;
+
+    Some(VatBreakdown {
+        rate,
+        gross,
+        vat,
+        net,
+    })
+}
+
+/// First day of the calendar quarter and first day of the one after it.
+pub fn quarter_dates(year: i32, quarter: u32) -> Option<(NaiveDate, NaiveDate)> {
+    if !(1..=4).contains(&quarter) {
+        return None;
+    }
+    let from = NaiveDate::from_ymd_opt(year, (quarter - 1) * 3 + 1, 1)?;
+    let to = if quarter == 4 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, quarter * 3 + 1, 1)?
+    };
+    Some((from, to))
+}
+
+/// Builds the VAT report for a quarter from the transactions in it, leaving out any without VAT.
+/// Returns None for a quarter that does not exist.
+pub fn vat_report(
+    year: i32,
+    quarter: u32,
+    transactions: &[Transaction],
+) -> Result<Option<VatReport>, MoneyError> {
+    let Some((from, to)) = quarter_dates(year, quarter) else {
+        return Ok(None);
+    };
+
+    let mut totals: Vec<VatRateTotal> = vec![];
+    let mut lines = vec![];
+    for transaction in transactions.iter() {
+        let date = transaction.created.date_naive();
+        if date < from || date >= to {
+            continue;
+        }
+        let Some(breakdown) = vat_breakdown(transaction) else {
+            continue;
+        };
+
+        let reclaimable_vat = if transaction.supplier_vat_number.is_some() {
+            breakdown.vat.clone()
+        } else {
+            Money::zero(&breakdown.vat.currency)
+        };
+        match totals.iter_mut().find(|total| {
+            total.currency == transaction.money.currency && total.rate == breakdown.rate
+        }) {
+            Some(total) => {
+                total.count += 1;
+                total.gross = total.gross.checked_add(&breakdown.gross)?;
+                total.vat = total.vat.checked_add(&breakdown.vat)?;
+                total.net = total.net.checked_add(&breakdown.net)?;
+                total.reclaimable_vat = total.reclaimable_vat.checked_add(&reclaimable_vat)?;
+            }
+            None => totals.push(VatRateTotal {
+                currency: transaction.money.currency.clone(),
+                rate: breakdown.rate,
+                count: 1,
+                gross: breakdown.gross.clone(),
+                vat: breakdown.vat.clone(),
+                net: breakdown.net.clone(),
+                reclaimable_vat,
+            }),
+        }
+
+        lines.push(VatReportLine {
+            transaction_id: transaction.id.clone(),
+            date,
+            description: transaction.description.clone(),
+            supplier_vat_number: transaction.supplier_vat_number.clone(),
+            breakdown,
+        });
+    }
+    totals.sort_by(|a, b| (&a.currency, a.rate).cmp(&(&b.currency, b.rate)));
+
+    let Some(to) = to.pred_opt() else {
+        return Ok(None);
+    };
+    Ok(Some(VatReport {
+        year,
+        quarter,
+        from,
+        // The last day of the quarter reads better than the first day of the next one.
+        to,
+        totals,
+        lines,
+    }))
+}
+
+/// Normalises a VAT registration number, e.g. "gb 123 4567 89" to "GB123456789".
+pub fn normalise_vat_number(vat_number: &str) -> Option<String> {
+    let normalised: String = vat_number
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let valid = (4..=15).contains(&normalised.len())
+        && normalised.chars().all(|c| c.is_ascii_alphanumeric())
+        && normalised.chars().any(|c| c.is_ascii_digit());
+    valid.then_some(normalised)
+}
@@ -0,0 +1,54 @@
+use chrono::Duration;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const TRANSACTION_CREATED: &str = "transaction.created";
+pub const TRANSACTION_UPDATED: &str = "transaction.updated";
+pub const CLAIM_STATUS_CHANGED: &str = "claim.status_changed";
+pub const BUDGET_EXCEEDED: &str = "budget.exceeded";
+
+/// The events an endpoint can ask for.
+pub const WEBHOOK_EVENTS: [&str; 4] = [
+    TRANSACTION_CREATED,
+    TRANSACTION_UPDATED,
+    CLAIM_STATUS_CHANGED,
+    BUDGET_EXCEEDED,
+];
+
+/// Deliveries that still fail after this many attempts are given up on.
+pub const MAX_ATTEMPTS: i32 = 8;
+const FIRST_RETRY_SECONDS: i64 = 60;
+/// How many due deliveries are taken at a time.
+pub const DELIVERY_BATCH_SIZE: i64 = 50;
+/// How long an endpoint has to answer.
+pub const DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+/// How many deliveries of a batch are sent at once, so one slow endpoint does not hold up the rest.
+pub const DELIVERY_CONCURRENCY: usize = 10;
+/// How long taken deliveries are held back from other instances. It is longer than a batch
+/// could take to send, so a delivery is only taken again when its instance died part way through.
+pub const DELIVERY_LEASE_SECONDS: i64 = 15 * 60;
+
+pub const EVENT_HEADER: &str = "X-Expenses-Event";
+pub const DELIVERY_HEADER: &str = "X-Expenses-Delivery";
+pub const SIGNATURE_HEADER: &str = "X-Expenses-Signature";
+
+/// How long to wait before trying a delivery again after `attempts` failed ones, doubling from a
+/// minute, so all of them are spread over about two hours.
+pub fn retry_delay(attempts: i32) -> Duration {
+    Duration::seconds(FIRST_RETRY_SECONDS << (attempts - 1).clamp(0, 16))
+}
+
+/// Signs a delivery as "t=<timestamp>,v1=<signature>", where the signature is the hex HMAC-SHA256
+/// of "<timestamp>.<body>" with the endpoint's secret. Receivers check it by doing the same, and
+/// can use the timestamp to refuse old deliveries being replayed.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}